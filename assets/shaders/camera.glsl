@@ -0,0 +1,5 @@
+// The camera, shared by every shader through one uniform buffer. It is filled from Camera::block().
+layout (std140) uniform Camera {
+    mat4 perspective;
+    mat4 camera;
+};
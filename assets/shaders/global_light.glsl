@@ -0,0 +1,6 @@
+// The global light, shared by every shader through one uniform buffer. It is filled from GlobalLighting::block().
+layout (std140) uniform GlobalLight {
+    vec3 global_light_color;
+    float global_ambient;
+    vec3 global_light_direction; // unit vector towards the light, in world space
+};
@@ -0,0 +1,32 @@
+// Extra lights, kind is 0 for directional, 1 for point and 2 for spot lights. MAX_LIGHTS is defined by
+// the ShaderBuilder from `lights::MAX_LIGHTS`.
+struct Light {
+    int kind;
+    vec3 color;
+    vec3 position;
+    vec3 direction;
+    float range;
+    float inner_cone;
+    float outer_cone;
+};
+uniform Light lights[MAX_LIGHTS];
+uniform int light_count = 0;
+
+// The light that one light adds at `position`, banded into `levels` steps.
+vec3 light_contribution(Light light, vec3 normal, vec3 position, float levels) {
+    vec3 to_light = -light.direction;
+    float attenuation = 1.0;
+    if(light.kind != 0) {
+        vec3 offset = light.position - position;
+        float light_distance = length(offset);
+        to_light = offset / max(light_distance, 0.0001);
+        float falloff = clamp(1.0 - pow(light_distance / light.range, 2.0), 0.0, 1.0);
+        attenuation = falloff * falloff;
+    }
+    if(light.kind == 2) {
+        attenuation *= smoothstep(light.outer_cone, light.inner_cone, dot(-to_light, light.direction));
+    }
+
+    float difference = max(dot(normal, to_light), 0.0) * attenuation;
+    return floor(difference * levels) / levels * light.color;
+}
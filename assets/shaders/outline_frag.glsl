@@ -0,0 +1,53 @@
+#version 410
+out vec4 frag_color;
+
+in vec2 screen_uv;
+
+uniform sampler2D screen_texture;
+uniform sampler2D depth_texture;
+uniform sampler2D normal_texture;
+
+uniform vec4 outline_color;
+uniform int thickness = 1;
+// 0 draws around the model, 1 draws on the edge of the model.
+uniform int outline_mode = 0;
+uniform int use_creases = 0;
+// Cosine of the crease angle.
+uniform float crease_threshold = 0.5;
+
+const int MAX_THICKNESS = 8;
+
+bool is_model(float depth) {
+    return depth < 1.0;
+}
+
+void main() {
+    ivec2 size = textureSize(screen_texture, 0);
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    vec4 color = texelFetch(screen_texture, p, 0);
+    float depth = texelFetch(depth_texture, p, 0).r;
+    vec3 normal = texelFetch(normal_texture, p, 0).xyz * 2.0 - 1.0;
+
+    bool edge = false;
+    for(int y = -MAX_THICKNESS; y <= MAX_THICKNESS; y++) {
+        for(int x = -MAX_THICKNESS; x <= MAX_THICKNESS; x++) {
+            // Diamond shaped kernel, this keeps the corners of a 1px outline clean.
+            if(abs(x) + abs(y) > thickness || (x == 0 && y == 0)) continue;
+
+            ivec2 q = p + ivec2(x, y);
+            bool outside = any(lessThan(q, ivec2(0))) || any(greaterThanEqual(q, size));
+            float neighbour_depth = outside ? 1.0 : texelFetch(depth_texture, q, 0).r;
+
+            if(outline_mode == 0 && !is_model(depth) && is_model(neighbour_depth)) edge = true;
+            if(outline_mode == 1 && is_model(depth) && !is_model(neighbour_depth)) edge = true;
+
+            // Creases only get drawn on the pixel that is closer to the camera, so they stay 1px wide.
+            if(use_creases == 1 && abs(x) + abs(y) == 1 && is_model(depth) && is_model(neighbour_depth) && depth <= neighbour_depth) {
+                vec3 neighbour_normal = texelFetch(normal_texture, q, 0).xyz * 2.0 - 1.0;
+                if(dot(normalize(normal), normalize(neighbour_normal)) < crease_threshold) edge = true;
+            }
+        }
+    }
+
+    frag_color = edge ? outline_color : color;
+}
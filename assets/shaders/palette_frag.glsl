@@ -0,0 +1,78 @@
+#version 410
+out vec4 frag_color;
+
+in vec2 screen_uv;
+
+uniform sampler2D screen_texture;
+uniform sampler2D palette_texture;
+uniform int palette_size;
+
+// Ordered dithering, dither_size is the size of the Bayer matrix (0 turns it off).
+uniform int dither_size = 0;
+uniform float dither_spread = 0.0;
+
+// https://bottosson.github.io/posts/oklab/
+vec3 linear_to_oklab(vec3 c) {
+    float l = 0.4122214708 * c.r + 0.5363325363 * c.g + 0.0514459929 * c.b;
+    float m = 0.2119034982 * c.r + 0.6806995451 * c.g + 0.1073969566 * c.b;
+    float s = 0.0883024619 * c.r + 0.2817188376 * c.g + 0.6299787005 * c.b;
+
+    vec3 lms = pow(max(vec3(l, m, s), vec3(0.0)), vec3(1.0 / 3.0));
+
+    return vec3(
+        0.2104542553 * lms.x + 0.7936177850 * lms.y - 0.0040720468 * lms.z,
+        1.9779984951 * lms.x - 2.4285922050 * lms.y + 0.4505937099 * lms.z,
+        0.0259040371 * lms.x + 0.7827717662 * lms.y - 0.8086757660 * lms.z
+    );
+}
+
+vec3 srgb_to_linear(vec3 c) {
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(vec3(0.04045), c));
+}
+
+vec3 linear_to_srgb(vec3 c) {
+    return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(vec3(0.0031308), c));
+}
+
+// Value of the Bayer matrix at p, see bayer_value in dither.rs.
+int bayer_value(ivec2 p, int size) {
+    int levels = int(log2(float(size)) + 0.5);
+    int value = 0;
+    for(int bit = 0; bit < levels; bit++) {
+        int bx = (p.x >> bit) & 1;
+        int by = (p.y >> bit) & 1;
+        value += (2 * (bx ^ by) + by) << (2 * (levels - 1 - bit));
+    }
+    return value;
+}
+
+void main() {
+    vec4 color = texture(screen_texture, screen_uv);
+    if(color.a == 0.0) {
+        frag_color = color;
+        return;
+    }
+
+    vec3 dithered = color.rgb;
+    if(dither_size > 0) {
+        ivec2 p = ivec2(gl_FragCoord.xy) % dither_size;
+        float threshold = (float(bayer_value(p, dither_size)) + 0.5) / float(dither_size * dither_size) - 0.5;
+        dithered = srgb_to_linear(clamp(linear_to_srgb(color.rgb) + threshold * dither_spread, 0.0, 1.0));
+    }
+
+    vec3 lab = linear_to_oklab(dithered);
+    vec3 best_color = color.rgb;
+    float best_distance = 1e20;
+
+    for(int i = 0; i < palette_size; i++) {
+        vec3 palette_color = texelFetch(palette_texture, ivec2(i, 0), 0).rgb;
+        vec3 difference = linear_to_oklab(palette_color) - lab;
+        float distance = dot(difference, difference);
+        if(distance < best_distance) {
+            best_distance = distance;
+            best_color = palette_color;
+        }
+    }
+
+    frag_color = vec4(best_color, color.a);
+}
@@ -0,0 +1,4 @@
+#version 410
+
+// Only the depth is needed for the shadow map.
+void main() {}
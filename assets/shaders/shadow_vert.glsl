@@ -0,0 +1,11 @@
+#version 410
+
+layout (location = 0) in vec3 pos;
+
+uniform mat4 light_space;
+uniform mat4 transform;
+uniform mat4 node = mat4(1.0);
+
+void main() {
+    gl_Position = light_space * transform * node * vec4(pos, 1.0);
+}
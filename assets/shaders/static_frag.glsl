@@ -1,33 +1,73 @@
 #version 410
-out vec4 frag_color;
+layout (location = 0) out vec4 frag_color;
+layout (location = 1) out vec4 frag_normal;
 
 in vec4 out_norm;
 in vec2 uv_pos;
+in vec3 world_pos;
 
 uniform sampler2D our_texture;
 
-// Global Light
-uniform vec3 global_light_color;
-uniform vec3 global_light_direction;
-uniform float global_ambient;
+#include "global_light.glsl"
+
+// Shadow map of the global light, rendered from light_space.
+uniform sampler2D shadow_map;
+uniform mat4 light_space;
+uniform int shadows_enabled = 0;
+uniform int shadow_hard_edges = 1;
+uniform float shadow_bias = 0.005;
+
+#include "lights.glsl"
 
 uniform float level_amout = 4.0;
 
 uniform int use_texture = 1;
+uniform vec3 base_color = vec3(1.0, 1.0, 1.0);
+
+// How much of the global light is blocked at this fragment, from 0 to 1. Hard edges take a single
+// sample of the shadow map, soft edges average a 3x3 block of them.
+float shadow_amount() {
+    if(shadows_enabled == 0) {
+        return 0.0;
+    }
+    vec4 light_pos = light_space * vec4(world_pos, 1.0);
+    vec3 coords = light_pos.xyz / light_pos.w * 0.5 + 0.5;
+    if(coords.z > 1.0) {
+        return 0.0;
+    }
+    float depth = coords.z - shadow_bias;
+    if(shadow_hard_edges == 1) {
+        return depth > texture(shadow_map, coords.xy).r ? 1.0 : 0.0;
+    }
+    vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+    float shadow = 0.0;
+    for(int x = -1; x <= 1; x++) {
+        for(int y = -1; y <= 1; y++) {
+            shadow += depth > texture(shadow_map, coords.xy + vec2(x, y) * texel).r ? 1.0 : 0.0;
+        }
+    }
+    return shadow / 9.0;
+}
 
 void main() {
-    float global_difference = max(dot(normalize(out_norm), normalize(vec4(global_light_direction, 1.0))), 0.0);
+    vec3 normal = normalize(out_norm.xyz);
+    float global_difference = max(dot(normal, normalize(global_light_direction)), 0.0);
+    global_difference *= 1.0 - shadow_amount();
     vec3 ambient = global_light_color * global_ambient;
     float diffuse_level = floor(global_difference * level_amout);
     global_difference = diffuse_level / level_amout;
     vec3 diffuse = (global_difference) * global_light_color;
+    for(int i = 0; i < min(light_count, MAX_LIGHTS); i++) {
+        diffuse += light_contribution(lights[i], normal, world_pos, level_amout);
+    }
 
     vec4 albeto;
     if(use_texture == 1) {
-        albeto = texture(our_texture, uv_pos);
+        albeto = texture(our_texture, uv_pos) * vec4(base_color, 1.0);
     } else {
-        albeto = vec4(1.0, 1.0, 1.0, 1.0);
+        albeto = vec4(base_color, 1.0);
     }
 
     frag_color = albeto * vec4((diffuse + ambient), 1.0);
+    frag_normal = vec4(normalize(out_norm.xyz) * 0.5 + 0.5, 1.0);
 }
\ No newline at end of file
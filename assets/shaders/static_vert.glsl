@@ -6,13 +6,18 @@ layout (location = 2) in vec3 norm;
 
 out vec2 uv_pos;
 out vec4 out_norm;
+out vec3 world_pos;
 
 uniform mat4 transform;
-uniform mat4 perspective;
-uniform mat4 camera;
+uniform mat3 transform_normal = mat3(1.0);
+#include "camera.glsl"
+uniform mat4 node = mat4(1.0);
+uniform mat3 node_normal = mat3(1.0);
 
 void main() {
-    gl_Position = perspective * camera * transform * vec4(pos, 1.0);
+    vec4 world = transform * node * vec4(pos, 1.0);
+    gl_Position = perspective * camera * world;
+    world_pos = world.xyz;
     uv_pos = uv;
-    out_norm = transform * vec4(norm, 1.0);
+    out_norm = vec4(transform_normal * node_normal * norm, 0.0);
 }
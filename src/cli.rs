@@ -0,0 +1,688 @@
+//The command line entry point. This lets the downsize pipeline be used without ever opening the debug
+//window, so it can be used as part of an asset pipeline.
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector2, Vector3};
+    use crate::cli::*;
+
+    fn parse(args : &str) -> Result<ConvertOptions, String> {
+        ConvertOptions::parse(&args.split_whitespace().map(str::to_owned).collect::<Vec<_>>())
+    }
+
+    fn error(args : &str) -> String {
+        match parse(args) {
+            Ok(_) => panic!("'{}' should not parse", args),
+            Err(error) => error
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse("models/cube.obj").unwrap();
+        assert_eq!(options.out, "cube.png");
+        assert_eq!((options.width, options.height), (64, 64));
+        assert_eq!(options.angles, 1);
+        assert_eq!(options.clip_fps, 24);
+        assert_eq!(options.dither.mode, DitherMode::NONE);
+        assert_eq!(options.outline_mode, OutlineMode::OUTER);
+        assert!(!options.record && !options.frame && !options.shadows);
+    }
+
+    #[test]
+    fn reads_values_and_flags() {
+        let options = parse("cube.obj --height 32 --width 48 --out sheet.png --angles 8 --columns 4 --scale 1,2,3 \
+            --light-dir 45,90 --dither atkinson --dither-strength 0.5 --outline 1,0,0 --outline-inner \
+            --clip walk --clip-frames --record --loops 2 --layers").unwrap();
+        assert_eq!((options.width, options.height), (48, 32));
+        assert_eq!(options.out, "sheet.png");
+        assert_eq!((options.angles, options.columns), (8, Some(4)));
+        assert_eq!(options.transform.scale(), Vector3::new(1.0, 2.0, 3.0));
+        assert_eq!(options.light_direction, Vector2::new(45.0, 90.0));
+        assert_eq!((options.dither.mode, options.dither.strength), (DitherMode::ATKINSON, 0.5));
+        assert_eq!(options.outline, Some(Vector3::new(1.0, 0.0, 0.0)));
+        assert_eq!(options.outline_mode, OutlineMode::INNER);
+        assert_eq!(options.clip.as_deref(), Some("walk"));
+        assert!(options.clip_frames && options.record && options.layers);
+        assert_eq!(options.loops, 2);
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert!(error("cube.obj --height 0").contains("height"));
+        assert!(error("cube.obj --clip-fps 0").contains("fps"));
+        assert!(error("cube.obj --height").contains("Missing value for '--height'"));
+        assert!(error("cube.obj --height tall").contains("'tall'"));
+        assert!(error("cube.obj --scale 1,2").contains("three"));
+        assert!(error("cube.obj --light-dir 1,2,3").contains("two"));
+        assert!(error("cube.obj --projection fisheye").contains("'fisheye'"));
+        assert!(error("cube.obj --dither noise").contains("'noise'"));
+        assert!(error("cube.obj --bogus").contains("Unknown option '--bogus'"));
+        assert!(error("").starts_with("Usage"));
+    }
+
+    #[test]
+    fn clip_frames_need_a_clip() {
+        assert!(error("cube.obj --clip-frames").contains("--clip"));
+        assert!(parse("cube.obj --clip 0 --clip-frames").is_ok());
+    }
+}
+
+use std::path::Path;
+use cgmath::{Vector2, Vector3};
+use glow::Context;
+use glutin::dpi::PhysicalSize;
+use crate::render::create_headless_context;
+use crate::render::downsize::Downsize;
+use crate::render::lighting::{direction_from_pitch_yaw, GlobalLighting};
+use crate::render::lights::{LightList, MAX_LIGHTS};
+use crate::render::model::load_model;
+use crate::render::Deletable;
+use crate::render::mesh::{Bounds, DEFAULT_CREASE_ANGLE};
+use crate::render::palette::Palette;
+use crate::render::dither::{Dither, DitherMode};
+use crate::render::outline::{OutlineMode, OutlinePass};
+use crate::render::post::PostProcess;
+use crate::render::shader::ShaderBuilder;
+use crate::render::shadow::ShadowMap;
+use crate::render::transform::Transform;
+use crate::render::camera::{Camera, Projection};
+use crate::render::orbit::OrbitController;
+use crate::render::animation::Timeline;
+use crate::render::skin::find_clip;
+use crate::render::uniform_block::UniformBlock;
+use crate::export::sprite_sheet::SpriteSheet;
+use crate::export::animated::AnimatedImage;
+use crate::export::aseprite::{changed_pixels, AsepriteFile};
+
+const USAGE : &str = "Usage: project-downsize convert <model.obj|model.gltf|model.glb> [options]
+
+Options:
+    --height <pixels>           Height of the output image, this is the pixel density. (default: 64)
+    --width <pixels>            Width of the output image. (default: same as height)
+    --out <file.png>            Where to write the image. An .ase or .aseprite file gets every angle and
+                                clip frame as a frame, tagged by angle. (default: <model>.png)
+    --cam-pos <x,y,z>           Camera position. (default: 0,0,-3)
+    --cam-rot <x,y,z>           Camera rotation in degrees. (default: 35.264,45,0)
+    --projection <mode>         perspective, orthographic, isometric, dimetric (2:1) or trimetric. The
+                                last three set the camera rotation. (default: perspective)
+    --fov <degrees>             Vertical field of view of the perspective projection. (default: 80)
+    --ortho-size <units>        World units that fit in the height of an orthographic view. (default: 3)
+    --pixel-snap                Snap the camera to whole output pixels, so renders of a moving camera
+                                line up.
+    --frame                     Move the camera so the whole model fits in the image. --cam-pos is ignored.
+    --rot <x,y,z>               Model rotation in degrees. (default: 0,0,0)
+    --scale <x,y,z>             Model scale. (default: 1,1,1)
+    --light-color <r,g,b>       Global light color. (default: 1,1,1)
+    --light-dir <pitch,yaw>     Global light direction in degrees above the horizon and around
+                                the y axis from +z. (default: 0,0)
+    --ambient <amount>          Global ambient light. (default: 0.2)
+    --bands <amount>            Number of lighting bands. (default: 4)
+    --lights <file.json>        Point, spot and directional lights to add to the global light, as saved
+                                from the Lights panel of the debug window.
+    --shadows                   Cast shadows from the global light.
+    --shadow-resolution <px>    Width and height of the shadow map. (default: 1024)
+    --shadow-bias <amount>      Depth offset that keeps surfaces from shadowing themselves. (default: 0.005)
+    --shadow-soft               Average the shadow map around each pixel instead of a single hard sample.
+    --smooth-angle <degrees>    When the model has no normals, faces that meet at more than this angle
+                                are flat shaded. (default: 60)
+    --angles <amount>           Render the model at this many yaw angles into a sprite sheet, and write
+                                the frame rects to a JSON file next to the image. (default: 1)
+    --columns <amount>          Frames per row of the sprite sheet. (default: as square as possible)
+    --palette <file>            Map every pixel to the nearest color of a .hex, .gpl or .pal palette.
+    --dither <mode>             Dithering used with the palette: none, bayer2, bayer4, bayer8,
+                                floyd-steinberg or atkinson. (default: none)
+    --dither-strength <amount>  Strength of the dithering. (default: 1)
+    --outline <r,g,b>           Draw an outline with this color around the model.
+    --outline-thickness <px>    Thickness of the outline in output pixels. (default: 1)
+    --outline-inner             Draw the outline on the edge of the model instead of around it.
+    --creases <degrees>         Also outline creases where the normals differ by more than this angle.
+    --animation <file.json>     Keyframe timeline to pose the model and light with.
+    --animation-frame <frame>   Frame of the timeline to render. (default: 0)
+    --clip <name|index>         Animation clip of a glTF model to pose it with.
+    --clip-frame <frame>        Frame of the clip to render. (default: 0)
+    --clip-fps <fps>            Frames per second the clip is sampled at. (default: 24)
+    --clip-frames               Render every frame of the clip into the sprite sheet, with a row for
+                                every angle.
+    --record                    Capture the timeline, the clip, or a turntable when there is neither, into
+                                an animated image. An --out ending in .gif writes a GIF that uses the
+                                palette, anything else writes an APNG.
+    --record-frames <amount>    Frames to capture. (default: the length of the clip or timeline, or
+                                --angles for a turntable)
+    --delay <ms>                Time every frame is shown for. (default: one frame of the clip or timeline)
+    --loops <amount>            Times the animation plays, 0 loops forever. (default: 0)
+    --upscale <factor>          Scale the frames up by a whole number, keeping the pixels sharp. (default: 1)
+    --layers                    Put the outline, and the shadows with --shadows, on their own layers above
+                                the color in Aseprite files.";
+
+///All of the settings that can be given to the `convert` command.
+pub struct ConvertOptions {
+    pub model : String,
+    pub out : String,
+    pub height : u32,
+    pub width : u32,
+    pub camera : Camera,
+    pub frame : bool,
+    pub transform : Transform,
+    pub light_color : Vector3<f32>,
+    pub light_direction : Vector2<f32>,
+    pub ambient : f32,
+    pub bands : f32,
+    pub lights : LightList,
+    pub shadows : bool,
+    pub shadow_resolution : u32,
+    pub shadow_bias : f32,
+    pub shadow_soft : bool,
+    pub smooth_angle : f32,
+    pub angles : u32,
+    pub columns : Option<u32>,
+    pub palette : Option<Palette>,
+    pub dither : Dither,
+    pub outline : Option<Vector3<f32>>,
+    pub outline_thickness : u32,
+    pub outline_mode : OutlineMode,
+    pub crease_angle : Option<f32>,
+    pub animation : Option<Timeline>,
+    pub animation_frame : u32,
+    pub clip : Option<String>,
+    pub clip_frame : u32,
+    pub clip_fps : u32,
+    pub clip_frames : bool,
+    pub record : bool,
+    pub record_frames : Option<u32>,
+    pub delay : Option<u32>,
+    pub loops : u32,
+    pub upscale : u32,
+    pub layers : bool
+}
+
+impl ConvertOptions {
+    pub fn parse(args : &[String]) -> Result<Self, String> {
+        let model = args.first().ok_or_else(|| USAGE.to_owned())?.clone();
+        let mut out = None;
+        let mut height = 64;
+        let mut width = None;
+        let mut camera_pos = None;
+        let mut camera_rot = None;
+        let mut projection = Projection::PERSPECTIVE;
+        let mut fov = None;
+        let mut ortho_size = None;
+        let mut frame = false;
+        let mut camera_snap = false;
+        let mut transform = Transform::default();
+        let mut light_color = Vector3::new(1.0, 1.0, 1.0);
+        let mut light_direction = Vector2::new(0.0, 0.0);
+        let mut ambient = 0.2;
+        let mut bands = 4.0;
+        let mut lights = LightList::default();
+        let mut shadows = false;
+        let mut shadow_resolution = 1024;
+        let mut shadow_bias = 0.005;
+        let mut shadow_soft = false;
+        let mut smooth_angle = DEFAULT_CREASE_ANGLE;
+        let mut angles = 1;
+        let mut columns = None;
+        let mut palette = None;
+        let mut dither = Dither::default();
+        let mut outline = None;
+        let mut outline_thickness = 1;
+        let mut outline_mode = OutlineMode::OUTER;
+        let mut crease_angle = None;
+        let mut animation = None;
+        let mut animation_frame = 0;
+        let mut clip = None;
+        let mut clip_frame = 0;
+        let mut clip_fps = 24;
+        let mut clip_frames = false;
+        let mut record = false;
+        let mut record_frames = None;
+        let mut delay = None;
+        let mut loops = 0;
+        let mut upscale = 1;
+        let mut layers = false;
+
+        let mut args = args.iter().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value for '{}'.", arg));
+            match arg.as_str() {
+                "--height" => height = parse_value(value()?)?,
+                "--width" => width = Some(parse_value(value()?)?),
+                "--out" => out = Some(value()?.clone()),
+                "--cam-pos" => camera_pos = Some(parse_vec3(value()?)?),
+                "--cam-rot" => camera_rot = Some(parse_vec3(value()?)?),
+                "--projection" => {
+                    let name = value()?;
+                    projection = Projection::from_name(name).ok_or_else(|| format!("'{}' is not a projection.", name))?;
+                }
+                "--fov" => fov = Some(parse_value(value()?)?),
+                "--ortho-size" => ortho_size = Some(parse_value(value()?)?),
+                "--frame" => frame = true,
+                "--pixel-snap" => { camera_snap = true; }
+                "--rot" => { transform.set_rot(parse_vec3(value()?)?); }
+                "--scale" => { transform.set_scale(parse_vec3(value()?)?); }
+                "--light-color" => light_color = parse_vec3(value()?)?,
+                "--light-dir" => light_direction = parse_vec2(value()?)?,
+                "--ambient" => ambient = parse_value(value()?)?,
+                "--bands" => bands = parse_value(value()?)?,
+                "--lights" => lights = LightList::load(value()?)?,
+                "--shadows" => shadows = true,
+                "--shadow-resolution" => shadow_resolution = parse_value(value()?)?,
+                "--shadow-bias" => shadow_bias = parse_value(value()?)?,
+                "--shadow-soft" => shadow_soft = true,
+                "--smooth-angle" => smooth_angle = parse_value(value()?)?,
+                "--angles" => angles = parse_value(value()?)?,
+                "--columns" => columns = Some(parse_value(value()?)?),
+                "--palette" => palette = Some(load_palette(value()?)?),
+                "--dither" => {
+                    let mode = value()?;
+                    dither.mode = DitherMode::from_name(mode).ok_or_else(|| format!("'{}' is not a dither mode.", mode))?;
+                }
+                "--dither-strength" => dither.strength = parse_value(value()?)?,
+                "--outline" => outline = Some(parse_vec3(value()?)?),
+                "--outline-thickness" => outline_thickness = parse_value(value()?)?,
+                "--outline-inner" => outline_mode = OutlineMode::INNER,
+                "--creases" => crease_angle = Some(parse_value(value()?)?),
+                "--animation" => animation = Some(Timeline::load(value()?)?),
+                "--animation-frame" => animation_frame = parse_value(value()?)?,
+                "--clip" => clip = Some(value()?.clone()),
+                "--clip-frame" => clip_frame = parse_value(value()?)?,
+                "--clip-fps" => clip_fps = parse_value(value()?)?,
+                "--clip-frames" => clip_frames = true,
+                "--record" => record = true,
+                "--record-frames" => record_frames = Some(parse_value(value()?)?),
+                "--delay" => delay = Some(parse_value(value()?)?),
+                "--loops" => loops = parse_value(value()?)?,
+                "--upscale" => upscale = parse_value(value()?)?,
+                "--layers" => layers = true,
+                _ => return Err(format!("Unknown option '{}'.\n\n{}", arg, USAGE))
+            }
+        }
+
+        if height == 0 { return Err("The height must be at least 1 pixel.".to_owned()) }
+        if clip_fps == 0 { return Err("The clip fps must be at least 1.".to_owned()) }
+        if clip_frames && clip.is_none() { return Err("--clip-frames needs a --clip.".to_owned()) }
+
+        let mut camera = Camera::new(projection);
+        if let Some(pos) = camera_pos { camera.set_pos(pos); }
+        if let Some(rot) = camera_rot { camera.set_rot(rot); }
+        if let Some(fov) = fov { camera.set_fov(fov); }
+        if let Some(size) = ortho_size { camera.set_ortho_size(size); }
+        camera.set_snap_resolution(height).set_pixel_snap(camera_snap);
+
+        let out = out.unwrap_or_else(|| {
+            Path::new(&model).with_extension("png").file_name().unwrap().to_string_lossy().into_owned()
+        });
+
+        Ok(ConvertOptions {
+            model,
+            out,
+            height,
+            width : width.unwrap_or(height),
+            camera,
+            frame,
+            transform,
+            light_color,
+            light_direction,
+            ambient,
+            bands,
+            lights,
+            shadows,
+            shadow_resolution,
+            shadow_bias,
+            shadow_soft,
+            smooth_angle,
+            angles,
+            columns,
+            palette,
+            dither,
+            outline,
+            outline_thickness,
+            outline_mode,
+            crease_angle,
+            animation,
+            animation_frame,
+            clip,
+            clip_frame,
+            clip_fps,
+            clip_frames,
+            record,
+            record_frames,
+            delay,
+            loops,
+            upscale,
+            layers
+        })
+    }
+}
+
+///Runs the command line interface. `args` should not contain the executable name.
+pub fn run(args : &[String]) -> Result<(), String> {
+    match args.first().map(|s| s.as_str()) {
+        Some("convert") => convert(ConvertOptions::parse(&args[1..])?),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_owned())
+    }
+}
+
+///Loads the model, renders it through `Downsize` and writes the color attachment to disk.
+pub fn convert(mut options : ConvertOptions) -> Result<(), String> {
+    let render_context = create_headless_context(options.width, options.height)?;
+    let size = render_context.surface_size();
+    let gl = &render_context.gl;
+
+    let mut global_lighting = GlobalLighting::new(options.light_color, direction_from_pitch_yaw(options.light_direction), options.ambient);
+
+    let shader = ShaderBuilder::new()
+        .with_vert_shader("static_vert.glsl")
+        .with_frag_shader("static_frag.glsl")
+        .with_define("MAX_LIGHTS", MAX_LIGHTS)
+        .build(&render_context)
+        .map_err(|e| format!("Unable to create shader: {:?}", e))?;
+
+    shader.add_multi_uniform(&mut options.lights);
+
+    let mut shadow_map = ShadowMap::new(&render_context, options.shadow_resolution).map_err(|e| format!("Unable to create shadow map: {:?}", e))?;
+    shadow_map.set_bias(options.shadow_bias);
+    shadow_map.set_hard_edges(!options.shadow_soft);
+    shader.add_multi_uniform(&mut shadow_map);
+    if let Some(timeline) = &options.animation {
+        timeline.apply_frame(options.animation_frame, &mut options.transform, &mut global_lighting);
+    }
+    let camera_block = UniformBlock::new(&render_context, "Camera", &options.camera.block())?;
+    let lighting_block = UniformBlock::new(&render_context, "GlobalLight", &global_lighting.block())?;
+
+    let model_path = if Path::new(&options.model).exists() {
+        Path::new(&options.model).to_path_buf()
+    } else {
+        Path::new("assets").join("models").join(&options.model)
+    };
+    let mut model = load_model(&render_context, model_path, shader, options.smooth_angle).map_err(|e| format!("Could not load model '{}': {}", options.model, e))?;
+
+    let clip = match &options.clip {
+        Some(name) => Some(find_clip(model.clips(), name).ok_or_else(|| format!("The model does not have a clip '{}'.", name))?),
+        None => None
+    };
+    let clip_name = clip.map(|index| model.clips()[index].name.clone());
+    let clip_frames = match clip {
+        Some(index) if options.clip_frames => model.clips()[index].frame_count(options.clip_fps),
+        _ => 1
+    };
+    let clip_frame = |frame : u32| if options.clip_frames || options.record { frame } else { options.clip_frame };
+    let clip_time = |frame : u32| clip_frame(frame) as f32 / options.clip_fps as f32;
+
+    // Recordings step through the clip if there is one, otherwise through the timeline.
+    let record_fps = match (clip, &options.animation) {
+        (None, Some(timeline)) => timeline.fps.max(1),
+        _ => options.clip_fps
+    };
+    let record_frames = options.record_frames.unwrap_or_else(|| match (clip, &options.animation) {
+        (Some(index), _) => model.clips()[index].frame_count(options.clip_fps),
+        (None, Some(timeline)) => timeline.length,
+        (None, None) => if options.angles > 1 { options.angles } else { 24 }
+    }).max(1);
+
+    // The transform of a captured frame. Without a clip or timeline a recording is a turntable.
+    let start = options.transform.clone();
+    let turntable = clip.is_none() && options.animation.is_none();
+    let step = 360.0 / record_frames as f32;
+    let record_transform = |frame : u32, lighting : &mut GlobalLighting| {
+        let mut transform = start.clone();
+        if let Some(timeline) = &options.animation {
+            timeline.apply_frame(frame % timeline.length.max(1), &mut transform, lighting);
+        }
+        if turntable { transform.add_rot_wrap((0.0, step * frame as f32, 0.0), 0.0, 360.0); }
+        transform
+    };
+
+    if options.frame {
+        // Every pose that ends up in the output has to fit, not just the first one: every yaw angle and
+        // animation frame of a sprite sheet, or every captured frame of a recording.
+        let mut bounds = Bounds::empty();
+        if options.record {
+            for frame in 0..record_frames {
+                let transform = record_transform(frame, &mut global_lighting);
+                if clip.is_some() { model.pose(clip, clip_time(frame)) }
+                bounds = bounds.union(&model.bounds().transformed(transform.calc_mat()));
+            }
+        } else {
+            let mut transform = options.transform.clone();
+            for _ in 0..options.angles.max(1) {
+                for frame in 0..clip_frames {
+                    model.pose(clip, clip_time(frame));
+                    bounds = bounds.union(&model.bounds().transformed(transform.calc_mat()));
+                }
+                transform.add_rot_wrap((0.0, 360.0 / options.angles.max(1) as f32, 0.0), 0.0, 360.0);
+            }
+        }
+        let margin = if options.outline.is_some() { options.outline_thickness } else { 0 };
+        options.camera.set_aspect_ratio(options.width as f32 / options.height as f32);
+        OrbitController::from_camera(&options.camera).frame(&mut options.camera, &bounds, options.height, margin);
+    }
+
+    let mut downsize = Downsize::new(gl, options.height);
+
+    let mut outline_pass = OutlinePass::new(&render_context).map_err(|e| format!("Unable to create outline pass: {:?}", e))?;
+    if let Some(color) = options.outline {
+        outline_pass.set_enabled(true);
+        outline_pass.set_color(color);
+        outline_pass.set_thickness(options.outline_thickness);
+        outline_pass.set_mode(options.outline_mode);
+    }
+    if let Some(angle) = options.crease_angle {
+        outline_pass.set_enabled(true);
+        outline_pass.set_creases(true, angle);
+    }
+    let passes : [&dyn PostProcess; 1] = [&outline_pass];
+
+    // `shadow` is the direction towards the global light when it casts shadows. The shadow map has to be
+    // rendered in the middle of the Downsize render, after the model is posed.
+    let target = downsize.framebuffer();
+    let mut camera = std::mem::take(&mut options.camera);
+    let mut draw = |gl : &Context, aspect_ratio : f32, transform : &Transform, frame : u32, shadow : Option<Vector3<f32>>| unsafe {
+        if clip.is_some() { model.pose(clip, clip_time(frame)) }
+        shadow_map.set_enabled(shadow.is_some());
+        if let Some(direction) = shadow {
+            shadow_map.fit(direction, &model.bounds().transformed(transform.calc_mat()));
+            shadow_map.render(gl, Some(target), |gl, shader| {
+                shader.send_uniform("transform", transform.clone()).ok();
+                model.render_with(gl, shader)
+            });
+        }
+        camera.set_aspect_ratio(aspect_ratio);
+        camera_block.update(&camera.block());
+        model.shader().send_uniform("transform", transform.clone()).ok();
+        model.shader().send_uniform("transform_normal", transform.normal_matrix()).ok();
+        model.shader().send_uniform("level_amout", options.bands).ok();
+        model.render(gl);
+    };
+
+    let shadow = options.shadows.then(|| global_lighting.direction());
+    let mut output = Output { gl, downsize : &mut downsize, size, passes : &passes, options : &options };
+    if options.record {
+        output.write_recording(record_frames, record_fps, |gl, aspect_ratio, frame| {
+            let transform = record_transform(frame, &mut global_lighting);
+            if options.animation.is_some() { lighting_block.update(&global_lighting.block()) }
+            draw(gl, aspect_ratio, &transform, frame, options.shadows.then(|| global_lighting.direction()))
+        })?;
+    } else if is_aseprite(&options.out) {
+        output.write_aseprite(clip_name.as_deref(), clip_frames, shadow, draw)?;
+    } else if options.angles > 1 || clip_frames > 1 {
+        output.write_sprite_sheet(clip_frames, |gl, aspect_ratio, transform, frame| draw(gl, aspect_ratio, transform, frame, shadow))?;
+    } else {
+        output.write_image(|gl, aspect_ratio| draw(gl, aspect_ratio, &options.transform, 0, shadow))?;
+    }
+
+    downsize.delete(gl);
+    outline_pass.delete(gl);
+    unsafe {
+        shadow_map.delete(gl);
+        camera_block.delete(gl);
+        lighting_block.delete(gl);
+        model.delete(gl)
+    }
+    Ok(())
+}
+
+///What every output of `convert` renders through. The draw callbacks are handed the aspect ratio, the
+///transform of the model, the clip frame and the direction of the shadow casting light.
+struct Output<'a> {
+    gl : &'a Context,
+    downsize : &'a mut Downsize,
+    size : PhysicalSize<u32>,
+    passes : &'a [&'a dyn PostProcess],
+    options : &'a ConvertOptions,
+}
+
+impl Output<'_> {
+    ///Captures `frames` frames at `fps` into a GIF or APNG. `capture` draws the frame with the given index.
+    fn write_recording(&mut self, frames : u32, fps : u32, mut capture : impl FnMut(&Context, f32, u32)) -> Result<(), String> {
+        let options = self.options;
+        let mut animated = AnimatedImage::capture(self.gl, self.downsize, self.size, self.passes, frames, 1.0 / fps as f32, |gl, aspect_ratio, frame, _| {
+            capture(gl, aspect_ratio, frame)
+        }).with_loops(options.loops).with_scale(options.upscale);
+        if let Some(delay) = options.delay { animated = animated.with_delay(delay) }
+
+        if let Some(palette) = &options.palette { animated.quantize(&options.dither, palette) }
+        animated.save(&options.out, options.palette.as_ref())?;
+        println!("Wrote {} frame animation to '{}'.", animated.frames.len(), options.out);
+        Ok(())
+    }
+
+    ///Writes every clip frame of every angle as a frame of an Aseprite file, with a tag for each angle.
+    ///With `--layers` the outline, and the shadow when there is one, get layers of their own.
+    fn write_aseprite(&mut self, clip_name : Option<&str>, clip_frames : u32, shadow : Option<Vector3<f32>>,
+                      mut draw : impl FnMut(&Context, f32, &Transform, u32, Option<Vector3<f32>>)) -> Result<(), String> {
+        let (gl, size, options) = (self.gl, self.size, self.options);
+        let layers : &[&str] = match (options.layers, options.shadows) {
+            (true, true) => &["color", "shadow", "outline"],
+            (true, false) => &["color", "outline"],
+            (false, _) => &["color"]
+        };
+        let duration = if clip_name.is_some() { 1000 / options.clip_fps } else { 100 };
+        let step = 360.0 / options.angles.max(1) as f32;
+        let mut transform = options.transform.clone();
+        let mut file : Option<AsepriteFile> = None;
+
+        for angle in 0..options.angles.max(1) {
+            let mut first = None;
+            for frame in 0..clip_frames {
+                // The shadow layer is whatever changes when the same frame is rendered again with shadows.
+                let unshadowed = if options.layers && shadow.is_some() {
+                    self.downsize.render_offscreen(gl, size, |gl, aspect_ratio| draw(gl, aspect_ratio, &transform, frame, None));
+                    Some(self.downsize.read_image(gl))
+                } else {
+                    None
+                };
+                self.downsize.render_offscreen(gl, size, |gl, aspect_ratio| draw(gl, aspect_ratio, &transform, frame, shadow));
+                let before = self.downsize.read_image(gl);
+                self.downsize.apply_passes(gl, self.passes);
+                let after = self.downsize.read_image(gl);
+
+                let mut image = after.clone();
+                if let Some(palette) = &options.palette { options.dither.quantize(&mut image, palette) }
+                let cels = if options.layers {
+                    let mut shaded = before.clone();
+                    if let Some(palette) = &options.palette { options.dither.quantize(&mut shaded, palette) }
+                    // The outline is whatever the passes changed, found before quantizing so dithering does not leak into it.
+                    let outline = changed_pixels(&before, &after, &image);
+                    match unshadowed {
+                        Some(unshadowed) => {
+                            let mut color = unshadowed.clone();
+                            if let Some(palette) = &options.palette { options.dither.quantize(&mut color, palette) }
+                            let shadow = changed_pixels(&unshadowed, &before, &shaded);
+                            vec![color, shadow, outline]
+                        }
+                        None => vec![shaded, outline]
+                    }
+                } else {
+                    vec![image]
+                };
+                let (width, height) = cels[0].dimensions();
+                let index = file.get_or_insert_with(|| AsepriteFile::new(width, height, layers)).add_frame(duration, cels);
+                first.get_or_insert(index);
+            }
+
+            if let (Some(file), Some(first)) = (&mut file, first) {
+                let angle = step * angle as f32;
+                let last = first + clip_frames as u16 - 1;
+                match (clip_name, options.angles > 1) {
+                    (Some(name), true) => file.add_tag(&format!("{} {}", name, angle), first, last),
+                    (Some(name), false) => file.add_tag(name, first, last),
+                    (None, true) => file.add_tag(&angle.to_string(), first, last),
+                    (None, false) => {}
+                }
+            }
+            transform.add_rot_wrap((0.0, step, 0.0), 0.0, 360.0);
+        }
+
+        let mut file = file.ok_or_else(|| "Nothing was rendered.".to_owned())?;
+        if let Some(palette) = &options.palette { file.set_palette(palette) }
+        file.save(&options.out)?;
+        println!("Wrote {} frame Aseprite file to '{}'.", options.angles.max(1) * clip_frames, options.out);
+        Ok(())
+    }
+
+    ///Packs every clip frame of every angle into a sprite sheet with a JSON file of the frame rects.
+    fn write_sprite_sheet(&mut self, clip_frames : u32, draw : impl FnMut(&Context, f32, &Transform, u32)) -> Result<(), String> {
+        let options = self.options;
+        let mut sheet = SpriteSheet::new(options.angles).with_frames(clip_frames);
+        if let Some(columns) = options.columns { sheet = sheet.with_columns(columns) }
+
+        let mut transform = options.transform.clone();
+        let mut sheet = sheet.render(self.gl, self.downsize, self.size, self.passes, &mut transform, draw);
+        if let Some(palette) = &options.palette { options.dither.quantize(&mut sheet.image, palette) }
+        sheet.save(&options.out)?;
+        println!("Wrote {} frame sprite sheet to '{}'.", sheet.frames.len(), options.out);
+        Ok(())
+    }
+
+    ///Writes a single frame as an image.
+    fn write_image(&mut self, draw : impl FnOnce(&Context, f32)) -> Result<(), String> {
+        let options = self.options;
+        let (width, height) = self.downsize.render_offscreen(self.gl, self.size, draw);
+        self.downsize.apply_passes(self.gl, self.passes);
+        let mut image = self.downsize.read_image(self.gl);
+        if let Some(palette) = &options.palette { options.dither.quantize(&mut image, palette) }
+        image.save(&options.out)
+            .map_err(|e| format!("Unable to write '{}': {}", options.out, e))?;
+        println!("Wrote {}x{} image to '{}'.", width, height, options.out);
+        Ok(())
+    }
+}
+
+fn is_aseprite(path : &str) -> bool {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    extension == "ase" || extension == "aseprite"
+}
+
+///Loads a palette from a path, or from `assets/palettes` if the path does not exist.
+fn load_palette(value : &str) -> Result<Palette, String> {
+    if Path::new(value).exists() {
+        Palette::load(value)
+    } else {
+        Palette::from_file(value)
+    }
+}
+
+fn parse_value<T>(value : &str) -> Result<T, String> where T : std::str::FromStr {
+    value.trim().parse().map_err(|_| format!("'{}' is not a valid value.", value))
+}
+
+fn parse_vec3(value : &str) -> Result<Vector3<f32>, String> {
+    let parts = value.split(',').map(parse_value).collect::<Result<Vec<f32>, String>>()?;
+    match parts.as_slice() {
+        [x, y, z] => Ok(Vector3::new(*x, *y, *z)),
+        _ => Err(format!("'{}' should be three comma separated numbers.", value))
+    }
+}
+
+fn parse_vec2(value : &str) -> Result<Vector2<f32>, String> {
+    let parts = value.split(',').map(parse_value).collect::<Result<Vec<f32>, String>>()?;
+    match parts.as_slice() {
+        [x, y] => Ok(Vector2::new(*x, *y)),
+        _ => Err(format!("'{}' should be two comma separated numbers.", value))
+    }
+}
@@ -0,0 +1,3 @@
+pub mod sprite_sheet;
+pub mod animated;
+pub mod aseprite;
@@ -0,0 +1,232 @@
+//Captures consecutive downsized frames at a fixed timestep and writes them out as an animated GIF or
+//APNG, so an animation can be shared without opening the tool.
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use crate::export::animated::*;
+    use crate::render::palette::Palette;
+
+    fn frames() -> Vec<RgbaImage> {
+        vec![
+            RgbaImage::from_fn(2, 2, |x, _| if x == 0 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 0, 0]) }),
+            RgbaImage::from_fn(2, 2, |x, _| if x == 1 { Rgba([0, 0, 255, 255]) } else { Rgba([0, 0, 0, 0]) }),
+        ]
+    }
+
+    #[test]
+    fn gif_uses_the_palette() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 0, 0], [0, 0, 255]]);
+        let animated = AnimatedImage::new(frames()).with_delay(50).with_scale(2);
+        let mut data = Vec::new();
+        animated.write_gif(&mut data, Some(&palette)).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(data.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (4, 4));
+        assert_eq!(&decoder.global_palette().unwrap()[..12], &[0, 0, 0, 255, 0, 0, 0, 0, 255, 0, 0, 0]);
+
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(frame.delay, 5);
+        assert_eq!(frame.transparent, Some(3));
+        assert_eq!(&frame.buffer[..4], &[1, 1, 3, 3]);
+        assert!(decoder.read_next_frame().unwrap().is_some());
+        assert!(decoder.read_next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn transparent_index_is_in_the_color_table() {
+        let colors = (0..16).map(|index| [index * 16, 0, 0]).collect();
+        let animated = AnimatedImage::new(frames());
+        let mut data = Vec::new();
+        animated.write_gif(&mut data, Some(&Palette::new(colors))).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(data.as_slice()).unwrap();
+        let table = decoder.global_palette().unwrap().len() / 3;
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        assert_eq!(frame.transparent, Some(16));
+        assert!(table > 16, "{} colors", table);
+    }
+
+    #[test]
+    fn apng_has_every_frame() {
+        let animated = AnimatedImage::new(frames()).with_delay(40).with_loops(3).with_scale(3);
+        let mut data = Vec::new();
+        animated.write_apng(&mut data).unwrap();
+
+        let decoder = png::Decoder::new(data.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!((info.width, info.height), (6, 6));
+        let control = info.animation_control().unwrap();
+        assert_eq!((control.num_frames, control.num_plays), (2, 3));
+    }
+
+    #[test]
+    fn upscale_is_nearest_neighbour() {
+        let animated = AnimatedImage::new(frames()).with_scale(2);
+        let frame = &animated.scaled_frames()[0];
+        assert_eq!(frame.dimensions(), (4, 4));
+        assert_eq!(frame.get_pixel(1, 3), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frame.get_pixel(2, 0), &Rgba([0, 0, 0, 0]));
+    }
+}
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use glow::Context;
+use glutin::dpi::PhysicalSize;
+use image::imageops::FilterType;
+use image::RgbaImage;
+use crate::render::dither::Dither;
+use crate::render::downsize::Downsize;
+use crate::render::palette::Palette;
+use crate::render::post::PostProcess;
+
+///A run of frames that are shown one after the other, along with how they should be played back.
+pub struct AnimatedImage {
+    pub frames : Vec<RgbaImage>,
+    delay : u32,
+    loops : u32,
+    scale : u32,
+}
+
+impl AnimatedImage {
+    ///Plays the frames every 100 milliseconds, forever, at their own size.
+    pub fn new(frames : Vec<RgbaImage>) -> Self {
+        AnimatedImage {
+            frames,
+            delay : 100,
+            loops : 0,
+            scale : 1,
+        }
+    }
+
+    ///Renders `frames` frames through `downsize`, `timestep` seconds apart. The render callback is handed
+    ///the aspect ratio, the index of the frame and its time in seconds, and `passes` are applied to every
+    ///frame before it is read back. The delay between frames is set to the timestep.
+    pub fn capture(gl : &Context, downsize : &mut Downsize, size : PhysicalSize<u32>, passes : &[&dyn PostProcess], frames : u32, timestep : f32,
+                   mut render_frame : impl FnMut(&Context, f32, u32, f32)) -> Self {
+        let images = (0..frames.max(1)).map(|frame| {
+            downsize.render_offscreen(gl, size, |gl, aspect_ratio| render_frame(gl, aspect_ratio, frame, frame as f32 * timestep));
+            downsize.apply_passes(gl, passes);
+            downsize.read_image(gl)
+        }).collect();
+
+        AnimatedImage::new(images).with_delay((timestep * 1000.0).round() as u32)
+    }
+
+    ///Sets the time every frame is shown for in milliseconds.
+    pub fn with_delay(mut self, delay : u32) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    ///Sets how many times the animation plays. 0 loops forever.
+    pub fn with_loops(mut self, loops : u32) -> Self {
+        self.loops = loops;
+        self
+    }
+
+    ///Scales every frame up by a whole number when it is written, so the pixels stay sharp.
+    pub fn with_scale(mut self, scale : u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    ///Maps every frame onto the palette with the dither.
+    pub fn quantize(&mut self, dither : &Dither, palette : &Palette) {
+        for frame in &mut self.frames {
+            dither.quantize(frame, palette);
+        }
+    }
+
+    ///The frames at the size they are written at.
+    pub fn scaled_frames(&self) -> Vec<RgbaImage> {
+        self.frames.iter().map(|frame| match self.scale {
+            1 => frame.clone(),
+            scale => image::imageops::resize(frame, frame.width() * scale, frame.height() * scale, FilterType::Nearest)
+        }).collect()
+    }
+
+    ///Writes a GIF if the path ends in `.gif` and an APNG otherwise. See `write_gif` for how the palette is used.
+    pub fn save<P>(&self, path : P, palette : Option<&Palette>) -> Result<(), String> where P : AsRef<Path> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| format!("Unable to write '{}': {}", path.display(), e))?;
+        let writer = BufWriter::new(file);
+        let result = match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()) {
+            Some(extension) if extension == "gif" => self.write_gif(writer, palette),
+            _ => self.write_apng(writer)
+        };
+        result.map_err(|e| format!("Unable to write '{}': {}", path.display(), e))
+    }
+
+    ///Encodes the frames as a GIF. With a palette, the palette becomes the global color table of the GIF,
+    ///so frames that were quantized to it come out exactly, and transparent pixels get an index after the
+    ///palette colors. A 256 color palette has no room for that, so transparent pixels take the nearest color.
+    ///Without a palette, every frame gets its own color table.
+    pub fn write_gif<W>(&self, writer : W, palette : Option<&Palette>) -> Result<(), String> where W : Write {
+        let frames = self.scaled_frames();
+        let (width, height) = frames.first().map(|frame| frame.dimensions()).unwrap_or((1, 1));
+        if width > u16::MAX as u32 || height > u16::MAX as u32 { return Err("The frames are too large for a GIF.".to_owned()) }
+
+        let palette = palette.filter(|palette| palette.len() <= 256);
+        let mut global_palette : Vec<u8> = palette.map(|palette| palette.colors().iter().flatten().copied().collect()).unwrap_or_default();
+        let transparent = palette.map(|palette| palette.len()).filter(|len| *len < 256).map(|len| len as u8);
+        // The color table is only padded up to a power of two, so the transparent index needs a slot of its own.
+        if transparent.is_some() { global_palette.extend([0, 0, 0]) }
+
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &global_palette).map_err(|e| e.to_string())?;
+        let repeat = match self.loops {
+            0 => gif::Repeat::Infinite,
+            loops => gif::Repeat::Finite(loops.min(u16::MAX as u32) as u16)
+        };
+        encoder.set_repeat(repeat).map_err(|e| e.to_string())?;
+
+        for image in frames {
+            let mut frame = match palette {
+                Some(palette) => gif::Frame {
+                    width : width as u16,
+                    height : height as u16,
+                    transparent,
+                    buffer : Cow::Owned(image.pixels().map(|pixel| match transparent {
+                        Some(index) if pixel[3] < 128 => index,
+                        _ => palette.nearest([pixel[0], pixel[1], pixel[2]]) as u8
+                    }).collect()),
+                    ..gif::Frame::default()
+                },
+                None => gif::Frame::from_rgba_speed(width as u16, height as u16, &mut image.into_raw(), 10)
+            };
+            // The delay of a GIF frame is in hundredths of a second.
+            frame.delay = ((self.delay + 5) / 10).min(u16::MAX as u32) as u16;
+            frame.dispose = gif::DisposalMethod::Background;
+            encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    ///Encodes the frames as an APNG in full RGBA. Every frame replaces the last one completely, so
+    ///transparent pixels stay transparent.
+    pub fn write_apng<W>(&self, writer : W) -> Result<(), String> where W : Write {
+        let frames = self.scaled_frames();
+        let (width, height) = frames.first().map(|frame| frame.dimensions()).unwrap_or((1, 1));
+
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(frames.len() as u32, self.loops).map_err(|e| e.to_string())?;
+        encoder.set_frame_delay(self.delay.min(u16::MAX as u32) as u16, 1000).map_err(|e| e.to_string())?;
+        encoder.set_blend_op(png::BlendOp::Source).map_err(|e| e.to_string())?;
+
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        for frame in &frames {
+            writer.write_image_data(frame.as_raw()).map_err(|e| e.to_string())?;
+        }
+        writer.finish().map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,342 @@
+//Writes rendered frames to an Aseprite file, so artists can open the output of the tool directly instead
+//of importing PNG strips. Every frame can have a cel on a number of layers, runs of frames can be tagged,
+//and the palette used for quantization is stored in the file.
+//See https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md for the format.
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+    use crate::export::aseprite::*;
+    use crate::render::palette::Palette;
+
+    fn word(data : &[u8], at : usize) -> u16 {
+        u16::from_le_bytes([data[at], data[at + 1]])
+    }
+
+    fn dword(data : &[u8], at : usize) -> u32 {
+        u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+    }
+
+    ///The types of the chunks of every frame.
+    fn chunk_types(data : &[u8]) -> Vec<Vec<u16>> {
+        let mut frames = Vec::new();
+        let mut at = 128;
+        for _ in 0..word(data, 6) {
+            assert_eq!(word(data, at + 4), 0xF1FA);
+            let (size, chunks) = (dword(data, at) as usize, dword(data, at + 12));
+            let mut chunk = at + 16;
+            let mut types = Vec::new();
+            for _ in 0..chunks {
+                types.push(word(data, chunk + 4));
+                chunk += dword(data, chunk) as usize;
+            }
+            assert_eq!(chunk, at + size);
+            frames.push(types);
+            at += size;
+        }
+        frames
+    }
+
+    fn sprite() -> AsepriteFile {
+        let red = RgbaImage::from_fn(4, 4, |x, y| if x == 1 && y == 2 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 0, 0]) });
+        let mut file = AsepriteFile::new(4, 4, &["color", "outline"]);
+        file.add_frame(100, vec![red.clone(), RgbaImage::new(4, 4)]);
+        file.add_frame(100, vec![red.clone(), red]);
+        file.add_tag("walk 0", 0, 1);
+        file
+    }
+
+    #[test]
+    fn header_and_frames() {
+        let mut data = Vec::new();
+        sprite().write(&mut data).unwrap();
+        assert_eq!(dword(&data, 0) as usize, data.len());
+        assert_eq!(word(&data, 4), 0xA5E0);
+        assert_eq!((word(&data, 6), word(&data, 8), word(&data, 10), word(&data, 12)), (2, 4, 4, 32));
+
+        let chunks = chunk_types(&data);
+        // The empty outline of the first frame does not get a cel.
+        assert_eq!(chunks[0], vec![0x2007, 0x2004, 0x2004, 0x2018, 0x2005]);
+        assert_eq!(chunks[1], vec![0x2005, 0x2005]);
+    }
+
+    #[test]
+    fn cels_are_cropped() {
+        let mut data = Vec::new();
+        sprite().write(&mut data).unwrap();
+        let frame = 128;
+        let mut cel = frame + 16;
+        while word(&data, cel + 4) != 0x2005 { cel += dword(&data, cel) as usize }
+        let cel = cel + 6;
+        assert_eq!((word(&data, cel + 2), word(&data, cel + 4)), (1, 2));
+        assert_eq!((word(&data, cel + 16), word(&data, cel + 18)), (1, 1));
+        assert_eq!(&data[cel + 20..cel + 24], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn changed_pixels_only_keep_the_pass() {
+        let base = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { Rgba([255, 0, 0, 255]) } else { Rgba([0, 0, 0, 0]) });
+        let outlined = RgbaImage::from_fn(2, 1, |_, _| Rgba([255, 0, 0, 255]));
+        let changed = changed_pixels(&base, &outlined, &outlined);
+        assert_eq!(changed.get_pixel(0, 0)[3], 0);
+        assert_eq!(changed.get_pixel(1, 0), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn palette_is_stored() {
+        let mut file = sprite();
+        file.set_palette(&Palette::new(vec![[0, 0, 0], [255, 0, 0]]));
+        let mut data = Vec::new();
+        file.write(&mut data).unwrap();
+        assert_eq!(word(&data, 32), 2);
+        assert_eq!(chunk_types(&data)[0], vec![0x2007, 0x2019, 0x2004, 0x2004, 0x2018, 0x2005]);
+    }
+
+    #[test]
+    fn empty_palette_is_skipped() {
+        let mut file = sprite();
+        file.set_palette(&Palette::new(Vec::new()));
+        let mut data = Vec::new();
+        file.write(&mut data).unwrap();
+        assert_eq!(word(&data, 32), 0);
+        assert_eq!(chunk_types(&data)[0], vec![0x2007, 0x2004, 0x2004, 0x2018, 0x2005]);
+    }
+}
+
+use std::fs;
+use std::path::Path;
+use image::RgbaImage;
+use crate::render::palette::Palette;
+
+const HEADER_MAGIC : u16 = 0xA5E0;
+const FRAME_MAGIC : u16 = 0xF1FA;
+const LAYER_CHUNK : u16 = 0x2004;
+const CEL_CHUNK : u16 = 0x2005;
+const COLOR_PROFILE_CHUNK : u16 = 0x2007;
+const TAGS_CHUNK : u16 = 0x2018;
+const PALETTE_CHUNK : u16 = 0x2019;
+
+///A named run of frames, like every frame of one angle or one animation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AsepriteTag {
+    pub name : String,
+    pub from : u16,
+    pub to : u16,
+}
+
+struct AsepriteFrame {
+    duration : u16,
+    cels : Vec<RgbaImage>,
+}
+
+///An RGBA Aseprite sprite that is built up frame by frame.
+pub struct AsepriteFile {
+    width : u16,
+    height : u16,
+    layers : Vec<String>,
+    frames : Vec<AsepriteFrame>,
+    tags : Vec<AsepriteTag>,
+    palette : Option<Palette>,
+}
+
+impl AsepriteFile {
+    ///Creates an empty sprite with the named layers, from the bottom layer to the top.
+    pub fn new(width : u32, height : u32, layers : &[&str]) -> Self {
+        AsepriteFile {
+            width : width.min(u16::MAX as u32) as u16,
+            height : height.min(u16::MAX as u32) as u16,
+            layers : layers.iter().map(|name| name.to_string()).collect(),
+            frames : Vec::new(),
+            tags : Vec::new(),
+            palette : None,
+        }
+    }
+
+    ///Adds a frame that is shown for `duration` milliseconds, with one image for every layer in the
+    ///order the layers were given. Returns the index of the frame.
+    pub fn add_frame(&mut self, duration : u32, cels : Vec<RgbaImage>) -> u16 {
+        self.frames.push(AsepriteFrame { duration : duration.min(u16::MAX as u32) as u16, cels });
+        self.frames.len() as u16 - 1
+    }
+
+    ///Tags the frames from `from` to `to`, both included.
+    pub fn add_tag(&mut self, name : &str, from : u16, to : u16) {
+        self.tags.push(AsepriteTag { name : name.to_owned(), from, to });
+    }
+
+    ///Stores the palette in the sprite, so it shows up in the palette editor. An empty palette is not
+    ///stored, since a palette chunk needs at least one color.
+    pub fn set_palette(&mut self, palette : &Palette) {
+        self.palette = (!palette.is_empty()).then(|| palette.clone());
+    }
+
+    pub fn save<P>(&self, path : P) -> Result<(), String> where P : AsRef<Path> {
+        let mut data = Vec::new();
+        self.write(&mut data)?;
+        fs::write(path.as_ref(), data).map_err(|e| format!("Unable to write '{}': {}", path.as_ref().display(), e))
+    }
+
+    ///Encodes the sprite. The layers, tags and palette are written into the first frame, and every cel
+    ///is cropped to its visible pixels. Cels without any visible pixels are left out.
+    pub fn write(&self, data : &mut Vec<u8>) -> Result<(), String> {
+        if self.frames.is_empty() { return Err("The sprite does not have any frames.".to_owned()) }
+        if self.frames.len() > u16::MAX as usize { return Err("The sprite has too many frames.".to_owned()) }
+        let start = data.len();
+
+        // Header
+        put_dword(data, 0);
+        put_word(data, HEADER_MAGIC);
+        put_word(data, self.frames.len() as u16);
+        put_word(data, self.width);
+        put_word(data, self.height);
+        put_word(data, 32);
+        put_dword(data, 1);
+        put_word(data, self.frames[0].duration);
+        put_dword(data, 0);
+        put_dword(data, 0);
+        data.push(0);
+        data.extend([0; 3]);
+        put_word(data, self.palette.as_ref().map(|palette| palette.len().min(256) as u16).unwrap_or(0));
+        data.extend([1, 1]);
+        put_word(data, 0);
+        put_word(data, 0);
+        put_word(data, 16);
+        put_word(data, 16);
+        data.extend([0; 84]);
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            let mut chunks = Vec::new();
+            if index == 0 {
+                chunks.push(chunk(COLOR_PROFILE_CHUNK, |data| {
+                    // sRGB, which is what the frames are read back as.
+                    put_word(data, 1);
+                    put_word(data, 0);
+                    put_dword(data, 0);
+                    data.extend([0; 8]);
+                }));
+                if let Some(palette) = &self.palette { chunks.push(palette_chunk(palette)) }
+                for layer in &self.layers {
+                    chunks.push(chunk(LAYER_CHUNK, |data| {
+                        put_word(data, 3);
+                        put_word(data, 0);
+                        put_word(data, 0);
+                        put_word(data, 0);
+                        put_word(data, 0);
+                        put_word(data, 0);
+                        data.push(255);
+                        data.extend([0; 3]);
+                        put_string(data, layer);
+                    }));
+                }
+                if !self.tags.is_empty() { chunks.push(self.tags_chunk()) }
+            }
+
+            for (layer, image) in frame.cels.iter().enumerate().take(self.layers.len()) {
+                if let Some(cel) = cel_chunk(layer as u16, image) { chunks.push(cel) }
+            }
+
+            let size : usize = 16 + chunks.iter().map(Vec::len).sum::<usize>();
+            put_dword(data, size as u32);
+            put_word(data, FRAME_MAGIC);
+            put_word(data, chunks.len().min(0xFFFF) as u16);
+            put_word(data, frame.duration);
+            data.extend([0; 2]);
+            put_dword(data, chunks.len() as u32);
+            for chunk in chunks {
+                data.extend(chunk);
+            }
+        }
+
+        let size = (data.len() - start) as u32;
+        data[start..start + 4].copy_from_slice(&size.to_le_bytes());
+        Ok(())
+    }
+
+    fn tags_chunk(&self) -> Vec<u8> {
+        chunk(TAGS_CHUNK, |data| {
+            put_word(data, self.tags.len() as u16);
+            data.extend([0; 8]);
+            for tag in &self.tags {
+                put_word(data, tag.from);
+                put_word(data, tag.to);
+                data.push(0);
+                put_word(data, 0);
+                data.extend([0; 6]);
+                data.extend([0, 0, 0]);
+                data.push(0);
+                put_string(data, &tag.name);
+            }
+        })
+    }
+}
+
+///The pixels of `output` where `after` differs from `before`, so a post processing pass like the outline
+///can go on its own layer. The rest of the image is transparent.
+pub fn changed_pixels(before : &RgbaImage, after : &RgbaImage, output : &RgbaImage) -> RgbaImage {
+    RgbaImage::from_fn(output.width(), output.height(), |x, y| {
+        if before.get_pixel(x, y) != after.get_pixel(x, y) { *output.get_pixel(x, y) } else { image::Rgba([0, 0, 0, 0]) }
+    })
+}
+
+fn palette_chunk(palette : &Palette) -> Vec<u8> {
+    let colors = &palette.colors()[..palette.len().min(256)];
+    chunk(PALETTE_CHUNK, |data| {
+        put_dword(data, colors.len() as u32);
+        put_dword(data, 0);
+        put_dword(data, colors.len() as u32 - 1);
+        data.extend([0; 8]);
+        for [r, g, b] in colors {
+            put_word(data, 0);
+            data.extend([*r, *g, *b, 255]);
+        }
+    })
+}
+
+///A raw cel with the visible part of `image`, or `None` when every pixel is transparent.
+fn cel_chunk(layer : u16, image : &RgbaImage) -> Option<Vec<u8>> {
+    let visible = image.enumerate_pixels().filter(|(_, _, pixel)| pixel[3] > 0);
+    let (min_x, min_y, max_x, max_y) = visible.fold(None, |bounds, (x, y, _)| match bounds {
+        None => Some((x, y, x, y)),
+        Some((min_x, min_y, max_x, max_y)) => Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)))
+    })?;
+
+    Some(chunk(CEL_CHUNK, |data| {
+        put_word(data, layer);
+        put_word(data, min_x as u16);
+        put_word(data, min_y as u16);
+        data.push(255);
+        put_word(data, 0);
+        put_word(data, 0);
+        data.extend([0; 5]);
+        put_word(data, (max_x - min_x + 1) as u16);
+        put_word(data, (max_y - min_y + 1) as u16);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                data.extend(image.get_pixel(x, y).0);
+            }
+        }
+    }))
+}
+
+///Builds a chunk with its size and type in front of the data written by `write`.
+fn chunk(kind : u16, write : impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+    let mut data = vec![0; 4];
+    put_word(&mut data, kind);
+    write(&mut data);
+    let size = data.len() as u32;
+    data[..4].copy_from_slice(&size.to_le_bytes());
+    data
+}
+
+fn put_word(data : &mut Vec<u8>, value : u16) {
+    data.extend(value.to_le_bytes());
+}
+
+fn put_dword(data : &mut Vec<u8>, value : u32) {
+    data.extend(value.to_le_bytes());
+}
+
+fn put_string(data : &mut Vec<u8>, value : &str) {
+    put_word(data, value.len() as u16);
+    data.extend(value.as_bytes());
+}
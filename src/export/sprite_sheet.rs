@@ -0,0 +1,193 @@
+//Renders a model at a number of yaw angles, and optionally a number of animation frames at every angle,
+//and packs all of the low resolution frames into a single sprite sheet. A JSON file is written next to
+//the sheet that describes where each frame is.
+
+#[cfg(test)]
+mod tests {
+    use crate::export::sprite_sheet::*;
+
+    #[test]
+    fn angles_are_kept_square() {
+        let sheet = SpriteSheet::new(8);
+        assert_eq!(sheet.columns(), 3);
+        assert_eq!(sheet.rows(), 3);
+
+        let sheet = SpriteSheet::new(1);
+        assert_eq!((sheet.columns(), sheet.rows()), (1, 1));
+    }
+
+    #[test]
+    fn every_angle_gets_a_row_of_frames() {
+        let sheet = SpriteSheet::new(4).with_frames(6);
+        assert_eq!((sheet.columns(), sheet.rows()), (6, 4));
+        assert_eq!(sheet.cell(0, 5), (5, 0));
+        assert_eq!(sheet.cell(2, 3), (3, 2));
+    }
+
+    #[test]
+    fn columns_wrap_frames_onto_the_next_row() {
+        let sheet = SpriteSheet::new(2).with_frames(3).with_columns(4);
+        assert_eq!((sheet.columns(), sheet.rows()), (4, 2));
+        assert_eq!(sheet.cell(0, 2), (2, 0));
+        assert_eq!(sheet.cell(1, 0), (3, 0));
+        assert_eq!(sheet.cell(1, 2), (1, 1));
+
+        let sheet = SpriteSheet::new(3).with_columns(10);
+        assert_eq!((sheet.columns(), sheet.rows()), (3, 1));
+    }
+}
+
+use std::fs;
+use std::path::Path;
+use glow::Context;
+use glutin::dpi::PhysicalSize;
+use image::RgbaImage;
+use serde::Serialize;
+use crate::render::downsize::Downsize;
+use crate::render::post::PostProcess;
+use crate::render::transform::Transform;
+
+pub struct SpriteSheet {
+    angles : u32,
+    frames : u32,
+    columns : Option<u32>
+}
+
+impl SpriteSheet {
+    ///Creates a sprite sheet that steps the yaw of the model `angles` times around a full rotation.
+    pub fn new(angles : u32) -> Self {
+        SpriteSheet {
+            angles : angles.max(1),
+            frames : 1,
+            columns : None
+        }
+    }
+
+    ///Renders `frames` animation frames at every angle. Unless the columns are set, every angle gets
+    ///its own row.
+    pub fn with_frames(mut self, frames : u32) -> Self {
+        self.frames = frames.max(1);
+        self
+    }
+
+    ///Sets the amount of frames per row. By default the sheet is kept as square as possible.
+    pub fn with_columns(mut self, columns : u32) -> Self {
+        self.columns = Some(columns.max(1));
+        self
+    }
+
+    fn cells(&self) -> u32 {
+        self.angles * self.frames
+    }
+
+    pub fn columns(&self) -> u32 {
+        let columns = if self.frames > 1 { self.frames } else { (self.cells() as f32).sqrt().ceil() as u32 };
+        self.columns.unwrap_or(columns).min(self.cells())
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.cells().div_ceil(self.columns())
+    }
+
+    ///The column and row of the cell a frame is packed into, counting the frames of every angle in order.
+    fn cell(&self, angle : u32, animation_frame : u32) -> (u32, u32) {
+        let index = angle * self.frames + animation_frame;
+        (index % self.columns(), index / self.columns())
+    }
+
+    ///Renders every frame of every angle through `downsize` and packs them into a grid. `transform` is
+    ///rotated around the y axis with `add_rot_wrap` after every angle, and ends up back at its starting
+    ///rotation. The render callback is handed the aspect ratio, the transform for the current angle and
+    ///the animation frame, and `passes` are applied to every frame before it is read back.
+    pub fn render(&self, gl : &Context, downsize : &mut Downsize, size : PhysicalSize<u32>, passes : &[&dyn PostProcess], transform : &mut Transform,
+                  mut render_frame : impl FnMut(&Context, f32, &Transform, u32)) -> SpriteSheetImage {
+        let step = 360.0 / self.angles as f32;
+        let columns = self.columns();
+        let mut frames = Vec::new();
+        let mut image = None;
+
+        for angle in 0..self.angles {
+            for animation_frame in 0..self.frames {
+                let index = angle * self.frames + animation_frame;
+                downsize.render_offscreen(gl, size, |gl, aspect_ratio| render_frame(gl, aspect_ratio, transform, animation_frame));
+                downsize.apply_passes(gl, passes);
+                let frame = downsize.read_image(gl);
+                let (width, height) = frame.dimensions();
+
+                let sheet = image.get_or_insert_with(|| RgbaImage::new(width * columns, height * self.rows()));
+                let (column, row) = self.cell(angle, animation_frame);
+                let (x, y) = (column * width, row * height);
+                image::imageops::replace(sheet, &frame, x as i64, y as i64);
+
+                frames.push(SpriteFrame {
+                    index,
+                    angle : step * angle as f32,
+                    frame : animation_frame,
+                    x,
+                    y,
+                    width,
+                    height
+                });
+            }
+
+            transform.add_rot_wrap((0.0, step, 0.0), 0.0, 360.0);
+        }
+
+        SpriteSheetImage {
+            image : image.unwrap(),
+            columns,
+            rows : self.rows(),
+            frames
+        }
+    }
+}
+
+///The rect of a single frame in the sprite sheet. `angle` is the yaw offset in degrees from the
+///starting rotation of the model, and `frame` is the animation frame it shows.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SpriteFrame {
+    pub index : u32,
+    pub angle : f32,
+    pub frame : u32,
+    pub x : u32,
+    pub y : u32,
+    pub width : u32,
+    pub height : u32
+}
+
+pub struct SpriteSheetImage {
+    pub image : RgbaImage,
+    pub columns : u32,
+    pub rows : u32,
+    pub frames : Vec<SpriteFrame>
+}
+
+#[derive(Serialize)]
+struct SpriteSheetSidecar<'a> {
+    image : String,
+    width : u32,
+    height : u32,
+    columns : u32,
+    rows : u32,
+    frames : &'a [SpriteFrame]
+}
+
+impl SpriteSheetImage {
+    ///Writes the sheet as a PNG to `path`, and the frame rects to the same path with a `.json` extension.
+    pub fn save<P>(&self, path : P) -> Result<(), String> where P : AsRef<Path> {
+        let path = path.as_ref();
+        self.image.save(path).map_err(|e| format!("Unable to write '{}': {}", path.display(), e))?;
+
+        let sidecar = SpriteSheetSidecar {
+            image : path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            width : self.image.width(),
+            height : self.image.height(),
+            columns : self.columns,
+            rows : self.rows,
+            frames : &self.frames
+        };
+        let json = serde_json::to_string_pretty(&sidecar).map_err(|e| e.to_string())?;
+        let json_path = path.with_extension("json");
+        fs::write(&json_path, json).map_err(|e| format!("Unable to write '{}': {}", json_path.display(), e))
+    }
+}
@@ -1,66 +1,91 @@
 mod render;
 mod util;
+mod cli;
+mod export;
 
 use glow::*;
-use crate::render::{createGlutinContext, buffer::VBO, Renderable, texture::Texture, Deletable};
-use cgmath::{Vector3, Vector2, Matrix4, SquareMatrix, Rad, Deg, perspective};
+use crate::render::{createGlutinContext, buffer::VBO, Renderable, Deletable};
+use cgmath::{Vector3, Vector2, Matrix4, SquareMatrix, Rad, Deg};
 use crate::render::buffer::{FBO, VAO};
 use glutin::event::{Event, MouseButton, WindowEvent};
 use glutin::event_loop::ControlFlow;
-use crate::render::shader::{ShaderBuilder, UniformValue};
+use crate::render::shader::ShaderBuilder;
 use std::time::{Instant, SystemTime};
 use crate::render::debug::{Debugable, UIRenderType};
 use crate::render::debug::UIRenderType::*;
 use crate::render::transform::Transform;
+use crate::render::camera::Camera;
+use crate::render::animation::Timeline;
+use crate::render::orbit::OrbitController;
 use egui::{Align2, Color32, Pos2, DragValue};
 use crate::render::downsize::Downsize;
 use crate::render::lighting::GlobalLighting;
-use crate::render::model::OBJModel;
+use crate::render::lights::{LightList, MAX_LIGHTS};
+use crate::render::shadow::ShadowMap;
+use crate::render::hot_reload::ShaderWatcher;
+use crate::render::uniform_block::UniformBlock;
+use crate::render::model::{Model, OBJModel};
+use crate::render::palette::PalettePass;
+use crate::render::outline::OutlinePass;
 use crate::render::shader::UniformValue::VEC3F;
 use crate::util::input::InputState;
 
 fn main() -> Result<(), String> {
+    let args : Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args);
+    }
+
     let (render_context, shader_version, event_loop, mut egui_glow) = createGlutinContext("Downsize");
     let mut input = InputState::default();
 
-    let texture = Texture::new(&render_context.gl, "copper_block.png");
-
     let mut transform = Transform::default();
 
-    let mut camera_transform = Transform::default();
-    camera_transform.set_pos((0.0, 0.0, -3.0));
-    camera_transform.set_rot((35.264, 45.0, 0.0));
+    let mut camera = Camera::default();
+    let mut orbit = OrbitController::from_camera(&camera);
 
     let mut global_lighting = GlobalLighting::default();
+    let mut lights = LightList::default();
 
     ///Testing new Shader Code
     let mut shdr = ShaderBuilder::new()
         .with_vert_shader("static_vert.glsl")
         .with_frag_shader("static_frag.glsl")
+        .with_define("MAX_LIGHTS", MAX_LIGHTS)
         .build(&render_context).expect("Unable to create shader.");
 
-    shdr.add_uniform("camera", &mut camera_transform);
     shdr.add_uniform("transform", &mut transform);
-    shdr.add_multi_uniform(&mut global_lighting);
+    shdr.add_multi_uniform(&mut lights);
+
+    let camera_block = UniformBlock::new(&render_context, "Camera", &camera.block()).expect("Unable to create camera block.");
+    let lighting_block = UniformBlock::new(&render_context, "GlobalLight", &global_lighting.block()).expect("Unable to create lighting block.");
+
+    let mut shadow_map = ShadowMap::new(&render_context, 1024).expect("Unable to create shadow map.");
+    shdr.add_multi_uniform(&mut shadow_map);
 
     let model = OBJModel::new(&render_context, "torus.obj", shdr).expect("Could not load model");
 
     let mut downsize = Downsize::new(&render_context.gl, 240);
-    let mut should_animate = false;
+    let mut palette_pass = PalettePass::new(&render_context, "pico8.hex").expect("Unable to create palette pass.");
+    let mut outline_pass = OutlinePass::new(&render_context).expect("Unable to create outline pass.");
+    let mut timeline = Timeline::spin(24, 96);
+    let mut shader_watcher = ShaderWatcher::default();
 
     let mut last_frame_end = Instant::now();
     let mut current_frame_start = last_frame_end.elapsed();
     let mut number_of_bands = 4.0;
-    let mut use_texture = false;
 
     event_loop.run(move |event, test, control_flow| {
-        let (test, list) = egui_glow.run(render_context.window.window(), |egui_ctx| {
+        let (test, list) = egui_glow.run(render_context.window().window(), |egui_ctx| {
             let window = egui::Window::new("Debug")
                 .collapsible(false)
                 .anchor(Align2::LEFT_TOP, (10.0, 10.0))
                 .title_bar(false)
                 .resizable(false);
 
+            global_lighting.gizmo(egui_ctx, camera.view_matrix());
+            shader_watcher.overlay(egui_ctx);
+
             window.show(egui_ctx, |ui| {
                 ui.horizontal(|ui| {
                     ui.label(format!("FPS: {:.2}", 1.0 / current_frame_start.as_secs_f64()));
@@ -71,20 +96,54 @@ fn main() -> Result<(), String> {
                     ui.label("Pixel Density:");
                     downsize.debug(ui, true);
                 });
-                ui.collapsing("Camera Transform", |ui| {
-                    camera_transform.debug(ui, true);
+                ui.horizontal(|ui| {
+                    ui.label("Dither:");
+                    let mut dither = palette_pass.dither();
+                    if dither.debug(ui, true) { palette_pass.set_dither(dither) }
+                });
+                palette_pass.debug(ui, true);
+                ui.collapsing("Camera", |ui| {
+                    camera.debug(ui, true);
+                    if orbit.debug(ui, true) {
+                        orbit.apply(&mut camera);
+                    }
+                    if ui.button("Frame Model").clicked() {
+                        let bounds = model.bounds().transformed(transform.calc_mat());
+                        orbit.frame(&mut camera, &bounds, downsize.pixel_density(), 1);
+                    }
                 });
                 ui.collapsing("Plane Transform", |ui| {
                     transform.debug(ui, true);
                 });
                 global_lighting.debug(ui, true);
+                ui.collapsing("Lights", |ui| {
+                    lights.debug(ui, true);
+                    if ui.button("Save").clicked() {
+                        if let Err(error) = lights.save("lights.json") { eprintln!("{}", error) }
+                    }
+                });
+                ui.collapsing("Shadows", |ui| {
+                    shadow_map.debug(ui, true);
+                });
+                outline_pass.debug(ui, true);
                 ui.horizontal(|ui| {
                     ui.label("Number of Bands:");
                     ui.add(DragValue::new(&mut number_of_bands).speed(1.0).clamp_range(1.0..=100.0))
                 });
                 ui.separator();
-                ui.checkbox(&mut should_animate, "Should Animate");
-                ui.checkbox(&mut use_texture, "Should Use Texture");
+                ui.collapsing("Animation", |ui| {
+                    if timeline.debug(ui, true) {
+                        timeline.apply(&mut transform, &mut global_lighting);
+                    }
+                    ui.horizontal(|ui| {
+                        if ui.button("Key Transform").clicked() {
+                            timeline.key_transform(&transform);
+                        }
+                        if ui.button("Save").clicked() {
+                            if let Err(error) = timeline.save("animation.json") { eprintln!("{}", error) }
+                        }
+                    });
+                });
             });
         });
 
@@ -96,7 +155,7 @@ fn main() -> Result<(), String> {
                 // input.update_state(event, Some(egui_glow.egui_ctx.available_rect()));
                 match event {
                     WindowEvent::Resized(physical_size) => {
-                        render_context.window.resize(*physical_size);
+                        render_context.window().resize(*physical_size);
                         unsafe { render_context.gl.viewport(0, 0, physical_size.width as i32, physical_size.height as i32); }
                     }
                     WindowEvent::CloseRequested => {
@@ -112,7 +171,7 @@ fn main() -> Result<(), String> {
             Event::Suspended => {}
             Event::Resumed => {}
             Event::MainEventsCleared => {
-                render_context.window.window().request_redraw();
+                render_context.window().window().request_redraw();
             }
             Event::RedrawRequested(_) => {
                 current_frame_start = last_frame_end.elapsed();
@@ -121,35 +180,36 @@ fn main() -> Result<(), String> {
                 unsafe {
                     render_context.gl.clear(COLOR_BUFFER_BIT | DEPTH_BUFFER_BIT);
 
-                    if should_animate {
-                        transform.add_rot_wrap((0.0, 0.5, 0.0), 0.0, 360.0);
-                    }
+                    shader_watcher.poll(&render_context);
 
-                    if use_texture {
-                        model.shader.send_uniform("use_texture", 1);
-                    } else {
-                        model.shader.send_uniform("use_texture", 0);
+                    if timeline.update(current_frame_start.as_secs_f32()) {
+                        timeline.apply(&mut transform, &mut global_lighting);
                     }
 
-                    input.mouse.on_drag(MouseButton::Right, |x, y| {
-                        camera_transform.add_rot_clamp_xz((y, x, 0.0), -90.0, 90.0);
-                    });
+                    orbit.update(&input.mouse, &mut camera, render_context.window().window().inner_size().height as f32);
 
-                    input.mouse.on_scroll(|x, y| {
-                        camera_transform.add_pos((0.0, 0.0, y));
-                    });
+                    camera.set_snap_resolution(downsize.pixel_density());
+                    downsize.set_subtexel_offset(camera.snap_offset());
 
-                    downsize.render(&render_context.gl, render_context.window.window().inner_size(), |gl, aspect_ratio| {
-                        let pers = perspective(Deg(80.0), aspect_ratio, 0.00001, 200.0);
-                        model.shader.send_uniform("perspective", pers);
+                    if shadow_map.enabled() {
+                        shadow_map.fit(global_lighting.direction(), &model.bounds().transformed(transform.calc_mat()));
+                        shadow_map.render(&render_context.gl, None, |gl, shader| {
+                            shader.send_uniform("transform", transform.clone()).ok();
+                            model.render_with(gl, shader)
+                        });
+                    }
+                    downsize.render(&render_context.gl, render_context.window().window().inner_size(), &[&outline_pass, &palette_pass], |gl, aspect_ratio| {
+                        camera.set_aspect_ratio(aspect_ratio);
+                        camera_block.update(&camera.block());
+                        lighting_block.update(&global_lighting.block());
                         model.shader.send_uniform("transform", transform.clone());
+                        model.shader.send_uniform("transform_normal", transform.normal_matrix()).ok();
                         model.shader.send_uniform("level_amout", number_of_bands);
-                        texture.bind(&render_context.gl);
                         model.render(&render_context.gl)
                     });
 
-                    egui_glow.paint(&render_context.window, &render_context.gl, list);
-                    render_context.window.swap_buffers().unwrap();
+                    egui_glow.paint(render_context.window(), &render_context.gl, list);
+                    render_context.window().swap_buffers().unwrap();
 
                     input.update();
                 }
@@ -161,6 +221,11 @@ fn main() -> Result<(), String> {
                 //vert_vbo.destroy(&render_context.gl);
                 //uv_vbo.destroy(&render_context.gl);
                 downsize.delete(&render_context.gl);
+                palette_pass.delete(&render_context.gl);
+                outline_pass.delete(&render_context.gl);
+                unsafe { shadow_map.delete(&render_context.gl) }
+                unsafe { camera_block.delete(&render_context.gl) }
+                unsafe { lighting_block.delete(&render_context.gl) }
                 unsafe { model.delete(&render_context.gl) }
                 //unsafe { shdr.delete(&render_context.gl); }
             }
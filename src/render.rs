@@ -1,11 +1,13 @@
-use std::rc::Rc;
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
 use glow::*;
 use glutin::window::Window;
 use glutin::event_loop::EventLoop;
-use glutin::{ContextWrapper, PossiblyCurrent, WindowedContext};
+use glutin::{Api, ContextWrapper, GlProfile, GlRequest, PossiblyCurrent, WindowedContext};
 use egui_glow::EguiGlow;
 use egui::Ui;
 use glutin::dpi::PhysicalSize;
+use crate::render::shader::ReloadableProgram;
 
 pub mod frame;
 pub mod buffer;
@@ -16,6 +18,22 @@ pub mod transform;
 pub mod model;
 pub mod downsize;
 pub mod lighting;
+pub mod post;
+pub mod palette;
+pub mod dither;
+pub mod outline;
+pub mod mesh;
+pub mod gltf_model;
+pub mod camera;
+pub mod orbit;
+pub mod animation;
+pub mod skin;
+pub mod lights;
+pub mod shadow;
+pub mod hot_reload;
+pub mod preprocess;
+pub mod reflect;
+pub mod uniform_block;
 
 pub trait Renderable {
     unsafe fn render(&self, gl : &Context);
@@ -27,7 +45,53 @@ pub trait Deletable {
 
 pub struct RenderContext {
     pub gl : Context,
-    pub window : ContextWrapper<PossiblyCurrent, Window>,
+    pub window : Option<ContextWrapper<PossiblyCurrent, Window>>,
+    ///The headless context, kept alive for as long as `gl` is used, and the size it was created with.
+    headless : Option<(glutin::Context<PossiblyCurrent>, PhysicalSize<u32>)>,
+    ///Every shader built with this context, so they can be rebuilt when their files change.
+    shaders : RefCell<Vec<Weak<ReloadableProgram>>>,
+    ///The names of the uniform blocks shared by every shader. The index of a name is its binding point.
+    uniform_blocks : RefCell<Vec<String>>,
+}
+
+impl RenderContext {
+    ///Returns the windowed context. This will panic if the context was created with `create_headless_context`.
+    pub fn window(&self) -> &ContextWrapper<PossiblyCurrent, Window> {
+        self.window.as_ref().expect("This render context is headless and has no window.")
+    }
+
+    ///The shaders built with this context that have not been dropped or deleted.
+    pub(crate) fn shaders(&self) -> Vec<Rc<ReloadableProgram>> {
+        let mut shaders = self.shaders.borrow_mut();
+        shaders.retain(|shader| shader.upgrade().map_or(false, |shader| !shader.is_deleted()));
+        shaders.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    ///The binding point of the shared uniform block `name`, given out the first time it is asked for.
+    pub(crate) fn uniform_block_binding(&self, name : &str) -> u32 {
+        let mut blocks = self.uniform_blocks.borrow_mut();
+        match blocks.iter().position(|block| block == name) {
+            Some(binding) => binding as u32,
+            None => {
+                blocks.push(name.to_owned());
+                blocks.len() as u32 - 1
+            }
+        }
+    }
+
+    ///The shared uniform blocks with their binding points.
+    pub(crate) fn uniform_blocks(&self) -> Vec<(String, u32)> {
+        self.uniform_blocks.borrow().iter().enumerate().map(|(binding, name)| (name.clone(), binding as u32)).collect()
+    }
+
+    ///The size of the surface that is being rendered to. For a windowed context this is the inner size
+    ///of the window, for a headless context it is the size it was created with.
+    pub fn surface_size(&self) -> PhysicalSize<u32> {
+        match &self.headless {
+            Some((_, size)) => *size,
+            None => self.window().window().inner_size()
+        }
+    }
 }
 //
 // impl RenderContext {
@@ -108,8 +172,7 @@ pub fn createGlutinContext<'a>(title : &str) -> (Rc<RenderContext>, &'a str, Eve
         let gl =
             glow::Context::from_loader_function(|s| window.get_proc_address(s) as *const _);
 
-        gl.enable(FRAMEBUFFER_SRGB);
-        gl.enable(DEPTH_TEST);
+        setup_gl(&gl);
 
         // gl.enable(CULL_FACE);
         // gl.cull_face(BACK);
@@ -127,6 +190,57 @@ pub fn createGlutinContext<'a>(title : &str) -> (Rc<RenderContext>, &'a str, Eve
         //     println!("[GL ERROR][{}]:{}", severity_text, message)
         // });
 
-        (Rc::new(RenderContext{gl, window }) , "#version 410", event_loop, egui_glow)
+        (Rc::new(RenderContext{gl, window : Some(window), headless : None, shaders : RefCell::new(Vec::new()), uniform_blocks : RefCell::new(Vec::new()) }) , "#version 410", event_loop, egui_glow)
     }
+}
+
+///Creates a render context that has no window attached to it. This is used to run the downsize pipeline
+///on machines without a display (build servers, CI, ...). On unix this uses OSMesa, which means it also
+///works with a software rasterizer like llvmpipe. Other platforms need an event loop on the main thread to
+///create a context, so they return an error. Everything should be rendered into an FBO, as the default
+///framebuffer of a headless context is not guaranteed to exist.
+pub fn create_headless_context(width : u32, height : u32) -> Result<Rc<RenderContext>, String> {
+    unsafe {
+        let size = PhysicalSize::new(width, height);
+        let context = build_headless(size)?
+            .make_current()
+            .map_err(|(_, e)| format!("Unable to make headless context current: {:?}", e))?;
+
+        let gl =
+            glow::Context::from_loader_function(|s| context.get_proc_address(s) as *const _);
+
+        setup_gl(&gl);
+        gl.viewport(0, 0, width as i32, height as i32);
+
+        Ok(Rc::new(RenderContext{gl, window : None, headless : Some((context, size)), shaders : RefCell::new(Vec::new()), uniform_blocks : RefCell::new(Vec::new()) }))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))]
+fn build_headless(size : PhysicalSize<u32>) -> Result<glutin::Context<glutin::NotCurrent>, String> {
+    use glutin::platform::unix::HeadlessContextExt;
+
+    headless_context_builder()
+        .build_osmesa(size)
+        .map_err(|e| format!("Unable to create OSMesa context: {}", e))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+fn build_headless(_size : PhysicalSize<u32>) -> Result<glutin::Context<glutin::NotCurrent>, String> {
+    Err("Headless rendering needs OSMesa, which is only available on unix.".to_owned())
+}
+
+#[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))]
+fn headless_context_builder<'a>() -> glutin::ContextBuilder<'a, glutin::NotCurrent> {
+    glutin::ContextBuilder::new()
+        .with_gl(GlRequest::Specific(Api::OpenGl, (4, 1)))
+        .with_gl_profile(GlProfile::Core)
+        .with_depth_buffer(0)
+        .with_srgb(true)
+        .with_stencil_buffer(0)
+}
+
+unsafe fn setup_gl(gl : &Context) {
+    gl.enable(FRAMEBUFFER_SRGB);
+    gl.enable(DEPTH_TEST);
 }
\ No newline at end of file
@@ -0,0 +1,385 @@
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+    use crate::render::animation::*;
+
+    fn track(interpolation : Interpolation) -> Track {
+        let mut track = Track::new(Property::POSITION);
+        track.insert(Keyframe::new(0, (0.0, 0.0, 0.0), interpolation));
+        track.insert(Keyframe::new(10, (10.0, 20.0, 30.0), interpolation));
+        track
+    }
+
+    #[test]
+    fn linear_interpolates() {
+        assert_eq!(track(Interpolation::LINEAR).sample(5.0), Some(Vector3::new(5.0, 10.0, 15.0)));
+    }
+
+    #[test]
+    fn step_holds_until_next_key() {
+        let track = track(Interpolation::STEP);
+        assert_eq!(track.sample(9.0), Some(Vector3::new(0.0, 0.0, 0.0)));
+        assert_eq!(track.sample(10.0), Some(Vector3::new(10.0, 20.0, 30.0)));
+    }
+
+    #[test]
+    fn values_are_held_outside_of_the_keys() {
+        let track = track(Interpolation::LINEAR);
+        assert_eq!(track.sample(-3.0), Some(Vector3::new(0.0, 0.0, 0.0)));
+        assert_eq!(track.sample(30.0), Some(Vector3::new(10.0, 20.0, 30.0)));
+        assert_eq!(Track::new(Property::SCALE).sample(0.0), None);
+    }
+
+    #[test]
+    fn bezier_eases() {
+        assert_eq!(Interpolation::BEZIER(EASE_IN_OUT).ease(0.0), 0.0);
+        assert_eq!(Interpolation::BEZIER(EASE_IN_OUT).ease(1.0), 1.0);
+        assert!((Interpolation::BEZIER(EASE_IN_OUT).ease(0.5) - 0.5).abs() < 0.0001);
+        assert!(Interpolation::BEZIER(EASE_IN_OUT).ease(0.2) < 0.2);
+        assert!((Interpolation::BEZIER([0.0, 0.0, 1.0, 1.0]).ease(0.3) - 0.3).abs() < 0.0001);
+    }
+
+    #[test]
+    fn playback_is_frame_exact() {
+        let mut timeline = Timeline::new(24, 48);
+        timeline.play();
+        for _ in 0..24 { timeline.update(1.0 / 24.0); }
+        assert_eq!(timeline.frame(), 24);
+        for _ in 0..30 { timeline.update(1.0 / 24.0); }
+        assert_eq!(timeline.frame(), 6);
+        timeline.set_frame(100);
+        assert_eq!(timeline.frame(), 47);
+    }
+}
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use cgmath::Vector3;
+use egui::{ComboBox, DragValue, Slider, Ui};
+use serde::{Deserialize, Serialize};
+use crate::render::debug::Debugable;
+use crate::render::lighting::GlobalLighting;
+use crate::render::transform::Transform;
+
+///The control points of CSS's `ease-in-out`.
+pub const EASE_IN_OUT : [f32; 4] = [0.42, 0.0, 0.58, 1.0];
+
+///How the value moves from one keyframe to the next. The interpolation of the earlier key is used.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Interpolation {
+    STEP,
+    LINEAR,
+    ///A cubic bezier easing curve from (0, 0) to (1, 1) with the two control points `[x1, y1, x2, y2]`,
+    ///the same as CSS's `cubic-bezier`.
+    BEZIER([f32; 4]),
+}
+
+impl Interpolation {
+    pub const ALL : [Interpolation; 3] = [Interpolation::STEP, Interpolation::LINEAR, Interpolation::BEZIER(EASE_IN_OUT)];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Interpolation::STEP => "Step",
+            Interpolation::LINEAR => "Linear",
+            Interpolation::BEZIER(_) => "Bezier",
+        }
+    }
+
+    ///Maps the progress between two keys, from 0 to 1, to how far the value has moved.
+    pub fn ease(&self, t : f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Interpolation::STEP => if t >= 1.0 { 1.0 } else { 0.0 },
+            Interpolation::LINEAR => t,
+            Interpolation::BEZIER(_) if t <= 0.0 || t >= 1.0 => t,
+            Interpolation::BEZIER([x1, y1, x2, y2]) => {
+                let curve = |a : f32, b : f32, s : f32| 3.0 * (1.0 - s) * (1.0 - s) * s * a + 3.0 * (1.0 - s) * s * s * b + s * s * s;
+
+                //The curve is monotonic in x as long as the control points stay within 0 to 1, so a
+                //bisection always finds the parameter for t.
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..32 {
+                    let mid = (low + high) / 2.0;
+                    if curve(*x1, *x2, mid) < t { low = mid } else { high = mid }
+                }
+                curve(*y1, *y2, (low + high) / 2.0)
+            }
+        }
+    }
+}
+
+///What a track animates. Light directions use x and y as pitch and yaw, and the ambient uses x.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Property {
+    POSITION,
+    ROTATION,
+    SCALE,
+    LIGHT_COLOR,
+    LIGHT_DIRECTION,
+    LIGHT_AMBIENT,
+}
+
+impl Property {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Property::POSITION => "Position",
+            Property::ROTATION => "Rotation",
+            Property::SCALE => "Scale",
+            Property::LIGHT_COLOR => "Light Color",
+            Property::LIGHT_DIRECTION => "Light Direction",
+            Property::LIGHT_AMBIENT => "Light Ambient",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    pub frame : u32,
+    pub value : Vector3<f32>,
+    pub interpolation : Interpolation,
+}
+
+impl Keyframe {
+    pub fn new<T>(frame : u32, value : T, interpolation : Interpolation) -> Self where T : Into<Vector3<f32>> {
+        Keyframe { frame, value : value.into(), interpolation }
+    }
+}
+
+///The keyframes of one property, sorted by frame.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Track {
+    pub property : Property,
+    keyframes : Vec<Keyframe>,
+}
+
+impl Track {
+    pub fn new(property : Property) -> Self {
+        Track { property, keyframes : Vec::new() }
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    ///Adds a keyframe, replacing any key that is already on that frame.
+    pub fn insert(&mut self, keyframe : Keyframe) {
+        match self.keyframes.binary_search_by_key(&keyframe.frame, |key| key.frame) {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    pub fn remove(&mut self, frame : u32) {
+        self.keyframes.retain(|key| key.frame != frame);
+    }
+
+    ///The value at a frame. Before the first key and after the last one the value is held.
+    pub fn sample(&self, frame : f32) -> Option<Vector3<f32>> {
+        let first = self.keyframes.first()?;
+        let next = self.keyframes.iter().position(|key| key.frame as f32 > frame);
+
+        Some(match next {
+            None => self.keyframes.last().unwrap().value,
+            Some(0) => first.value,
+            Some(index) => {
+                let from = &self.keyframes[index - 1];
+                let to = &self.keyframes[index];
+                let t = (frame - from.frame as f32) / (to.frame - from.frame) as f32;
+                from.value + (to.value - from.value) * from.interpolation.ease(t)
+            }
+        })
+    }
+}
+
+///A set of tracks played back at a fixed frame rate. Frames are whole numbers, so evaluating a frame
+///always gives the same result no matter how fast the preview runs, which keeps exports deterministic.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Timeline {
+    pub fps : u32,
+    ///The number of frames, playback loops back to frame 0 after the last one.
+    pub length : u32,
+    pub tracks : Vec<Track>,
+    #[serde(skip)]
+    time : f32,
+    #[serde(skip)]
+    playing : bool,
+    #[serde(skip, default = "default_interpolation")]
+    key_interpolation : Interpolation,
+}
+
+fn default_interpolation() -> Interpolation {
+    Interpolation::LINEAR
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline::new(24, 96)
+    }
+}
+
+impl Timeline {
+    pub fn new(fps : u32, length : u32) -> Self {
+        Timeline {
+            fps : fps.max(1),
+            length : length.max(1),
+            tracks : Vec::new(),
+            time : 0.0,
+            playing : false,
+            key_interpolation : default_interpolation(),
+        }
+    }
+
+    ///A timeline that spins a model once around the y axis.
+    pub fn spin(fps : u32, length : u32) -> Self {
+        let mut timeline = Timeline::new(fps, length);
+        timeline.key(Property::ROTATION, Keyframe::new(0, (0.0, 0.0, 0.0), Interpolation::LINEAR));
+        timeline.key(Property::ROTATION, Keyframe::new(length, (0.0, 360.0, 0.0), Interpolation::LINEAR));
+        timeline
+    }
+
+    ///Reads a timeline from a JSON file.
+    pub fn load<P>(path : P) -> Result<Self, String> where P : AsRef<Path> {
+        let file = File::open(path.as_ref()).map_err(|e| format!("Unable to open '{}': {}", path.as_ref().display(), e))?;
+        let timeline : Timeline = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("Unable to read '{}': {}", path.as_ref().display(), e))?;
+        Ok(Timeline::new(timeline.fps, timeline.length).with_tracks(timeline.tracks))
+    }
+
+    pub fn save<P>(&self, path : P) -> Result<(), String> where P : AsRef<Path> {
+        let file = File::create(path.as_ref()).map_err(|e| format!("Unable to write '{}': {}", path.as_ref().display(), e))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self).map_err(|e| e.to_string())
+    }
+
+    fn with_tracks(mut self, tracks : Vec<Track>) -> Self {
+        self.tracks = tracks;
+        self
+    }
+
+    ///Adds a keyframe to the track of a property, creating the track if it does not exist yet.
+    pub fn key(&mut self, property : Property, keyframe : Keyframe) {
+        match self.tracks.iter_mut().find(|track| track.property == property) {
+            Some(track) => track.insert(keyframe),
+            None => {
+                let mut track = Track::new(property);
+                track.insert(keyframe);
+                self.tracks.push(track);
+            }
+        }
+    }
+
+    ///Keys the position, rotation and scale of a transform on the current frame.
+    pub fn key_transform(&mut self, transform : &Transform) {
+        let frame = self.frame();
+        let interpolation = self.key_interpolation;
+        self.key(Property::POSITION, Keyframe::new(frame, transform.position(), interpolation));
+        self.key(Property::ROTATION, Keyframe::new(frame, transform.rotation(), interpolation));
+        self.key(Property::SCALE, Keyframe::new(frame, transform.scale(), interpolation));
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn frame(&self) -> u32 {
+        //The small bias stops frames from being skipped when the time lands a rounding error short of one.
+        ((self.time * self.fps as f32 + 0.0001).floor() as u32).min(self.length - 1)
+    }
+
+    ///Jumps to a frame, clamped to the length of the timeline.
+    pub fn set_frame(&mut self, frame : u32) {
+        self.time = frame.min(self.length - 1) as f32 / self.fps as f32;
+    }
+
+    ///Advances playback by `delta` seconds. Returns true if the frame changed.
+    pub fn update(&mut self, delta : f32) -> bool {
+        if !self.playing { return false }
+        let frame = self.frame();
+        let duration = self.length as f32 / self.fps as f32;
+        self.time = (self.time + delta) % duration;
+        frame != self.frame()
+    }
+
+    ///Sets every animated property to its value on the current frame.
+    pub fn apply(&self, transform : &mut Transform, lighting : &mut GlobalLighting) {
+        self.apply_frame(self.frame(), transform, lighting)
+    }
+
+    ///Sets every animated property to its value on `frame`.
+    pub fn apply_frame(&self, frame : u32, transform : &mut Transform, lighting : &mut GlobalLighting) {
+        for track in &self.tracks {
+            let value = match track.sample(frame as f32) {
+                Some(value) => value,
+                None => continue
+            };
+
+            match track.property {
+                Property::POSITION => { transform.set_pos(value); }
+                Property::ROTATION => { transform.set_rot(value); }
+                Property::SCALE => { transform.set_scale(value); }
+                Property::LIGHT_COLOR => lighting.set_color(value),
+                Property::LIGHT_DIRECTION => lighting.set_pitch_yaw((value.x, value.y)),
+                Property::LIGHT_AMBIENT => lighting.set_ambient(value.x),
+            }
+        }
+    }
+}
+
+impl Debugable for Timeline {
+    fn debug(&mut self, ui: &mut Ui, enabled: bool) -> bool {
+        ui.add_enabled_ui(enabled, |ui| {
+            let mut changed = false;
+
+            ui.horizontal(|ui| {
+                if ui.button(if self.playing { "Pause" } else { "Play" }).clicked() {
+                    if self.playing { self.pause() } else { self.play() }
+                }
+
+                let mut frame = self.frame();
+                if ui.add(Slider::new(&mut frame, 0..=self.length - 1).text("Frame")).changed() {
+                    self.set_frame(frame);
+                    changed = true;
+                }
+            });
+
+            ui.horizontal(|ui| {
+                let frame = self.frame();
+                ui.label("FPS:");
+                changed |= ui.add(DragValue::new(&mut self.fps).clamp_range(1..=120)).changed();
+                ui.label("Length:");
+                changed |= ui.add(DragValue::new(&mut self.length).clamp_range(1..=10000)).changed();
+                //Changing the rate or length keeps the same frame selected.
+                if changed { self.set_frame(frame) }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("New Keys:");
+                ComboBox::from_id_source("key_interpolation")
+                    .selected_text(self.key_interpolation.name())
+                    .show_ui(ui, |ui| {
+                        for option in Interpolation::ALL {
+                            ui.selectable_value(&mut self.key_interpolation, option, option.name());
+                        }
+                    });
+            });
+
+            let frame = self.frame();
+            for track in &mut self.tracks {
+                let keyed = track.keyframes().iter().any(|key| key.frame == frame);
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} ({} keys)", track.property.name(), track.keyframes().len()));
+                    if keyed && ui.small_button("Remove Key").clicked() {
+                        track.remove(frame);
+                        changed = true;
+                    }
+                });
+            }
+
+            changed
+        }).inner
+    }
+}
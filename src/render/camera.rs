@@ -0,0 +1,384 @@
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector2, Vector3, Vector4};
+    use crate::render::camera::*;
+
+    fn screen_axis(camera : &Camera, axis : Vector4<f32>) -> (f32, f32) {
+        let projected = camera.view_matrix() * axis;
+        (projected.x, projected.y)
+    }
+
+    #[test]
+    fn isometric_axes_are_equal_length() {
+        let camera = Camera::new(Projection::ISOMETRIC);
+        let lengths : Vec<f32> = [Vector4::unit_x(), Vector4::unit_y(), Vector4::unit_z()].iter()
+            .map(|axis| {
+                let (x, y) = screen_axis(&camera, *axis);
+                Vector2::new(x, y).magnitude()
+            })
+            .collect();
+        assert!((lengths[0] - lengths[1]).abs() < 0.0001);
+        assert!((lengths[1] - lengths[2]).abs() < 0.0001);
+    }
+
+    #[test]
+    fn dimetric_is_two_to_one() {
+        let camera = Camera::new(Projection::DIMETRIC);
+        for axis in [Vector4::unit_x(), Vector4::unit_z()] {
+            let (x, y) = screen_axis(&camera, axis);
+            assert!(((y / x).abs() - 0.5).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn rotating_a_preset_makes_it_orthographic() {
+        let mut camera = Camera::new(Projection::DIMETRIC);
+        camera.set_rot((30.0, 45.0, 0.0));
+        assert_eq!(camera.projection(), Projection::DIMETRIC);
+        camera.set_rot((30.0, 50.0, 0.0));
+        assert_eq!(camera.projection(), Projection::ORTHOGRAPHIC);
+    }
+
+    #[test]
+    fn pixel_snap_rounds_to_texels() {
+        let mut camera = Camera::new(Projection::DIMETRIC);
+        camera.set_ortho_size(2.0).set_snap_resolution(64).set_target((0.123, 0.0, 0.456));
+        let unsnapped = camera.view_matrix();
+        camera.set_pixel_snap(true);
+        let snapped = camera.view_matrix();
+        let texel = camera.texel_size();
+        assert!((texel - 2.0 / 64.0).abs() < 0.00001);
+
+        for value in [snapped.w.x, snapped.w.y] {
+            let texels = value / texel;
+            assert!((texels - texels.round()).abs() < 0.001);
+        }
+
+        let offset = camera.snap_offset();
+        assert!(offset.x.abs() <= 0.5 && offset.y.abs() <= 0.5);
+        let restored = Vector3::new(snapped.w.x + offset.x * texel, snapped.w.y + offset.y * texel, snapped.w.z);
+        assert!((restored - unsnapped.w.truncate()).magnitude() < 0.0001);
+    }
+}
+
+use cgmath::{Deg, Matrix4, Vector2, Vector3, ortho, perspective};
+use egui::{ComboBox, DragValue, Grid, Ui};
+use crate::render::debug::Debugable;
+use crate::render::transform::Transform;
+use crate::render::uniform_block::{Std140, Std140Writer};
+
+///How the camera projects the scene. The axonometric presets are orthographic with a fixed rotation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    PERSPECTIVE,
+    ORTHOGRAPHIC,
+    ///True isometric, all three axes are foreshortened the same.
+    ISOMETRIC,
+    ///The 2:1 "game isometric" view, where every edge along the ground moves one pixel down for every two across.
+    DIMETRIC,
+    ///All three axes are foreshortened differently.
+    TRIMETRIC,
+}
+
+impl Projection {
+    pub const ALL : [Projection; 5] = [Projection::PERSPECTIVE, Projection::ORTHOGRAPHIC, Projection::ISOMETRIC, Projection::DIMETRIC, Projection::TRIMETRIC];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::PERSPECTIVE => "Perspective",
+            Projection::ORTHOGRAPHIC => "Orthographic",
+            Projection::ISOMETRIC => "Isometric",
+            Projection::DIMETRIC => "Dimetric 2:1",
+            Projection::TRIMETRIC => "Trimetric",
+        }
+    }
+
+    pub fn from_name(name : &str) -> Option<Projection> {
+        match name.to_lowercase().as_str() {
+            "perspective" => Some(Projection::PERSPECTIVE),
+            "orthographic" | "ortho" => Some(Projection::ORTHOGRAPHIC),
+            "isometric" => Some(Projection::ISOMETRIC),
+            "dimetric" => Some(Projection::DIMETRIC),
+            "trimetric" => Some(Projection::TRIMETRIC),
+            _ => None
+        }
+    }
+
+    ///The camera rotation (pitch, yaw, roll) in degrees that the preset uses, if it is a preset.
+    pub fn preset_rotation(&self) -> Option<Vector3<f32>> {
+        match self {
+            // atan(1 / sqrt(2)) tips the camera so that all three axes are the same length.
+            Projection::ISOMETRIC => Some(Vector3::new(35.264_39, 45.0, 0.0)),
+            // asin(tan(atan(1 / 2))) gives ground edges a slope of exactly 1:2.
+            Projection::DIMETRIC => Some(Vector3::new(30.0, 45.0, 0.0)),
+            Projection::TRIMETRIC => Some(Vector3::new(30.0, 60.0, 0.0)),
+            _ => None
+        }
+    }
+
+    pub fn is_orthographic(&self) -> bool {
+        !matches!(self, Projection::PERSPECTIVE)
+    }
+}
+
+///The contents of the `Camera` uniform block, see `Camera::block`. The `Std140` impl is written by hand
+///and pushes the members in the order `camera.glsl` declares them.
+pub struct CameraBlock {
+    pub perspective : Matrix4<f32>,
+    pub camera : Matrix4<f32>,
+}
+
+impl Std140 for CameraBlock {
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.push(&self.perspective).push(&self.camera);
+    }
+}
+
+///The camera that views the scene. Its matrices reach the shaders through the `Camera` uniform block,
+///see `Camera::block`.
+pub struct Camera {
+    projection : Projection,
+    transform : Transform,
+    fov : f32,
+    ortho_size : f32,
+    near : f32,
+    far : f32,
+    aspect_ratio : f32,
+    pixel_snap : bool,
+    snap_resolution : u32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        let mut transform = Transform::default();
+        transform.set_pos((0.0, 0.0, -3.0));
+        transform.set_rot((35.264, 45.0, 0.0));
+
+        Camera {
+            projection: Projection::PERSPECTIVE,
+            transform,
+            fov: 80.0,
+            ortho_size: 3.0,
+            near: 0.01,
+            far: 200.0,
+            aspect_ratio: 1.0,
+            pixel_snap: false,
+            snap_resolution: 1
+        }
+    }
+}
+
+impl Camera {
+    pub fn new(projection : Projection) -> Self {
+        let mut camera = Camera::default();
+        camera.set_projection(projection);
+        camera
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    ///Changes the projection. The presets also snap the rotation of the camera to their angles.
+    pub fn set_projection(&mut self, projection : Projection) -> &mut Self {
+        self.projection = projection;
+        if let Some(rotation) = projection.preset_rotation() {
+            self.transform.set_rot(rotation);
+        }
+        self
+    }
+
+    ///The vertical field of view in degrees, used by the perspective projection.
+    pub fn set_fov(&mut self, fov : f32) -> &mut Self {
+        self.fov = fov.clamp(1.0, 179.0);
+        self
+    }
+
+    ///How many world units fit in the height of the view, used by the orthographic projections.
+    pub fn set_ortho_size(&mut self, size : f32) -> &mut Self {
+        self.ortho_size = size.max(0.001);
+        self
+    }
+
+    ///When enabled, the camera only moves in whole texels of the low resolution target, so the pixels of
+    ///still geometry don't crawl while the camera moves. The part of the movement that was cut off is
+    ///given by `snap_offset`, which `Downsize` uses to shift the upscaled image.
+    pub fn set_pixel_snap(&mut self, pixel_snap : bool) -> &mut Self {
+        self.pixel_snap = pixel_snap;
+        self
+    }
+
+    ///The height in texels of the low resolution target, this is the pixel density of `Downsize`.
+    pub fn set_snap_resolution(&mut self, height : u32) -> &mut Self {
+        self.snap_resolution = height.max(1);
+        self
+    }
+
+    ///Should be called with the aspect ratio of the target before rendering.
+    pub fn set_aspect_ratio(&mut self, aspect_ratio : f32) -> &mut Self {
+        self.aspect_ratio = aspect_ratio;
+        self
+    }
+
+    pub fn set_pos<T>(&mut self, value : T) -> &mut Self where T : Into<Vector3<f32>> {
+        self.transform.set_pos(value);
+        self
+    }
+
+    ///Sets the rotation of the camera. Moving a preset away from its angles turns it into a plain
+    ///orthographic camera.
+    pub fn set_rot<T>(&mut self, value : T) -> &mut Self where T : Into<Vector3<f32>> {
+        self.transform.set_rot(value);
+        self.leave_preset();
+        self
+    }
+
+    ///Sets the point in world space that the camera rotates around. The position of the camera is
+    ///relative to this point.
+    pub fn set_target<T>(&mut self, target : T) -> &mut Self where T : Into<Vector3<f32>> {
+        self.transform.set_origin(-target.into());
+        self
+    }
+
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    ///The view matrix, snapped to whole texels when pixel snapping is on.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        let view = self.transform.calc_mat();
+        if !self.pixel_snap { return view }
+
+        let (snapped, _) = self.snap(view);
+        Matrix4::from_translation(snapped.extend(0.0)) * view
+    }
+
+    ///How far, in texels of the low resolution target, the snapped view is from where the camera
+    ///really is. Shifting the upscaled image by this keeps the motion smooth. It is zero when pixel
+    ///snapping is off.
+    pub fn snap_offset(&self) -> Vector2<f32> {
+        if !self.pixel_snap { return Vector2::new(0.0, 0.0) }
+        self.snap(self.transform.calc_mat()).1
+    }
+
+    ///The size of one texel of the low resolution target in view space, at the distance of the target.
+    pub fn texel_size(&self) -> f32 {
+        let view_height = if self.projection.is_orthographic() {
+            self.ortho_size
+        } else {
+            let distance = self.transform.position().z.abs().max(self.near);
+            2.0 * distance * (self.fov / 2.0).to_radians().tan()
+        };
+        view_height / self.snap_resolution as f32
+    }
+
+    ///Rounds the view space translation to whole texels. Returns the change that snaps it, and what
+    ///was cut off in texels.
+    fn snap(&self, view : Matrix4<f32>) -> (Vector2<f32>, Vector2<f32>) {
+        let texel = self.texel_size();
+        let translation = Vector2::new(view.w.x, view.w.y);
+        let snapped = Vector2::new((translation.x / texel).round() * texel, (translation.y / texel).round() * texel);
+        (snapped - translation, (translation - snapped) / texel)
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        if self.projection.is_orthographic() {
+            let half_height = self.ortho_size / 2.0;
+            let half_width = half_height * self.aspect_ratio;
+            // The near plane sits behind the camera, so nothing between it and the model gets clipped.
+            ortho(-half_width, half_width, -half_height, half_height, -self.far, self.far)
+        } else {
+            perspective(Deg(self.fov), self.aspect_ratio, self.near, self.far)
+        }
+    }
+
+    ///The matrices for the `Camera` uniform block in `camera.glsl`.
+    pub fn block(&self) -> CameraBlock {
+        CameraBlock { perspective : self.projection_matrix(), camera : self.view_matrix() }
+    }
+
+    fn leave_preset(&mut self) {
+        if let Some(rotation) = self.projection.preset_rotation() {
+            let difference = self.transform.rotation() - rotation;
+            if difference.x.abs() + difference.y.abs() + difference.z.abs() > 0.001 {
+                self.projection = Projection::ORTHOGRAPHIC;
+            }
+        }
+    }
+}
+
+impl Debugable for Camera {
+    fn debug(&mut self, ui: &mut Ui, enabled: bool) -> bool {
+        ui.add_enabled_ui(enabled, |ui| {
+            let mut changed = false;
+
+            let mut projection = self.projection;
+            ComboBox::from_id_source("projection_combo")
+                .selected_text(projection.name())
+                .show_ui(ui, |ui| {
+                    for option in Projection::ALL {
+                        ui.selectable_value(&mut projection, option, option.name());
+                    }
+                });
+            if projection != self.projection {
+                self.set_projection(projection);
+                changed = true;
+            }
+
+            let mut pixel_snap = self.pixel_snap;
+            if ui.checkbox(&mut pixel_snap, "Pixel Snap").changed() {
+                self.set_pixel_snap(pixel_snap);
+                changed = true;
+            }
+
+            Grid::new("camera_grid")
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    if self.projection.is_orthographic() {
+                        ui.label("Size:");
+                        let mut size = self.ortho_size;
+                        if ui.add(DragValue::new(&mut size).speed(0.05).clamp_range(0.01..=100.0)).changed() {
+                            self.set_ortho_size(size);
+                            changed = true;
+                        }
+                    } else {
+                        ui.label("FOV:");
+                        let mut fov = self.fov;
+                        if ui.add(DragValue::new(&mut fov).speed(0.5).clamp_range(1.0..=179.0)).changed() {
+                            self.set_fov(fov);
+                            changed = true;
+                        }
+                    }
+                    ui.end_row();
+
+                    ui.label("Position:");
+                    let mut pos = self.transform.position();
+                    if pos.debug(ui, true) {
+                        self.set_pos(pos);
+                        changed = true;
+                    }
+                    ui.end_row();
+
+                    ui.label("Rotation:");
+                    let mut rotation = self.transform.rotation();
+                    if rotation.debug(ui, true) {
+                        self.set_rot(rotation);
+                        changed = true;
+                    }
+                    ui.end_row();
+                });
+
+            changed
+        }).inner
+    }
+}
@@ -0,0 +1,233 @@
+#[cfg(test)]
+mod tests {
+    use crate::render::dither::*;
+
+    #[test]
+    fn bayer_4x4_matrix() {
+        let expected = [
+            [0, 8, 2, 10],
+            [12, 4, 14, 6],
+            [3, 11, 1, 9],
+            [15, 7, 13, 5]
+        ];
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(bayer_value(x, y, 4), expected[y as usize][x as usize])
+            }
+        }
+    }
+
+    #[test]
+    fn error_diffusion_mixes_colors() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255]]);
+        let mut image = RgbaImage::from_pixel(8, 8, image::Rgba([128, 128, 128, 255]));
+        Dither { mode : DitherMode::FLOYD_STEINBERG, strength : 1.0 }.quantize(&mut image, &palette);
+
+        let white = image.pixels().filter(|pixel| pixel[0] == 255).count();
+        assert!(white > 0 && white < 64);
+    }
+
+    #[test]
+    fn zero_strength_matches_plain_quantize() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255]]);
+        let mut plain = RgbaImage::from_pixel(4, 4, image::Rgba([100, 100, 100, 255]));
+        let mut dithered = plain.clone();
+        palette.quantize(&mut plain);
+        Dither { mode : DitherMode::ATKINSON, strength : 0.0 }.quantize(&mut dithered, &palette);
+        assert_eq!(plain, dithered);
+    }
+}
+
+use egui::{ComboBox, DragValue, Ui};
+use image::RgbaImage;
+use crate::render::debug::Debugable;
+use crate::render::palette::Palette;
+use crate::util::color::{linear_to_srgb_bytes, srgb_bytes_to_linear, srgb_to_linear, linear_to_oklab};
+
+///The dithering modes. The Bayer modes are ordered dithering and run in the palette shader, the error
+///diffusion modes can only run on the CPU, so they are only applied to read back (exported) images.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DitherMode {
+    NONE,
+    BAYER_2X2,
+    BAYER_4X4,
+    BAYER_8X8,
+    FLOYD_STEINBERG,
+    ATKINSON
+}
+
+impl DitherMode {
+    pub const ALL : [DitherMode; 6] = [
+        DitherMode::NONE,
+        DitherMode::BAYER_2X2,
+        DitherMode::BAYER_4X4,
+        DitherMode::BAYER_8X8,
+        DitherMode::FLOYD_STEINBERG,
+        DitherMode::ATKINSON
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DitherMode::NONE => "None",
+            DitherMode::BAYER_2X2 => "Bayer 2x2",
+            DitherMode::BAYER_4X4 => "Bayer 4x4",
+            DitherMode::BAYER_8X8 => "Bayer 8x8",
+            DitherMode::FLOYD_STEINBERG => "Floyd-Steinberg",
+            DitherMode::ATKINSON => "Atkinson"
+        }
+    }
+
+    ///Parses the names used on the command line (`none`, `bayer2`, `bayer4`, `bayer8`, `floyd-steinberg`, `atkinson`).
+    pub fn from_name(name : &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "none" => Some(DitherMode::NONE),
+            "bayer2" => Some(DitherMode::BAYER_2X2),
+            "bayer4" => Some(DitherMode::BAYER_4X4),
+            "bayer8" => Some(DitherMode::BAYER_8X8),
+            "floyd-steinberg" | "fs" => Some(DitherMode::FLOYD_STEINBERG),
+            "atkinson" => Some(DitherMode::ATKINSON),
+            _ => None
+        }
+    }
+
+    ///The size of the Bayer matrix, or 0 if this is not an ordered mode. This is what the shader gets.
+    pub fn bayer_size(&self) -> u32 {
+        match self {
+            DitherMode::BAYER_2X2 => 2,
+            DitherMode::BAYER_4X4 => 4,
+            DitherMode::BAYER_8X8 => 8,
+            _ => 0
+        }
+    }
+
+    pub fn is_error_diffusion(&self) -> bool {
+        matches!(self, DitherMode::FLOYD_STEINBERG | DitherMode::ATKINSON)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Dither {
+    pub mode : DitherMode,
+    pub strength : f32
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Dither {
+            mode : DitherMode::NONE,
+            strength : 1.0
+        }
+    }
+}
+
+impl Dither {
+    ///Quantizes the image to the palette using this dither mode. This is the CPU path that is used on
+    ///read back images, so it supports every mode.
+    pub fn quantize(&self, image : &mut RgbaImage, palette : &Palette) {
+        match self.mode {
+            DitherMode::NONE => palette.quantize(image),
+            DitherMode::BAYER_2X2 | DitherMode::BAYER_4X4 | DitherMode::BAYER_8X8 => self.ordered(image, palette),
+            DitherMode::FLOYD_STEINBERG => self.diffuse(image, palette, &[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0),
+            DitherMode::ATKINSON => self.diffuse(image, palette, &[(1, 0, 1.0), (2, 0, 1.0), (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)], 8.0)
+        }
+    }
+
+    fn ordered(&self, image : &mut RgbaImage, palette : &Palette) {
+        let size = self.mode.bayer_size();
+        let spread = ordered_spread(self.strength, palette.len());
+
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            if pixel[3] == 0 { continue }
+            let threshold = (bayer_value(x % size, y % size, size) as f32 + 0.5) / (size * size) as f32 - 0.5;
+            let linear = [0, 1, 2].map(|i| {
+                srgb_to_linear((pixel[i] as f32 / 255.0 + threshold * spread).clamp(0.0, 1.0))
+            });
+
+            let color = palette.colors()[palette.nearest_lab(linear_to_oklab(linear))];
+            pixel[0] = color[0];
+            pixel[1] = color[1];
+            pixel[2] = color[2];
+        }
+    }
+
+    ///Error diffusion in linear RGB. `kernel` is a list of (dx, dy, weight) and the weights are divided by `divisor`.
+    fn diffuse(&self, image : &mut RgbaImage, palette : &Palette, kernel : &[(i32, i32, f32)], divisor : f32) {
+        let (width, height) = image.dimensions();
+        let mut colors : Vec<[f32; 3]> = image.pixels()
+            .map(|pixel| srgb_bytes_to_linear([pixel[0], pixel[1], pixel[2]]))
+            .collect();
+
+        for y in 0..height {
+            for x in 0..width {
+                let index = (y * width + x) as usize;
+                if image.get_pixel(x, y)[3] == 0 { continue }
+
+                let old = colors[index].map(|value| value.clamp(0.0, 1.0));
+                let new = srgb_bytes_to_linear(palette.colors()[palette.nearest_lab(linear_to_oklab(old))]);
+                let error = [0, 1, 2].map(|i| (old[i] - new[i]) * self.strength);
+                colors[index] = new;
+
+                for (dx, dy, weight) in kernel {
+                    let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                    if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 { continue }
+                    if image.get_pixel(nx as u32, ny as u32)[3] == 0 { continue }
+
+                    let neighbour = &mut colors[(ny as u32 * width + nx as u32) as usize];
+                    for i in 0..3 {
+                        neighbour[i] += error[i] * weight / divisor;
+                    }
+                }
+            }
+        }
+
+        for (pixel, color) in image.pixels_mut().zip(colors) {
+            if pixel[3] == 0 { continue }
+            let srgb = linear_to_srgb_bytes(color);
+            pixel[0] = srgb[0];
+            pixel[1] = srgb[1];
+            pixel[2] = srgb[2];
+        }
+    }
+}
+
+///The value of the Bayer matrix of `size` (a power of two) at `x`, `y`. This goes from 0 to size * size - 1.
+pub fn bayer_value(x : u32, y : u32, size : u32) -> u32 {
+    let levels = size.trailing_zeros();
+    let mut value = 0;
+    for bit in 0..levels {
+        let bx = (x >> bit) & 1;
+        let by = (y >> bit) & 1;
+        value += (2 * (bx ^ by) + by) << (2 * (levels - 1 - bit));
+    }
+    value
+}
+
+///How far the ordered dither can push a color (in sRGB, 0 to 1). Smaller palettes have colors that
+///are further apart, so they need a larger spread.
+pub fn ordered_spread(strength : f32, palette_size : usize) -> f32 {
+    strength / (palette_size.max(2) as f32).cbrt()
+}
+
+impl Debugable for Dither {
+    fn debug(&mut self, ui: &mut Ui, enabled: bool) -> bool {
+        ui.add_enabled_ui(enabled, |ui| {
+            let mut mode = self.mode;
+            ComboBox::from_id_source("dither_mode")
+                .selected_text(mode.name())
+                .show_ui(ui, |ui| {
+                    for option in DitherMode::ALL {
+                        ui.selectable_value(&mut mode, option, option.name());
+                    }
+                });
+            let mode_changed = mode != self.mode;
+            self.mode = mode;
+
+            let strength_changed = ui.add(DragValue::new(&mut self.strength).speed(0.01).clamp_range(0.0..=2.0)).changed();
+            if self.mode.is_error_diffusion() {
+                ui.label("(export only)");
+            }
+
+            mode_changed || strength_changed
+        }).inner
+    }
+}
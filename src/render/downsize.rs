@@ -2,7 +2,9 @@ use cgmath::Vector2;
 use egui::{DragValue, Ui};
 use glow::*;
 use glutin::dpi::PhysicalSize;
+use image::RgbaImage;
 use crate::render::debug::{Debugable, UIRenderType};
+use crate::render::post::{PostProcess, PostProcessInput};
 use crate::util::variable::UpdateVariable;
 
 const STARTING_DIM : u32 = 500;
@@ -11,9 +13,16 @@ pub struct Downsize {
     pixel_density : u32,
     last_width : u32,
     last_height : u32,
+    texture_width : u32,
+    texture_height : u32,
     fbo : NativeFramebuffer,
     color_attachment : NativeTexture,
     depth_attachment : NativeTexture,
+    normal_attachment : NativeTexture,
+    post_fbo : NativeFramebuffer,
+    post_attachment : NativeTexture,
+    screen_vao : NativeVertexArray,
+    subtexel_offset : Vector2<f32>,
     should_recalc : bool,
     regular_render : bool
 }
@@ -28,12 +37,22 @@ impl Downsize {
             //Texture Attachment
             let color_attachment = gl.create_texture().expect("Could not create texture.");
             gl.bind_texture(TEXTURE_2D, Some(color_attachment));
-            gl.tex_image_2d(TEXTURE_2D, 0, SRGB as i32, STARTING_DIM as i32, STARTING_DIM as i32, 0, RGB, UNSIGNED_BYTE, None);
+            gl.tex_image_2d(TEXTURE_2D, 0, SRGB8_ALPHA8 as i32, STARTING_DIM as i32, STARTING_DIM as i32, 0, RGBA, UNSIGNED_BYTE, None);
             gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
             gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
             gl.bind_texture(TEXTURE_2D, None);
             gl.framebuffer_texture_2d(FRAMEBUFFER, COLOR_ATTACHMENT0, TEXTURE_2D, Some(color_attachment), 0);
 
+            //Normal Attachment, the normals are stored as `normal * 0.5 + 0.5` for the outline pass.
+            let normal_attachment = gl.create_texture().expect("Could not create normal texture.");
+            gl.bind_texture(TEXTURE_2D, Some(normal_attachment));
+            gl.tex_image_2d(TEXTURE_2D, 0, RGBA8 as i32, STARTING_DIM as i32, STARTING_DIM as i32, 0, RGBA, UNSIGNED_BYTE, None);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
+            gl.bind_texture(TEXTURE_2D, None);
+            gl.framebuffer_texture_2d(FRAMEBUFFER, COLOR_ATTACHMENT1, TEXTURE_2D, Some(normal_attachment), 0);
+            gl.draw_buffers(&[COLOR_ATTACHMENT0, COLOR_ATTACHMENT1]);
+
             //Depth Stencil Attachment
             let depth_attachment = gl.create_texture().expect("Could not create depth texture");
             gl.bind_texture(TEXTURE_2D, Some(depth_attachment));
@@ -43,43 +62,172 @@ impl Downsize {
             gl.bind_texture(TEXTURE_2D, None);
             gl.framebuffer_texture_2d(FRAMEBUFFER, DEPTH_STENCIL_ATTACHMENT, TEXTURE_2D, Some(depth_attachment), 0);
 
+            //Post processing target, passes render into this and it gets copied back to the color attachment.
+            let post_fbo = gl.create_framebuffer().expect("Can't create post processing fbo.");
+            gl.bind_framebuffer(FRAMEBUFFER, Some(post_fbo));
+            let post_attachment = gl.create_texture().expect("Could not create texture.");
+            gl.bind_texture(TEXTURE_2D, Some(post_attachment));
+            gl.tex_image_2d(TEXTURE_2D, 0, SRGB8_ALPHA8 as i32, STARTING_DIM as i32, STARTING_DIM as i32, 0, RGBA, UNSIGNED_BYTE, None);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
+            gl.bind_texture(TEXTURE_2D, None);
+            gl.framebuffer_texture_2d(FRAMEBUFFER, COLOR_ATTACHMENT0, TEXTURE_2D, Some(post_attachment), 0);
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+
+            let screen_vao = gl.create_vertex_array().expect("Could not create screen vao.");
+
             Downsize {
                 pixel_density,
                 last_width : 0,
                 last_height : 0,
+                texture_width : STARTING_DIM,
+                texture_height : STARTING_DIM,
                 fbo,
                 color_attachment,
                 depth_attachment,
+                normal_attachment,
+                post_fbo,
+                post_attachment,
+                screen_vao,
+                subtexel_offset : Vector2::new(0.0, 0.0),
                 should_recalc : false,
                 regular_render : false
             }
         }
     }
 
-    pub fn render<'a>(&mut self, gl : &'a Context, size : PhysicalSize<u32>, renderCallback: impl FnOnce(&'a Context, f32)) {
+    pub fn render<'a>(&mut self, gl : &'a Context, size : PhysicalSize<u32>, passes : &[&dyn PostProcess], renderCallback: impl FnOnce(&'a Context, f32)) {
         unsafe {
-            if self.pixel_density < 1 {self.pixel_density = 1}
-            if self.pixel_density > size.height { self.pixel_density = size.height}
-            let (width, height, aspect_ratio) = self.calc_texture_size(gl, size);
-
             if !self.regular_render {
-                gl.bind_framebuffer(FRAMEBUFFER, Some(self.fbo));
-                gl.viewport(0, 0, width as i32, height as i32);
-                gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
-
-                renderCallback(gl, aspect_ratio);
-
-                gl.bind_framebuffer(FRAMEBUFFER, None);
-                gl.bind_framebuffer(READ_FRAMEBUFFER, Some(self.fbo));
-                gl.bind_framebuffer(DRAW_FRAMEBUFFER, None);
-                gl.blit_framebuffer(0, 0, width as i32, height as i32, 0, 0, size.width as i32, size.height as i32, COLOR_BUFFER_BIT, NEAREST);
-                gl.bind_framebuffer(READ_FRAMEBUFFER, None);
+                let (width, height) = self.render_offscreen(gl, size, renderCallback);
+                self.apply_passes(gl, passes);
+                self.blit(gl, width, height, size);
             } else {
+                self.clamp_pixel_density(size);
+                let (_, _, aspect_ratio) = self.calc_texture_size(gl, size);
                 renderCallback(gl, aspect_ratio);
             }
         }
     }
 
+    ///Renders into the low resolution FBO without blitting it to the default framebuffer. This is what
+    ///a headless context should use, as there may not be a default framebuffer to blit to. Returns the
+    ///size of the low resolution texture.
+    pub fn render_offscreen<'a>(&mut self, gl : &'a Context, size : PhysicalSize<u32>, render_callback: impl FnOnce(&'a Context, f32)) -> (u32, u32) {
+        unsafe {
+            self.clamp_pixel_density(size);
+            let (width, height, _) = self.calc_texture_size(gl, size);
+            //The width is rounded down to whole texels, so the texture's own aspect ratio keeps the texels square.
+            let aspect_ratio = width as f32 / height as f32;
+
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.fbo));
+            gl.viewport(0, 0, width as i32, height as i32);
+            gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+
+            render_callback(gl, aspect_ratio);
+
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+            (width, height)
+        }
+    }
+
+    ///The low resolution FBO that `render_offscreen` draws into, for render callbacks that have to bind
+    ///another framebuffer and come back to it.
+    pub fn framebuffer(&self) -> NativeFramebuffer {
+        self.fbo
+    }
+
+    ///Runs the post processing passes in order on the low resolution color attachment. Each pass renders
+    ///into a second texture, which is then copied back so the color attachment always holds the result.
+    pub fn apply_passes(&mut self, gl : &Context, passes : &[&dyn PostProcess]) {
+        let (width, height) = (self.texture_width as i32, self.texture_height as i32);
+        let input = PostProcessInput {
+            depth : self.depth_attachment,
+            normal : self.normal_attachment
+        };
+
+        unsafe {
+            let depth_test = gl.is_enabled(DEPTH_TEST);
+            gl.disable(DEPTH_TEST);
+            gl.bind_vertex_array(Some(self.screen_vao));
+
+            for pass in passes.iter().filter(|pass| pass.is_enabled()) {
+                gl.bind_framebuffer(FRAMEBUFFER, Some(self.post_fbo));
+                gl.viewport(0, 0, width, height);
+                gl.clear(COLOR_BUFFER_BIT);
+                gl.active_texture(TEXTURE0);
+                gl.bind_texture(TEXTURE_2D, Some(self.color_attachment));
+
+                pass.apply(gl, &input);
+
+                //Only copy into the color attachment, the normals have to stay intact for the next pass.
+                gl.bind_framebuffer(READ_FRAMEBUFFER, Some(self.post_fbo));
+                gl.bind_framebuffer(DRAW_FRAMEBUFFER, Some(self.fbo));
+                gl.draw_buffers(&[COLOR_ATTACHMENT0]);
+                gl.blit_framebuffer(0, 0, width, height, 0, 0, width, height, COLOR_BUFFER_BIT, NEAREST);
+                gl.draw_buffers(&[COLOR_ATTACHMENT0, COLOR_ATTACHMENT1]);
+            }
+
+            gl.active_texture(TEXTURE0);
+            gl.bind_texture(TEXTURE_2D, None);
+            gl.bind_vertex_array(None);
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+            if depth_test { gl.enable(DEPTH_TEST) }
+        }
+    }
+
+    unsafe fn blit(&self, gl : &Context, width : u32, height : u32, size : PhysicalSize<u32>) {
+        gl.bind_framebuffer(READ_FRAMEBUFFER, Some(self.fbo));
+        gl.bind_framebuffer(DRAW_FRAMEBUFFER, None);
+        gl.blit_framebuffer(0, 0, width as i32, height as i32, 0, 0, size.width as i32, size.height as i32, COLOR_BUFFER_BIT, NEAREST);
+
+        //Shift the image by the part of the camera movement that pixel snapping cut off. The unshifted
+        //copy underneath fills the strip that the shift uncovers at the edge.
+        let x = (self.subtexel_offset.x * size.width as f32 / width as f32).round() as i32;
+        let y = (self.subtexel_offset.y * size.height as f32 / height as f32).round() as i32;
+        if x != 0 || y != 0 {
+            gl.blit_framebuffer(0, 0, width as i32, height as i32, x, y, size.width as i32 + x, size.height as i32 + y, COLOR_BUFFER_BIT, NEAREST);
+        }
+        gl.bind_framebuffer(READ_FRAMEBUFFER, None);
+    }
+
+    ///Reads the color attachment back at its low resolution (the size of the last render). The color
+    ///attachment is stored sRGB encoded, so `FRAMEBUFFER_SRGB` is turned off while reading to make sure
+    ///the driver hands back the encoded values instead of converting them to linear. The image is flipped
+    ///so the first row is the top of the image, and the background is transparent.
+    pub fn read_image(&self, gl : &Context) -> RgbaImage {
+        unsafe {
+            let (width, height) = (self.texture_width, self.texture_height);
+            let mut pixels = vec![0; (width * height * 4) as usize];
+
+            let srgb_enabled = gl.is_enabled(FRAMEBUFFER_SRGB);
+            gl.disable(FRAMEBUFFER_SRGB);
+            gl.bind_framebuffer(READ_FRAMEBUFFER, Some(self.fbo));
+            gl.read_buffer(COLOR_ATTACHMENT0);
+            gl.pixel_store_i32(PACK_ALIGNMENT, 1);
+            gl.read_pixels(0, 0, width as i32, height as i32, RGBA, UNSIGNED_BYTE, PixelPackData::Slice(&mut pixels));
+            gl.bind_framebuffer(READ_FRAMEBUFFER, None);
+            if srgb_enabled { gl.enable(FRAMEBUFFER_SRGB) }
+
+            let image = RgbaImage::from_raw(width, height, pixels).expect("Pixel buffer does not match the texture size.");
+            image::imageops::flip_vertical(&image)
+        }
+    }
+
+    ///The offset in low resolution texels to shift the image by when it is upscaled, see `Camera::snap_offset`.
+    pub fn set_subtexel_offset(&mut self, offset : Vector2<f32>) {
+        self.subtexel_offset = offset;
+    }
+
+    pub fn pixel_density(&self) -> u32 {
+        self.pixel_density
+    }
+
+    fn clamp_pixel_density(&mut self, size : PhysicalSize<u32>) {
+        if self.pixel_density < 1 {self.pixel_density = 1}
+        if self.pixel_density > size.height { self.pixel_density = size.height}
+    }
+
     fn calc_texture_size(&mut self, gl : &Context, new_size : PhysicalSize<u32>) -> (u32, u32, f32) {
         let aspect_ratio = new_size.width as f32 / new_size.height as f32;
         let new_height = self.pixel_density;
@@ -89,20 +237,33 @@ impl Downsize {
             unsafe {
                 //println!("RECALCULATING TEXTURE! {}", new_height);
                 gl.bind_texture(TEXTURE_2D, Some(self.color_attachment));
-                gl.tex_image_2d(TEXTURE_2D, 0, SRGB as i32, new_width as i32, new_height as i32, 0, RGB, UNSIGNED_BYTE, None);
+                gl.tex_image_2d(TEXTURE_2D, 0, SRGB8_ALPHA8 as i32, new_width as i32, new_height as i32, 0, RGBA, UNSIGNED_BYTE, None);
                 gl.bind_texture(TEXTURE_2D, Some(self.depth_attachment));
                 gl.tex_image_2d(TEXTURE_2D, 0, DEPTH24_STENCIL8 as i32, new_width as i32, new_height as i32, 0, DEPTH_STENCIL, UNSIGNED_INT_24_8, None);
+                gl.bind_texture(TEXTURE_2D, Some(self.normal_attachment));
+                gl.tex_image_2d(TEXTURE_2D, 0, RGBA8 as i32, new_width as i32, new_height as i32, 0, RGBA, UNSIGNED_BYTE, None);
+                gl.bind_texture(TEXTURE_2D, Some(self.post_attachment));
+                gl.tex_image_2d(TEXTURE_2D, 0, SRGB8_ALPHA8 as i32, new_width as i32, new_height as i32, 0, RGBA, UNSIGNED_BYTE, None);
             }
             self.last_width = new_size.width;
             self.last_height = new_size.height;
             self.should_recalc = false;
         }
 
+        self.texture_width = new_width;
+        self.texture_height = new_height;
+
         (new_width, new_height, aspect_ratio)
     }
 
     pub fn delete(&self, gl : &Context) {
-        unsafe { gl.delete_framebuffer(self.fbo) }
+        unsafe {
+            gl.delete_framebuffer(self.fbo);
+            gl.delete_framebuffer(self.post_fbo);
+            gl.delete_texture(self.post_attachment);
+            gl.delete_texture(self.normal_attachment);
+            gl.delete_vertex_array(self.screen_vao);
+        }
     }
 }
 
@@ -0,0 +1,275 @@
+use std::path::Path;
+use std::rc::Rc;
+use cgmath::{Matrix4, Quaternion, Vector2, Vector3, Vector4};
+use glow::Context;
+use gltf::animation::util::ReadOutputs;
+use gltf::animation::{Animation, Interpolation};
+use gltf::image::{Data, Format};
+use gltf::mesh::Mode;
+use gltf::{Document, Node};
+use image::RgbaImage;
+use crate::render::mesh::{Bounds, MeshData};
+use crate::render::model::{MeshGroup, Model};
+use crate::render::skin::{pose, skin_mesh, AnimationClip, Channel, ChannelPath, SamplerInterpolation, SceneNode, Skin, SkinWeights};
+use crate::render::shader::Shader;
+use crate::render::texture::Texture;
+use crate::render::transform::Transform;
+use crate::render::{Deletable, Renderable, RenderContext};
+
+///A model loaded from a `.gltf` or `.glb` file. Every primitive of every mesh in the scene becomes its
+///own `MeshGroup`, with the world transform of its node and the base color of its material. Skinned
+///primitives keep their bind pose, and are skinned on the cpu whenever the model is posed.
+pub struct GLTFModel {
+    pub groups : Vec<MeshGroup>,
+    sources : Vec<GroupSource>,
+    nodes : Vec<SceneNode>,
+    skins : Vec<Skin>,
+    clips : Vec<AnimationClip>,
+    textures : Vec<Rc<Texture>>,
+    pub shader : Shader,
+    render_context : Rc<RenderContext>,
+}
+
+///Where a `MeshGroup` came from. `bind` is only kept for skinned groups.
+struct GroupSource {
+    node : usize,
+    skin : Option<usize>,
+    bind : Option<MeshData>,
+}
+
+impl GLTFModel {
+    ///Loads the default scene of a glTF file, or the first scene if there is no default.
+    pub fn from_path<P>(render_context : &Rc<RenderContext>, path : P, shader : Shader) -> Result<GLTFModel, String> where P : AsRef<Path> {
+        let gl = &render_context.gl;
+        let (document, buffers, images) = gltf::import(path.as_ref())
+            .map_err(|e| format!("Unable to read '{}': {}", path.as_ref().display(), e))?;
+
+        let textures = images.iter()
+            .map(|data| image_to_rgba(data).map(|image| Rc::new(Texture::from_rgba(gl, &image))))
+            .collect::<Result<Vec<Rc<Texture>>, String>>()?;
+
+        let scene = document.default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or_else(|| String::from("The file does not have a scene."))?;
+
+        let skins = document.skins().map(|skin| {
+            let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+            Skin {
+                joints: skin.joints().map(|joint| joint.index()).collect(),
+                inverse_binds: reader.read_inverse_bind_matrices()
+                    .map(|matrices| matrices.map(Matrix4::from).collect())
+                    .unwrap_or_default(),
+            }
+        }).collect();
+
+        let mut model = GLTFModel {
+            groups: Vec::new(),
+            sources: Vec::new(),
+            nodes: load_nodes(&document),
+            skins,
+            clips: document.animations().map(|animation| load_clip(&animation, &buffers)).collect(),
+            textures,
+            shader,
+            render_context: Rc::clone(render_context),
+        };
+
+        for node in scene.nodes() {
+            model.load_node(&node, &buffers)?;
+        }
+        model.pose(None, 0.0);
+
+        Ok(model)
+    }
+
+    fn load_node(&mut self, node : &Node, buffers : &[gltf::buffer::Data]) -> Result<(), String> {
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if primitive.mode() != Mode::Triangles { continue }
+
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let pbr = primitive.material().pbr_metallic_roughness();
+                let base_color_texture = pbr.base_color_texture();
+                let tex_coord_set = base_color_texture.as_ref().map(|info| info.tex_coord()).unwrap_or(0);
+
+                let positions : Vec<Vector3<f32>> = reader.read_positions()
+                    .ok_or_else(|| format!("A primitive of mesh '{}' has no positions.", mesh.name().unwrap_or("unnamed")))?
+                    .map(Vector3::from)
+                    .collect();
+                let uvs = reader.read_tex_coords(tex_coord_set).map(|uvs| uvs.into_f32().map(Vector2::from).collect());
+                let normals = reader.read_normals().map(|normals| normals.map(Vector3::from).collect());
+                let indices = reader.read_indices().map(|indices| indices.into_u32().collect());
+                let skin = match (node.skin(), reader.read_joints(0), reader.read_weights(0)) {
+                    (Some(_), Some(joints), Some(weights)) => Some(joints.into_u16().zip(weights.into_f32())
+                        .map(|(joints, weights)| SkinWeights { joints, weights })
+                        .collect()),
+                    _ => None
+                };
+
+                let mesh_data = MeshData::from_indexed(positions, uvs, normals, indices, skin);
+                let mut group = MeshGroup::new(&self.render_context.gl, &mesh_data);
+                let [r, g, b, _] = pbr.base_color_factor();
+                group.color = Vector3::new(r, g, b);
+                group.texture = base_color_texture.map(|info| Rc::clone(&self.textures[info.texture().source().index()]));
+                self.groups.push(group);
+
+                let skinned = !mesh_data.skin.is_empty();
+                self.sources.push(GroupSource {
+                    node: node.index(),
+                    skin: node.skin().filter(|_| skinned).map(|skin| skin.index()),
+                    bind: if skinned { Some(mesh_data) } else { None },
+                });
+            }
+        }
+
+        for child in node.children() {
+            self.load_node(&child, buffers)?;
+        }
+
+        Ok(())
+    }
+}
+
+///Reads the rest transform of every node in the file and links each one to its parent.
+fn load_nodes(document : &Document) -> Vec<SceneNode> {
+    let mut nodes : Vec<SceneNode> = document.nodes().map(|node| {
+        let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+        SceneNode {
+            parent: None,
+            translation: Vector3::from(translation),
+            rotation: Quaternion::new(w, x, y, z),
+            scale: Vector3::from(scale),
+        }
+    }).collect();
+
+    for node in document.nodes() {
+        for child in node.children() {
+            nodes[child.index()].parent = Some(node.index());
+        }
+    }
+    nodes
+}
+
+///Reads the channels of an animation. Morph target weights are not supported and are skipped.
+fn load_clip(animation : &Animation, buffers : &[gltf::buffer::Data]) -> AnimationClip {
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else { continue };
+
+        let (path, values) : (ChannelPath, Vec<Vector4<f32>>) = match outputs {
+            ReadOutputs::Translations(values) => (ChannelPath::TRANSLATION, values.map(|v| Vector3::from(v).extend(0.0)).collect()),
+            ReadOutputs::Rotations(values) => (ChannelPath::ROTATION, values.into_f32().map(Vector4::from).collect()),
+            ReadOutputs::Scales(values) => (ChannelPath::SCALE, values.map(|v| Vector3::from(v).extend(0.0)).collect()),
+            ReadOutputs::MorphTargetWeights(_) => continue,
+        };
+
+        channels.push(Channel {
+            node: channel.target().node().index(),
+            path,
+            interpolation: match channel.sampler().interpolation() {
+                Interpolation::Step => SamplerInterpolation::STEP,
+                Interpolation::Linear => SamplerInterpolation::LINEAR,
+                Interpolation::CubicSpline => SamplerInterpolation::CUBICSPLINE,
+            },
+            times: inputs.collect(),
+            values,
+        });
+    }
+
+    let duration = channels.iter().filter_map(|channel| channel.times.last().copied()).fold(0.0, f32::max);
+    AnimationClip {
+        name: animation.name().map(String::from).unwrap_or_else(|| format!("animation {}", animation.index())),
+        channels,
+        duration,
+    }
+}
+
+///Converts a decoded glTF image into 8 bit RGBA.
+fn image_to_rgba(data : &Data) -> Result<RgbaImage, String> {
+    let channels = match data.format {
+        Format::R8 | Format::R16 => 1,
+        Format::R8G8 | Format::R16G16 => 2,
+        Format::R8G8B8 | Format::R16G16B16 | Format::R32G32B32FLOAT => 3,
+        Format::R8G8B8A8 | Format::R16G16B16A16 | Format::R32G32B32A32FLOAT => 4,
+    };
+
+    let values : Vec<u8> = match data.format {
+        Format::R8 | Format::R8G8 | Format::R8G8B8 | Format::R8G8B8A8 => data.pixels.clone(),
+        Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16 => data.pixels.chunks_exact(2)
+            .map(|c| (u16::from_ne_bytes([c[0], c[1]]) >> 8) as u8)
+            .collect(),
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => data.pixels.chunks_exact(4)
+            .map(|c| (f32::from_ne_bytes([c[0], c[1], c[2], c[3]]).clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect(),
+    };
+
+    let pixels : Vec<u8> = values.chunks_exact(channels).flat_map(|p| match p {
+        [l] => [*l, *l, *l, 255],
+        [l, a] => [*l, *l, *l, *a],
+        [r, g, b] => [*r, *g, *b, 255],
+        [r, g, b, a] => [*r, *g, *b, *a],
+        _ => unreachable!(),
+    }).collect();
+
+    RgbaImage::from_raw(data.width, data.height, pixels)
+        .ok_or_else(|| String::from("Image data does not match its size."))
+}
+
+impl Renderable for GLTFModel {
+    unsafe fn render(&self, gl: &Context) {
+        self.render_with(gl, &self.shader);
+    }
+}
+
+impl Model for GLTFModel {
+    fn shader(&self) -> &Shader {
+        &self.shader
+    }
+
+    unsafe fn render_with(&self, gl : &Context, shader : &Shader) {
+        shader.bind();
+        for group in &self.groups {
+            group.render(gl, shader);
+        }
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.groups.iter().fold(Bounds::empty(), |bounds, group| bounds.union(&group.node_bounds()))
+    }
+
+    fn clips(&self) -> &[AnimationClip] {
+        &self.clips
+    }
+
+    ///Moves every node to where the clip has it at `time`. Skinned groups are skinned on the cpu and
+    ///uploaded again, so their node transform stays the identity like glTF asks.
+    fn pose(&mut self, clip : Option<usize>, time : f32) {
+        let world = pose(&self.nodes, clip.and_then(|index| self.clips.get(index)), time);
+        let gl = &self.render_context.gl;
+
+        for (group, source) in self.groups.iter_mut().zip(&self.sources) {
+            match (&source.bind, source.skin.and_then(|index| self.skins.get(index))) {
+                (Some(bind), Some(skin)) => {
+                    let (positions, normals) = skin_mesh(bind, &skin.joint_matrices(&world));
+                    group.bounds = Bounds::from_points(&positions);
+                    group.verts.load_vec3s(gl, positions);
+                    group.norms.load_vec3s(gl, normals);
+                    group.node = Transform::default();
+                }
+                _ => group.node = Transform::from_matrix(world[source.node]),
+            }
+        }
+    }
+}
+
+impl Deletable for GLTFModel {
+    unsafe fn delete(&self, gl: &Context) {
+        for group in &self.groups {
+            group.delete(gl);
+        }
+        for texture in &self.textures {
+            texture.destroy(gl);
+        }
+        self.shader.delete(gl);
+    }
+}
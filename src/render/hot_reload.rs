@@ -0,0 +1,147 @@
+//Rebuilds shaders while the app is running when their files change, so a shader can be edited without
+//restarting. Compile errors are shown in an overlay and the old program keeps running.
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::render::hot_reload::*;
+
+    fn directory(name : &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("downsize_{}_{}", name, std::process::id()));
+        fs::remove_dir_all(&directory).ok();
+        fs::create_dir_all(directory.join("lib")).unwrap();
+        fs::write(directory.join("static_frag.glsl"), "void main() {}").unwrap();
+        directory
+    }
+
+    #[test]
+    fn nothing_changed() {
+        let directory = directory("unchanged");
+        let mut watcher = ShaderWatcher::new(&directory);
+        assert!(watcher.changed_files().is_empty());
+        fs::remove_dir_all(&directory).ok();
+    }
+
+    #[test]
+    fn new_and_removed_files_are_changes() {
+        let directory = directory("changed");
+        let mut watcher = ShaderWatcher::new(&directory);
+
+        fs::write(directory.join("lib").join("light.glsl"), "float x;").unwrap();
+        assert_eq!(watcher.changed_files(), vec!["lib/light.glsl".to_owned()]);
+        assert!(watcher.changed_files().is_empty());
+
+        fs::remove_file(directory.join("static_frag.glsl")).unwrap();
+        assert_eq!(watcher.changed_files(), vec!["static_frag.glsl".to_owned()]);
+        fs::remove_dir_all(&directory).ok();
+    }
+}
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use egui::{Align2, Color32, CtxRef, RichText};
+use crate::render::RenderContext;
+
+///Looks for changed files under a directory, by default `assets/shaders`, and rebuilds the shaders that
+///use them. The modification times are polled, as editors often save by replacing the file.
+pub struct ShaderWatcher {
+    directory : PathBuf,
+    modified : HashMap<String, SystemTime>,
+    interval : Duration,
+    last_poll : Instant,
+    errors : Vec<String>,
+}
+
+impl Default for ShaderWatcher {
+    fn default() -> Self {
+        ShaderWatcher::new("assets/shaders")
+    }
+}
+
+impl ShaderWatcher {
+    pub fn new<P>(directory : P) -> Self where P : AsRef<Path> {
+        let directory = directory.as_ref().to_path_buf();
+        let mut modified = HashMap::new();
+        scan(&directory, &directory, &mut modified);
+        ShaderWatcher {
+            directory,
+            modified,
+            interval : Duration::from_millis(250),
+            last_poll : Instant::now(),
+            errors : Vec::new(),
+        }
+    }
+
+    ///Rebuilds every shader of the render context that uses a file that changed since the last poll.
+    ///This looks at the files at most four times a second, so it can be called every frame. Returns true
+    ///if any shader was rebuilt, even if that failed.
+    pub fn poll(&mut self, render_context : &RenderContext) -> bool {
+        if self.last_poll.elapsed() < self.interval { return false }
+        self.last_poll = Instant::now();
+
+        let changed = self.changed_files();
+        if changed.is_empty() { return false }
+
+        let shaders = render_context.shaders();
+        let mut reloaded = false;
+        for shader in &shaders {
+            if changed.iter().any(|file| shader.uses_file(file)) {
+                reloaded = true;
+                if let Err(error) = shader.reload(render_context) { eprintln!("{}", error) }
+            }
+        }
+        self.errors = shaders.iter().filter_map(|shader| shader.error()).collect();
+        reloaded
+    }
+
+    ///Shows the errors of the last rebuild on top of the viewport, if there are any.
+    pub fn overlay(&self, ctx : &CtxRef) {
+        if self.errors.is_empty() { return }
+
+        egui::Window::new("Shader Error")
+            .anchor(Align2::CENTER_TOP, (0.0, 10.0))
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("The last working shader is still being used.");
+                for error in &self.errors {
+                    ui.separator();
+                    ui.label(RichText::new(error).monospace().color(Color32::from_rgb(255, 110, 110)));
+                }
+            });
+    }
+
+    ///The files, relative to the directory, that were added, changed or removed since this was last called.
+    pub fn changed_files(&mut self) -> Vec<String> {
+        let mut modified = HashMap::new();
+        scan(&self.directory, &self.directory, &mut modified);
+
+        let mut changed : Vec<String> = modified.iter()
+            .filter(|(file, time)| self.modified.get(*file) != Some(time))
+            .map(|(file, _)| file.clone())
+            .chain(self.modified.keys().filter(|file| !modified.contains_key(*file)).cloned())
+            .collect();
+        changed.sort();
+        self.modified = modified;
+        changed
+    }
+}
+
+fn scan(root : &Path, directory : &Path, modified : &mut HashMap<String, SystemTime>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            scan(root, &path, modified);
+        } else if let (Ok(metadata), Ok(relative)) = (entry.metadata(), path.strip_prefix(root)) {
+            let name = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            modified.insert(name, metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+        }
+    }
+}
@@ -1,60 +1,176 @@
-use std::ops::IndexMut;
-use cgmath::{BaseFloat, Vector3, Vector2, Deg, Rad};
-use egui::{DragValue, Ui};
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector2, Vector3};
+    use crate::render::lighting::*;
+
+    fn close(a : Vector3<f32>, b : Vector3<f32>) -> bool {
+        (a - b).magnitude() < 0.0001
+    }
+
+    #[test]
+    fn pitch_and_yaw_point_the_right_way() {
+        assert!(close(direction_from_pitch_yaw((0.0, 0.0)), Vector3::new(0.0, 0.0, 1.0)));
+        assert!(close(direction_from_pitch_yaw((0.0, 90.0)), Vector3::new(1.0, 0.0, 0.0)));
+        assert!(close(direction_from_pitch_yaw((90.0, 0.0)), Vector3::new(0.0, 1.0, 0.0)));
+        assert!(close(direction_from_pitch_yaw((-90.0, 45.0)), Vector3::new(0.0, -1.0, 0.0)));
+    }
+
+    #[test]
+    fn pitch_and_yaw_round_trip() {
+        for (pitch, yaw) in [(0.0, 0.0), (30.0, 45.0), (-60.0, 170.0), (10.0, -120.0)] {
+            let direction = direction_from_pitch_yaw((pitch, yaw));
+            assert!((direction.magnitude() - 1.0).abs() < 0.0001);
+            let angles = pitch_yaw_from_direction(direction);
+            assert!((angles - Vector2::new(pitch, yaw)).magnitude() < 0.001);
+        }
+    }
+
+    #[test]
+    fn direction_is_kept_unit_length() {
+        let mut lighting = GlobalLighting::new((1.0, 1.0, 1.0), (0.0, 3.0, 4.0), 0.2);
+        assert!(close(lighting.direction(), Vector3::new(0.0, 0.6, 0.8)));
+
+        lighting.set_direction((0.0, 0.0, 0.0));
+        assert!(close(lighting.direction(), Vector3::new(0.0, 0.6, 0.8)));
+
+        lighting.set_pitch_yaw((90.0, 0.0));
+        assert!(close(lighting.direction(), Vector3::new(0.0, 1.0, 0.0)));
+        assert!((lighting.pitch_yaw().x - 90.0).abs() < 0.001);
+    }
+}
+
+use cgmath::{InnerSpace, Matrix4, Vector2, Vector3};
+use egui::{Align2, Color32, CtxRef, DragValue, Sense, Stroke, Ui};
 use crate::render::debug::{debug_colorRBG, Debugable};
-use crate::render::shader::{MultiUniform, ShaderResult, ShaderUniformHandler};
+use crate::render::uniform_block::{Std140, Std140Writer};
+
+///The unit vector towards a light that is `pitch` degrees above the horizon and turned `yaw` degrees
+///around the y axis, starting at +z and turning towards +x.
+pub fn direction_from_pitch_yaw<T>(angles : T) -> Vector3<f32> where T : Into<Vector2<f32>> {
+    let angles = angles.into();
+    let (pitch, yaw) = (angles.x.to_radians(), angles.y.to_radians());
+    Vector3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos())
+}
+
+///The pitch and yaw in degrees of a direction, the reverse of `direction_from_pitch_yaw`. Straight up
+///or down the yaw is 0.
+pub fn pitch_yaw_from_direction(direction : Vector3<f32>) -> Vector2<f32> {
+    let direction = direction.normalize();
+    let pitch = direction.y.clamp(-1.0, 1.0).asin().to_degrees();
+    let yaw = if direction.x.abs() < 0.00001 && direction.z.abs() < 0.00001 { 0.0 } else { direction.x.atan2(direction.z).to_degrees() };
+    Vector2::new(pitch, yaw)
+}
+
+///The contents of the `GlobalLight` uniform block, see `GlobalLighting::block`. The `Std140` impl is
+///written by hand and pushes the members in the order `global_light.glsl` declares them.
+pub struct GlobalLightBlock {
+    pub color : Vector3<f32>,
+    pub ambient : f32,
+    pub direction : Vector3<f32>,
+}
+
+impl Std140 for GlobalLightBlock {
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.push(&self.color).push(&self.ambient).push(&self.direction);
+    }
+}
 
+///The light that lights the whole scene from one direction, along with the ambient light. `direction`
+///is a unit vector in world space that points towards the light. It reaches the shaders through the
+///`GlobalLight` uniform block, see `GlobalLighting::block`.
 pub struct GlobalLighting {
     color : Vector3<f32>,
-    direction : Vector2<f32>,
+    direction : Vector3<f32>,
     ambient: f32,
-    color_uniform : Option<ShaderUniformHandler>,
-    direction_uniform : Option<ShaderUniformHandler>,
-    ambient_uniform: Option<ShaderUniformHandler>
+    show_gizmo : bool,
 }
 
 impl  GlobalLighting {
     pub fn new<T, V>(color : T, direction : V, ambient: f32) -> Self
-        where T : Into<Vector3<f32>>, V : Into<Vector2<f32>>{
-        GlobalLighting {
+        where T : Into<Vector3<f32>>, V : Into<Vector3<f32>>{
+        let mut lighting = GlobalLighting {
             color : color.into(),
-            direction : direction.into(),
             ambient,
             .. Self::default()
-        }
+        };
+        lighting.set_direction(direction);
+        lighting
+    }
+
+    pub fn color(&self) -> Vector3<f32> {
+        self.color
     }
 
     pub fn set_color<T>(&mut self, color: T) where T : Into<Vector3<f32>> {
         self.color = color.into();
-        self.update_color_to_shader();
     }
 
-    pub fn set_direction<T>(&mut self, dir : T) where T : Into<Vector2<f32>> {
-        self.direction = dir.into();
-        self.update_dir_to_shader();
+    ///The unit vector towards the light.
+    pub fn direction(&self) -> Vector3<f32> {
+        self.direction
     }
 
-    fn update_color_to_shader(&self) {
-        if self.color_uniform.is_some() {
-            self.color_uniform.as_ref().unwrap().update_uniform(self.color)
-        }
+    ///Points the light along `dir`, which points towards the light. It does not have to be unit length,
+    ///but a zero vector is ignored.
+    pub fn set_direction<T>(&mut self, dir : T) where T : Into<Vector3<f32>> {
+        let dir = dir.into();
+        if dir.magnitude2() <= 0.0 { return }
+        self.direction = dir.normalize();
     }
 
-    fn update_ambient_to_shader(&self) {
-        if self.ambient_uniform.is_some() {
-            self.ambient_uniform.as_ref().unwrap().update_uniform(self.ambient)
-        }
+    ///The direction as pitch and yaw in degrees, see `direction_from_pitch_yaw`.
+    pub fn pitch_yaw(&self) -> Vector2<f32> {
+        pitch_yaw_from_direction(self.direction)
     }
 
-    fn update_dir_to_shader(&self) {
-        if self.direction_uniform.is_some() {
-            let pitch = self.direction.x * 0.017453;
-            let yaw = self.direction.y * 0.017453;
-            let x = pitch.cos() * yaw.cos(); //cos(pitch)*cos(yaw)
-            let y = yaw.sin() * pitch.cos(); //sin(yaw)*cos(pitch)
-            let z = pitch.sin(); //sin(pitch)
-            self.direction_uniform.as_ref().unwrap().update_uniform((x, y, z))
-        }
+    pub fn set_pitch_yaw<T>(&mut self, angles : T) where T : Into<Vector2<f32>> {
+        self.set_direction(direction_from_pitch_yaw(angles));
+    }
+
+    pub fn ambient(&self) -> f32 {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient : f32) {
+        self.ambient = ambient;
+    }
+
+    ///The values for the `GlobalLight` uniform block in `global_light.glsl`.
+    pub fn block(&self) -> GlobalLightBlock {
+        GlobalLightBlock { color : self.color, ambient : self.ambient, direction : self.direction }
+    }
+
+    ///Draws the direction of the light as an arrow in the bottom right of the viewport, as seen through
+    ///`view`. Dragging it turns the yaw left and right and the pitch up and down. The tip is filled in
+    ///when the light is in front of the camera. The pitch stops short of straight up and down so the yaw is
+    ///not lost. Returns true if the direction changed.
+    pub fn gizmo(&mut self, ctx : &CtxRef, view : Matrix4<f32>) -> bool {
+        if !self.show_gizmo { return false }
+
+        egui::Area::new("global_light_gizmo")
+            .anchor(Align2::RIGHT_BOTTOM, (-10.0, -10.0))
+            .show(ctx, |ui| {
+                let (response, painter) = ui.allocate_painter(egui::vec2(100.0, 100.0), Sense::drag());
+                let center = response.rect.center();
+                let radius = response.rect.width() * 0.4;
+                let stroke = Stroke::new(2.0, Color32::from_rgb(255, 220, 100));
+
+                let facing = (view * self.direction.extend(0.0)).truncate();
+                let tip = center + egui::vec2(facing.x, -facing.y) * radius;
+                painter.circle_stroke(center, radius, Stroke::new(1.0, Color32::from_gray(160)));
+                painter.line_segment([center, tip], stroke);
+                if facing.z > 0.0 {
+                    painter.circle_filled(tip, 5.0, stroke.color);
+                } else {
+                    painter.circle_stroke(tip, 5.0, stroke);
+                }
+
+                let delta = response.drag_delta();
+                if delta == egui::Vec2::ZERO { return false }
+                let angles = self.pitch_yaw();
+                self.set_pitch_yaw(((angles.x - delta.y * 0.5).clamp(-89.9, 89.9), angles.y + delta.x * 0.5));
+                true
+            }).inner
     }
 }
 
@@ -62,26 +178,13 @@ impl Default for GlobalLighting {
     fn default() -> Self {
         GlobalLighting {
             color: Vector3::new(1.0, 1.0, 1.0),
-            direction: Vector2::new(0.0, 0.0),
+            direction: direction_from_pitch_yaw((0.0, 0.0)),
             ambient: 0.2,
-            color_uniform: None,
-            direction_uniform: None,
-            ambient_uniform: None
+            show_gizmo: false
         }
     }
 }
 
-impl MultiUniform for GlobalLighting {
-    fn provide_handle_provider(&mut self, provider: impl Fn(&str) -> Option<ShaderUniformHandler>) {
-        self.color_uniform = provider("global_light_color");
-        self.update_color_to_shader();
-        self.ambient_uniform = provider("global_ambient");
-        self.update_ambient_to_shader();
-        self.direction_uniform = provider("global_light_direction");
-        self.update_dir_to_shader();
-    }
-}
-
 impl Debugable for GlobalLighting {
     fn debug(&mut self, ui: &mut Ui, enabled: bool) -> bool {
         let mut color_changed = false;
@@ -91,21 +194,22 @@ impl Debugable for GlobalLighting {
         ui.horizontal(|ui| {
             ui.label("Lighting Color:");
             color_changed = debug_colorRBG(ui, enabled, &mut self.color);
-            if color_changed {self.update_color_to_shader()}
         });
 
         ui.horizontal(|ui| {
-            ui.label("Global Light Rotation");
-            dir_changed = self.direction.debug(ui, enabled);
-            if dir_changed {self.update_dir_to_shader()}
+            ui.label("Global Light Pitch/Yaw:");
+            let mut angles = self.pitch_yaw();
+            dir_changed |= ui.add_enabled(enabled, DragValue::new(&mut angles.x).speed(0.5).clamp_range(-90.0..=90.0).suffix("°")).changed();
+            dir_changed |= ui.add_enabled(enabled, DragValue::new(&mut angles.y).speed(0.5).suffix("°")).changed();
+            if dir_changed {self.set_pitch_yaw(angles)}
+            ui.add_enabled(enabled, egui::Checkbox::new(&mut self.show_gizmo, "Gizmo"));
         });
 
         ui.horizontal(|ui| {
             ui.label("Global Light Ambient:");
             ambient_changed = ui.add(DragValue::new(&mut self.ambient).speed(0.005).clamp_range(0.01..=1.0)).changed();
-            if ambient_changed {self.update_ambient_to_shader()}
         });
 
         color_changed || ambient_changed || dir_changed
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,294 @@
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+    use crate::render::lights::*;
+
+    #[test]
+    fn list_is_capped() {
+        let mut lights = LightList::default();
+        for _ in 0..MAX_LIGHTS + 2 {
+            lights.add(Light::default());
+        }
+        assert_eq!(lights.lights.len(), MAX_LIGHTS);
+    }
+
+    #[test]
+    fn cone_keeps_inner_inside_outer() {
+        let mut light = Light { kind : LightKind::SPOT, inner_angle : 40.0, outer_angle : 30.0, ..Light::default() };
+        let (inner, outer) = light.cone_cosines();
+        assert!(inner >= outer);
+        assert!((outer - 30.0f32.to_radians().cos()).abs() < 0.0001);
+
+        light.inner_angle = 10.0;
+        let (inner, _) = light.cone_cosines();
+        assert!((inner - 10.0f32.to_radians().cos()).abs() < 0.0001);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut lights = LightList::default();
+        lights.add(Light { kind : LightKind::DIRECTIONAL, direction : Vector3::new(1.0, -1.0, 0.0), ..Light::default() });
+        lights.add(Light { kind : LightKind::SPOT, inner_angle : 15.0, ..Light::default() });
+        let json = serde_json::to_string(&lights).unwrap();
+        let loaded : LightList = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.lights, lights.lights);
+        assert_eq!(loaded.lights[0].direction, Vector3::new(1.0, -1.0, 0.0));
+    }
+}
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use cgmath::{InnerSpace, Vector3};
+use egui::{ComboBox, DragValue, Grid, Ui};
+use serde::{Deserialize, Serialize};
+use crate::render::debug::{debug_colorRBG, Debugable};
+use crate::render::shader::{MultiUniform, ShaderUniformHandler};
+
+///The size of the `lights` uniform array in the static shader. It is passed to the shader as the
+///`MAX_LIGHTS` define.
+pub const MAX_LIGHTS : usize = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    DIRECTIONAL,
+    POINT,
+    SPOT,
+}
+
+impl LightKind {
+    pub const ALL : [LightKind; 3] = [LightKind::DIRECTIONAL, LightKind::POINT, LightKind::SPOT];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LightKind::DIRECTIONAL => "Directional",
+            LightKind::POINT => "Point",
+            LightKind::SPOT => "Spot",
+        }
+    }
+
+    ///The value of `Light.kind` in the shader.
+    fn shader_index(&self) -> i32 {
+        match self {
+            LightKind::DIRECTIONAL => 0,
+            LightKind::POINT => 1,
+            LightKind::SPOT => 2,
+        }
+    }
+}
+
+///A light on top of the global light. Point and spot lights fade out towards `range`, and spot lights
+///only light what is inside their cone. `direction` is the way the light shines, and the cone angles are
+///measured from it in degrees.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    pub kind : LightKind,
+    pub color : Vector3<f32>,
+    pub intensity : f32,
+    pub position : Vector3<f32>,
+    pub direction : Vector3<f32>,
+    pub range : f32,
+    pub inner_angle : f32,
+    pub outer_angle : f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Light {
+            kind : LightKind::POINT,
+            color : Vector3::new(1.0, 1.0, 1.0),
+            intensity : 1.0,
+            position : Vector3::new(0.0, 2.0, 0.0),
+            direction : Vector3::new(0.0, -1.0, 0.0),
+            range : 5.0,
+            inner_angle : 20.0,
+            outer_angle : 30.0,
+        }
+    }
+}
+
+impl Light {
+    ///The cosines of the inner and outer cone angles, which is what the shader compares against. The
+    ///inner angle is never allowed past the outer one.
+    pub fn cone_cosines(&self) -> (f32, f32) {
+        let outer = self.outer_angle.clamp(0.0, 90.0);
+        let inner = self.inner_angle.clamp(0.0, outer);
+        (inner.to_radians().cos(), outer.to_radians().cos())
+    }
+}
+
+///The uniforms of one element of the `lights` array.
+struct LightUniforms {
+    kind : Option<ShaderUniformHandler>,
+    color : Option<ShaderUniformHandler>,
+    position : Option<ShaderUniformHandler>,
+    direction : Option<ShaderUniformHandler>,
+    range : Option<ShaderUniformHandler>,
+    inner_cone : Option<ShaderUniformHandler>,
+    outer_cone : Option<ShaderUniformHandler>,
+}
+
+///The lights that are added on top of the global light, uploaded into the `lights` uniform array of the
+///static shader. Every light is banded on its own before they are added together.
+#[derive(Default, Serialize, Deserialize)]
+pub struct LightList {
+    lights : Vec<Light>,
+    #[serde(skip)]
+    uniforms : Vec<LightUniforms>,
+    #[serde(skip)]
+    count_uniform : Option<ShaderUniformHandler>,
+}
+
+impl LightList {
+    ///Reads a light list from a JSON file.
+    pub fn load<P>(path : P) -> Result<Self, String> where P : AsRef<Path> {
+        let file = File::open(path.as_ref()).map_err(|e| format!("Unable to open '{}': {}", path.as_ref().display(), e))?;
+        let mut list : LightList = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("Unable to read '{}': {}", path.as_ref().display(), e))?;
+        list.lights.truncate(MAX_LIGHTS);
+        Ok(list)
+    }
+
+    pub fn save<P>(&self, path : P) -> Result<(), String> where P : AsRef<Path> {
+        let file = File::create(path.as_ref()).map_err(|e| format!("Unable to write '{}': {}", path.as_ref().display(), e))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self).map_err(|e| e.to_string())
+    }
+
+    ///Adds a light if there is still room for it in the shader. Returns false if the list is full.
+    pub fn add(&mut self, light : Light) -> bool {
+        if self.lights.len() >= MAX_LIGHTS { return false }
+        self.lights.push(light);
+        self.update_to_shader();
+        true
+    }
+
+    pub fn remove(&mut self, index : usize) {
+        if index < self.lights.len() {
+            self.lights.remove(index);
+            self.update_to_shader();
+        }
+    }
+
+    fn update_to_shader(&self) {
+        if let Some(count) = &self.count_uniform { count.update_uniform(self.lights.len() as i32) }
+        for (light, uniforms) in self.lights.iter().zip(&self.uniforms) {
+            let direction = if light.direction.magnitude2() > 0.0 { light.direction.normalize() } else { Vector3::new(0.0, -1.0, 0.0) };
+            let (inner, outer) = light.cone_cosines();
+            if let Some(handle) = &uniforms.kind { handle.update_uniform(light.kind.shader_index()) }
+            if let Some(handle) = &uniforms.color { handle.update_uniform(light.color * light.intensity) }
+            if let Some(handle) = &uniforms.position { handle.update_uniform(light.position) }
+            if let Some(handle) = &uniforms.direction { handle.update_uniform(direction) }
+            if let Some(handle) = &uniforms.range { handle.update_uniform(light.range.max(0.001)) }
+            if let Some(handle) = &uniforms.inner_cone { handle.update_uniform(inner) }
+            if let Some(handle) = &uniforms.outer_cone { handle.update_uniform(outer) }
+        }
+    }
+}
+
+impl MultiUniform for LightList {
+    fn provide_handle_provider(&mut self, provider : impl Fn(&str) -> Option<ShaderUniformHandler>) {
+        self.count_uniform = provider("light_count");
+        self.uniforms = (0..MAX_LIGHTS).map(|index| {
+            let member = |name : &str| provider(&format!("lights[{}].{}", index, name));
+            LightUniforms {
+                kind : member("kind"),
+                color : member("color"),
+                position : member("position"),
+                direction : member("direction"),
+                range : member("range"),
+                inner_cone : member("inner_cone"),
+                outer_cone : member("outer_cone"),
+            }
+        }).collect();
+        self.update_to_shader();
+    }
+}
+
+impl Debugable for Light {
+    fn debug(&mut self, ui : &mut Ui, enabled : bool) -> bool {
+        ui.add_enabled_ui(enabled, |ui| {
+            Grid::new(ui.make_persistent_id("light_grid"))
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    let mut changed = false;
+
+                    ui.label("Kind:");
+                    ComboBox::from_id_source(ui.make_persistent_id("light_kind"))
+                        .selected_text(self.kind.name())
+                        .show_ui(ui, |ui| {
+                            for kind in LightKind::ALL {
+                                changed |= ui.selectable_value(&mut self.kind, kind, kind.name()).changed();
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Color:");
+                    changed |= debug_colorRBG(ui, true, &mut self.color);
+                    ui.end_row();
+
+                    ui.label("Intensity:");
+                    changed |= ui.add(DragValue::new(&mut self.intensity).speed(0.01).clamp_range(0.0..=10.0)).changed();
+                    ui.end_row();
+
+                    if self.kind != LightKind::DIRECTIONAL {
+                        ui.label("Position:");
+                        changed |= self.position.debug(ui, true);
+                        ui.end_row();
+
+                        ui.label("Range:");
+                        changed |= ui.add(DragValue::new(&mut self.range).speed(0.05).clamp_range(0.01..=100.0)).changed();
+                        ui.end_row();
+                    }
+
+                    if self.kind != LightKind::POINT {
+                        ui.label("Direction:");
+                        changed |= self.direction.debug(ui, true);
+                        ui.end_row();
+                    }
+
+                    if self.kind == LightKind::SPOT {
+                        ui.label("Cone:");
+                        ui.horizontal(|ui| {
+                            changed |= ui.add(DragValue::new(&mut self.inner_angle).speed(0.5).clamp_range(0.0..=90.0).suffix("°")).changed();
+                            changed |= ui.add(DragValue::new(&mut self.outer_angle).speed(0.5).clamp_range(0.0..=90.0).suffix("°")).changed();
+                        });
+                        ui.end_row();
+                    }
+
+                    changed
+                }).inner
+        }).inner
+    }
+}
+
+impl Debugable for LightList {
+    fn debug(&mut self, ui : &mut Ui, enabled : bool) -> bool {
+        let mut changed = false;
+        let mut removed = None;
+
+        for (index, light) in self.lights.iter_mut().enumerate() {
+            ui.collapsing(format!("{} Light {}", light.kind.name(), index + 1), |ui| {
+                changed |= light.debug(ui, enabled);
+                if ui.add_enabled(enabled, egui::Button::new("Remove")).clicked() { removed = Some(index) }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            let room = self.lights.len() < MAX_LIGHTS;
+            for kind in LightKind::ALL {
+                if ui.add_enabled(enabled && room, egui::Button::new(format!("Add {}", kind.name()))).clicked() {
+                    changed |= self.add(Light { kind, ..Light::default() });
+                }
+            }
+        });
+
+        if let Some(index) = removed {
+            self.remove(index);
+            changed = true;
+        }
+        if changed { self.update_to_shader() }
+        changed
+    }
+}
@@ -0,0 +1,329 @@
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use obj::raw::parse_obj;
+    use crate::render::mesh::*;
+
+    const CUBE : &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 0 1\nv 1 0 1\nv 1 1 1\nv 0 1 1\n\
+        f 1 4 3 2\nf 5 6 7 8\nf 1 2 6 5\nf 2 3 7 6\nf 3 4 8 7\nf 4 1 5 8\n";
+
+    fn cube() -> RawObj {
+        parse_obj(BufReader::new(CUBE.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn cube_is_flat_below_crease_angle() {
+        let mesh = MeshData::from_obj(&cube(), 60.0);
+        assert_eq!(mesh.positions.len(), 24);
+        assert_eq!(mesh.indices.len(), 36);
+        for normal in &mesh.normals {
+            let axis_aligned = [normal.x, normal.y, normal.z].iter().filter(|v| v.abs() > 0.999).count();
+            assert_eq!(axis_aligned, 1);
+        }
+    }
+
+    #[test]
+    fn cube_is_smooth_above_crease_angle() {
+        let mesh = MeshData::from_obj(&cube(), 180.0);
+        assert_eq!(mesh.positions.len(), 8);
+        let corner = mesh.positions.iter().position(|p| *p == Vector3::new(0.0, 0.0, 0.0)).unwrap();
+        let expected = -1.0 / 3.0f32.sqrt();
+        assert!((mesh.normals[corner] - Vector3::new(expected, expected, expected)).magnitude() < 0.0001);
+    }
+
+    #[test]
+    fn file_normals_are_used() {
+        let raw = parse_obj(BufReader::new("v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 1 0\nf 1//1 2//1 3//1\n".as_bytes())).unwrap();
+        let mesh = MeshData::from_obj(&raw, 60.0);
+        assert!(mesh.normals.iter().all(|n| *n == Vector3::new(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn polygons_are_split_by_material() {
+        let raw = parse_obj(BufReader::new("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nusemtl red\nf 1 2 3\nf 1 2 3\nusemtl blue\nf 1 2 3\n".as_bytes())).unwrap();
+        let groups = material_groups(&raw);
+        let sizes : Vec<(Option<&str>, usize)> = groups.iter().map(|(name, polygons)| (*name, polygons.len())).collect();
+        assert_eq!(sizes, vec![(None, 1), (Some("blue"), 1), (Some("red"), 2)]);
+    }
+
+    #[test]
+    fn bounds_follow_transform() {
+        let bounds = MeshData::from_obj(&cube(), 60.0).bounds();
+        assert_eq!(bounds, Bounds { min: Vector3::new(0.0, 0.0, 0.0), max: Vector3::new(1.0, 1.0, 1.0) });
+
+        let moved = bounds.transformed(Matrix4::from_translation(Vector3::new(1.0, 2.0, 3.0)) * Matrix4::from_scale(2.0));
+        assert_eq!(moved, Bounds { min: Vector3::new(1.0, 2.0, 3.0), max: Vector3::new(3.0, 4.0, 5.0) });
+        assert_eq!(Bounds::empty().union(&moved), moved);
+    }
+
+    #[test]
+    fn indexed_without_normals_is_flat() {
+        let positions = vec![Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0)];
+        let mesh = MeshData::from_indexed(positions, None, None, Some(vec![0, 1, 2, 0, 1, 3]), None);
+        assert_eq!(mesh.positions.len(), 6);
+        assert!(mesh.normals[..3].iter().all(|n| *n == Vector3::new(0.0, 0.0, 1.0)));
+        assert!(mesh.normals[3..].iter().all(|n| *n == Vector3::new(0.0, -1.0, 0.0)));
+    }
+}
+
+use std::collections::HashMap;
+use cgmath::{InnerSpace, Matrix4, Vector2, Vector3, Zero};
+use obj::raw::object::Polygon;
+use obj::raw::RawObj;
+use crate::render::skin::SkinWeights;
+
+///The angle (in degrees) between two faces above which generated normals are not smoothed.
+pub const DEFAULT_CREASE_ANGLE : f32 = 60.0;
+
+///Triangulated, indexed vertex data that is ready to be put into VBOs. Every vertex has a position,
+///uv and normal, so the layout always matches what the shaders expect.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions : Vec<Vector3<f32>>,
+    pub uvs : Vec<Vector2<f32>>,
+    pub normals : Vec<Vector3<f32>>,
+    pub indices : Vec<u32>,
+    ///The joints and weights of every vertex of a skinned mesh. Empty when the mesh is not skinned.
+    pub skin : Vec<SkinWeights>
+}
+
+///An axis aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub min : Vector3<f32>,
+    pub max : Vector3<f32>
+}
+
+impl Bounds {
+    ///Bounds that contain nothing, so the union with anything is that thing.
+    pub fn empty() -> Self {
+        Bounds {
+            min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY)
+        }
+    }
+
+    pub fn from_points<'a>(points : impl IntoIterator<Item = &'a Vector3<f32>>) -> Self {
+        points.into_iter().fold(Bounds::empty(), |bounds, point| bounds.with_point(*point))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn with_point(&self, point : Vector3<f32>) -> Self {
+        Bounds {
+            min: Vector3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z)),
+            max: Vector3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z))
+        }
+    }
+
+    pub fn union(&self, other : &Bounds) -> Self {
+        if other.is_empty() { return *self }
+        self.with_point(other.min).with_point(other.max)
+    }
+
+    ///The bounds of all 8 corners after they have been transformed.
+    pub fn transformed(&self, mat : Matrix4<f32>) -> Self {
+        if self.is_empty() { return *self }
+        let mut bounds = Bounds::empty();
+        for i in 0..8 {
+            let corner = Vector3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z }
+            );
+            bounds = bounds.with_point((mat * corner.extend(1.0)).truncate());
+        }
+        bounds
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) / 2.0
+    }
+
+    ///The radius of the sphere around the center that contains the whole box.
+    pub fn radius(&self) -> f32 {
+        if self.is_empty() { 0.0 } else { (self.max - self.min).magnitude() / 2.0 }
+    }
+}
+
+///A corner of a triangle as indices into the raw obj data.
+#[derive(Copy, Clone)]
+struct Corner {
+    position : usize,
+    uv : Option<usize>,
+    normal : Option<usize>
+}
+
+impl MeshData {
+    pub fn bounds(&self) -> Bounds {
+        Bounds::from_points(&self.positions)
+    }
+
+    ///Builds the mesh from every polygon of an obj.
+    #[cfg(test)]
+    pub fn from_obj(raw : &RawObj, crease_angle : f32) -> Self {
+        MeshData::from_obj_polygons(raw, raw.polygons.iter(), crease_angle)
+    }
+
+    ///Builds the mesh from a subset of the polygons of an obj. The normals from the file are used when
+    ///they exist, otherwise smooth normals are generated. Faces that meet at more than `crease_angle`
+    ///degrees do not get smoothed together, so hard edges stay flat shaded.
+    pub fn from_obj_polygons<'a>(raw : &RawObj, polygons : impl Iterator<Item = &'a Polygon>, crease_angle : f32) -> Self {
+        let triangles : Vec<[Corner; 3]> = polygons.flat_map(|polygon| triangulate(polygon)).collect();
+        let positions : Vec<Vector3<f32>> = raw.positions.iter().map(|&(x, y, z, _)| Vector3::new(x, y, z)).collect();
+        let generated = generate_normals(&positions, &triangles, crease_angle);
+
+        let mut mesh = MeshData::default();
+        let mut vertices : HashMap<(usize, Option<usize>, [u32; 3]), u32> = HashMap::new();
+
+        for (triangle, triangle_normals) in triangles.iter().zip(generated) {
+            for (corner, generated_normal) in triangle.iter().zip(triangle_normals) {
+                let normal = match corner.normal {
+                    Some(index) => {
+                        let (x, y, z) = raw.normals[index];
+                        Vector3::new(x, y, z)
+                    }
+                    None => generated_normal
+                };
+
+                let key = (corner.position, corner.uv, [normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits()]);
+                let index = *vertices.entry(key).or_insert_with(|| {
+                    let uv = corner.uv.map(|uv| {
+                        // Obj uvs start at the bottom of the image, but textures are uploaded top row first.
+                        let (u, v, _) = raw.tex_coords[uv];
+                        Vector2::new(u, 1.0 - v)
+                    }).unwrap_or_else(Vector2::zero);
+
+                    mesh.positions.push(positions[corner.position]);
+                    mesh.uvs.push(uv);
+                    mesh.normals.push(normal);
+                    mesh.positions.len() as u32 - 1
+                });
+                mesh.indices.push(index);
+            }
+        }
+
+        mesh
+    }
+
+    ///Builds the mesh from vertex data that is already indexed, like a glTF primitive. Missing uvs
+    ///are set to zero and missing normals are generated flat, since that is what glTF asks for.
+    pub fn from_indexed(positions : Vec<Vector3<f32>>, uvs : Option<Vec<Vector2<f32>>>, normals : Option<Vec<Vector3<f32>>>,
+                        indices : Option<Vec<u32>>, skin : Option<Vec<SkinWeights>>) -> Self {
+        let indices = indices.unwrap_or_else(|| (0..positions.len() as u32).collect());
+        let uvs = uvs.unwrap_or_else(|| vec![Vector2::zero(); positions.len()]);
+        let skin = skin.unwrap_or_default();
+
+        if let Some(normals) = normals {
+            return MeshData { positions, uvs, normals, indices, skin }
+        }
+
+        let triangles : Vec<[Corner; 3]> = indices.chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]].map(|i| Corner { position : i as usize, uv : Some(i as usize), normal : None }))
+            .collect();
+        let generated = generate_normals(&positions, &triangles, 0.0);
+
+        let mut mesh = MeshData::default();
+        for (triangle, triangle_normals) in triangles.iter().zip(generated) {
+            for (corner, normal) in triangle.iter().zip(triangle_normals) {
+                mesh.positions.push(positions[corner.position]);
+                mesh.uvs.push(uvs[corner.position]);
+                mesh.normals.push(normal);
+                if let Some(weights) = skin.get(corner.position) { mesh.skin.push(*weights) }
+                mesh.indices.push(mesh.indices.len() as u32);
+            }
+        }
+
+        mesh
+    }
+}
+
+///Splits the polygons of an obj by the material they use, ordered by material name. Polygons without
+///a material come first.
+pub fn material_groups(raw : &RawObj) -> Vec<(Option<&str>, Vec<&Polygon>)> {
+    let mut used = vec![false; raw.polygons.len()];
+    let mut names : Vec<&String> = raw.meshes.keys().collect();
+    names.sort();
+
+    let mut groups = Vec::new();
+    for name in names {
+        let polygons : Vec<&Polygon> = raw.meshes[name].polygons.iter()
+            .flat_map(|range| range.start..range.end)
+            .map(|index| {
+                used[index] = true;
+                &raw.polygons[index]
+            })
+            .collect();
+        // obj-rs puts the polygons that come before any `usemtl` under an empty name.
+        let material = if name.is_empty() { None } else { Some(name.as_str()) };
+        if !polygons.is_empty() { groups.push((material, polygons)) }
+    }
+
+    let rest : Vec<&Polygon> = raw.polygons.iter().zip(used).filter(|(_, used)| !used).map(|(polygon, _)| polygon).collect();
+    if !rest.is_empty() { groups.insert(0, (None, rest)) }
+
+    groups
+}
+
+///Splits a polygon into a triangle fan.
+fn triangulate(polygon : &Polygon) -> Vec<[Corner; 3]> {
+    let corners : Vec<Corner> = match polygon {
+        Polygon::P(p) => p.iter().map(|&position| Corner { position, uv : None, normal : None }).collect(),
+        Polygon::PT(p) => p.iter().map(|&(position, uv)| Corner { position, uv : Some(uv), normal : None }).collect(),
+        Polygon::PN(p) => p.iter().map(|&(position, normal)| Corner { position, uv : None, normal : Some(normal) }).collect(),
+        Polygon::PTN(p) => p.iter().map(|&(position, uv, normal)| Corner { position, uv : Some(uv), normal : Some(normal) }).collect()
+    };
+
+    (1..corners.len().saturating_sub(1))
+        .map(|i| [corners[0], corners[i], corners[i + 1]])
+        .collect()
+}
+
+///Generates an angle weighted normal for every corner of every triangle. Each corner averages the
+///normals of the faces around its position that are within `crease_angle` of its own face, weighted
+///by the angle those faces have at that position.
+fn generate_normals(positions : &[Vector3<f32>], triangles : &[[Corner; 3]], crease_angle : f32) -> Vec<[Vector3<f32>; 3]> {
+    let position = |index : usize| positions[index];
+
+    let mut face_normals = Vec::with_capacity(triangles.len());
+    let mut corner_angles = Vec::with_capacity(triangles.len());
+    let mut faces_at_position : HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+
+    for (face, triangle) in triangles.iter().enumerate() {
+        let p = [position(triangle[0].position), position(triangle[1].position), position(triangle[2].position)];
+        let normal = (p[1] - p[0]).cross(p[2] - p[0]);
+        face_normals.push(if normal.magnitude2() > 0.0 { normal.normalize() } else { Vector3::zero() });
+
+        let mut angles = [0.0; 3];
+        for i in 0..3 {
+            let a = p[(i + 1) % 3] - p[i];
+            let b = p[(i + 2) % 3] - p[i];
+            if a.magnitude2() > 0.0 && b.magnitude2() > 0.0 {
+                angles[i] = a.normalize().dot(b.normalize()).clamp(-1.0, 1.0).acos();
+            }
+            faces_at_position.entry(triangle[i].position).or_default().push((face, i));
+        }
+        corner_angles.push(angles);
+    }
+
+    let threshold = crease_angle.to_radians().cos();
+    triangles.iter().enumerate().map(|(face, triangle)| {
+        let face_normal = face_normals[face];
+        let mut normals = [face_normal; 3];
+
+        for (i, corner) in triangle.iter().enumerate() {
+            let mut sum = Vector3::zero();
+            for &(other, other_corner) in &faces_at_position[&corner.position] {
+                let other_normal = face_normals[other];
+                if other == face || face_normal.dot(other_normal) >= threshold {
+                    sum += other_normal * corner_angles[other][other_corner];
+                }
+            }
+            if sum.magnitude2() > 0.0 { normals[i] = sum.normalize() }
+        }
+
+        normals
+    }).collect()
+}
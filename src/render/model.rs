@@ -1,4 +1,5 @@
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use crate::render::buffer::{VBO, VAO};
 use glow::Context;
 use std::fs::File;
@@ -7,184 +8,252 @@ use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use cgmath::{Vector2, Vector3};
-use obj::{FromRawVertex, load_obj, Obj, ObjError, ObjResult, TexturedVertex, Vertex};
+use obj::ObjError;
+use obj::raw::{parse_mtl, parse_obj, RawObj};
+use obj::raw::material::{Material, MtlColor};
 use crate::render::shader::Shader;
 use crate::render::texture::Texture;
+use crate::render::mesh::{material_groups, Bounds, MeshData, DEFAULT_CREASE_ANGLE};
+use crate::render::gltf_model::GLTFModel;
+use crate::render::skin::AnimationClip;
 use crate::{Renderable, ShaderBuilder, Transform};
 use crate::render::{Deletable, RenderContext};
 
-pub struct OBJModel {
-    pub texture : Option<Texture>,
-    pub shader: Shader,
+///Anything that can be loaded from a model file and drawn with a shader.
+pub trait Model : Renderable + Deletable {
+    fn shader(&self) -> &Shader;
+
+    ///Draws every group of the model with another shader, like the depth shader of a shadow map.
+    unsafe fn render_with(&self, gl : &Context, shader : &Shader);
+
+    ///The bounds of the model before its own transform is applied.
+    fn bounds(&self) -> Bounds;
+
+    ///The animation clips of the model. Most formats have none.
+    fn clips(&self) -> &[AnimationClip] {
+        &[]
+    }
+
+    ///Poses the model `time` seconds into one of its clips, or in its rest pose when `clip` is `None`.
+    fn pose(&mut self, _clip : Option<usize>, _time : f32) {}
+}
+
+///Loads an OBJ or glTF model, chosen by the extension of the path.
+pub fn load_model<P>(render_context : &Rc<RenderContext>, path : P, shader: Shader, crease_angle : f32)
+    -> Result<Box<dyn Model>, String> where P : AsRef<Path> {
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    match extension.as_str() {
+        "gltf" | "glb" => Ok(Box::new(GLTFModel::from_path(render_context, path, shader)?)),
+        _ => OBJModel::load(render_context, path, shader, crease_angle)
+            .map(|model| Box::new(model) as Box<dyn Model>)
+            .map_err(|e| format!("{:?}", e))
+    }
+}
+
+///A mesh that has been uploaded to the gpu, along with the material and node transform it is drawn with.
+pub struct MeshGroup {
     pub vao : VAO,
     pub verts : VBO,
-    pub uvs: VBO,
-    norms : VBO,
-    pub transform: Transform,
+    pub uvs : VBO,
+    pub norms : VBO,
+    pub texture : Option<Rc<Texture>>,
+    pub color : Vector3<f32>,
+    pub node : Transform,
+    pub bounds : Bounds,
+}
+
+impl MeshGroup {
+    ///Uploads the mesh with position at location 0, uv at 1 and normal at 2.
+    pub fn new(gl : &Context, mesh : &MeshData) -> Self {
+        // load vertexes into vert_vbo
+        let mut vert_vbo = VBO::new(gl).unwrap();
+        vert_vbo.load_vec3s(gl, mesh.positions.clone());
+
+        // load uvs into uv_vbo
+        let mut uv_vbo = VBO::new(gl).unwrap();
+        uv_vbo.load_vec2s(gl, mesh.uvs.clone());
+
+        // load norms into norms_vbo
+        let mut norm_vbo = VBO::new(gl).unwrap();
+        norm_vbo.load_vec3s(gl, mesh.normals.clone());
+
+        // load indices from model and convert them to i32
+        let indices: Vec<i32> = mesh.indices.iter()
+            .map(|number| *number as i32)
+            .collect();
+
+        // Create VAO, add VBOs and indices to VAO
+        let mut vao = VAO::new(gl).unwrap();
+        vao.addIndexBuffer(gl, indices);
+        vao.add_vbo(gl, 0, &vert_vbo);
+        vao.add_vbo(gl, 1, &uv_vbo);
+        vao.add_vbo(gl, 2, &norm_vbo);
+
+        MeshGroup {
+            vao,
+            verts: vert_vbo,
+            uvs: uv_vbo,
+            norms: norm_vbo,
+            texture: None,
+            color: Vector3::new(1.0, 1.0, 1.0),
+            node: Transform::default(),
+            bounds: mesh.bounds(),
+        }
+    }
+
+    ///The bounds of the group after its node transform is applied.
+    pub fn node_bounds(&self) -> Bounds {
+        self.bounds.transformed(self.node.calc_mat())
+    }
+
+    ///Sends the node transform, its normal matrix and material of this group, then draws it.
+    pub unsafe fn render(&self, gl : &Context, shader : &Shader) {
+        shader.send_uniform("node", self.node.clone()).ok();
+        shader.send_uniform("node_normal", self.node.normal_matrix()).ok();
+        shader.send_uniform("base_color", self.color).ok();
+        shader.send_uniform("use_texture", self.texture.is_some() as i32).ok();
+        if let Some(texture) = &self.texture {
+            texture.bind_index(gl, 0);
+        }
+        self.vao.render(gl);
+    }
+}
+
+impl Deletable for MeshGroup {
+    unsafe fn delete(&self, gl: &Context) {
+        self.vao.delete(gl);
+        self.verts.delete(gl);
+        self.uvs.delete(gl);
+        self.norms.delete(gl);
+    }
+}
+
+pub struct OBJModel {
+    pub groups : Vec<MeshGroup>,
+    textures : Vec<Rc<Texture>>,
+    pub shader: Shader,
 }
 
 impl Renderable for OBJModel {
     unsafe fn render(&self, gl: &Context) {
-        self.texture.as_ref().unwrap().bind(gl);
-        self.shader.bind();
-        self.vao.render(gl);
+        self.render_with(gl, &self.shader);
     }
 }
 
 impl OBJModel {
     pub fn new(render_context : &Rc<RenderContext>, file_name : &str, shader: Shader)
         -> Result<OBJModel, ObjError> {
-        let gl = &render_context.gl;
         let path = Path::new("")
             .join("assets")
             .join("models")
             .join(file_name);
 
-        let input = BufReader::new(File::open(path.clone())?);
-
-        // attempt to load obj with textured vertex data
-        // if there is a load error, attempt to load obj without textured vertex data
-        let model: ObjResult<Obj<TexturedVertex, u32>> = load_obj(input);
-        if let Err(ObjError::Load(e)) = model{
-            let input = BufReader::new(File::open(path.clone())?);
-            let model: ObjResult<Obj<Vertex, u32>> = load_obj(input);
-            let obj_model: OBJModel =
-                vao_load_obj_vertex(Rc::clone(&render_context),model.unwrap(), shader);
-            Ok(obj_model)
-        } else {
-            let obj_model: OBJModel =
-                vao_load_obj_textured_vertex(Rc::clone(&render_context),model.unwrap(), shader);
-            Ok(obj_model)
+        OBJModel::from_path(render_context, path, shader)
+    }
+
+    ///Loads a model from a path instead of a file name in `assets/models`.
+    pub fn from_path<P>(render_context : &Rc<RenderContext>, path : P, shader: Shader)
+        -> Result<OBJModel, ObjError> where P : AsRef<Path> {
+        OBJModel::load(render_context, path, shader, DEFAULT_CREASE_ANGLE)
+    }
+
+    ///Loads a model from a path. The normals in the file are used if it has them, otherwise smooth
+    ///normals are generated, and faces that meet at more than `crease_angle` degrees stay flat shaded.
+    ///The model is split into one group per material of its MTL libraries, which are looked up
+    ///next to the OBJ.
+    pub fn load<P>(render_context : &Rc<RenderContext>, path : P, shader: Shader, crease_angle : f32)
+        -> Result<OBJModel, ObjError> where P : AsRef<Path> {
+        let gl = &render_context.gl;
+        let directory = path.as_ref().parent().map(Path::to_path_buf).unwrap_or_default();
+        let input = BufReader::new(File::open(path.as_ref())?);
+        let raw = parse_obj(input)?;
+        let materials = load_materials(&raw, &directory)?;
+
+        let mut model = OBJModel {
+            groups: Vec::new(),
+            textures: Vec::new(),
+            shader,
+        };
+        let mut loaded_textures : HashMap<String, Rc<Texture>> = HashMap::new();
+
+        for (material_name, polygons) in material_groups(&raw) {
+            let mesh = MeshData::from_obj_polygons(&raw, polygons.into_iter(), crease_angle);
+            let mut group = MeshGroup::new(gl, &mesh);
+
+            if let Some(material) = material_name.and_then(|name| materials.get(name)) {
+                if let Some(MtlColor::Rgb(r, g, b)) = material.diffuse {
+                    group.color = Vector3::new(r, g, b);
+                }
+
+                if let Some(map) = &material.diffuse_map {
+                    group.texture = match loaded_textures.get(&map.file) {
+                        Some(texture) => Some(Rc::clone(texture)),
+                        None => match Texture::from_path(gl, directory.join(&map.file)) {
+                            Ok(texture) => {
+                                let texture = Rc::new(texture);
+                                loaded_textures.insert(map.file.clone(), Rc::clone(&texture));
+                                model.textures.push(Rc::clone(&texture));
+                                Some(texture)
+                            }
+                            Err(error) => {
+                                eprintln!("{}", error);
+                                None
+                            }
+                        }
+                    };
+                }
+            }
+
+            model.groups.push(group);
         }
+
+        Ok(model)
     }
 }
 
-fn vao_load_obj_vertex(render_context: Rc<RenderContext>, model: Obj<Vertex, u32>, shader: Shader) -> OBJModel {
-    // load vertexes into vert_vbo
-    let gl = &render_context.gl;
-    let mut vert_vbo = VBO::new(gl).unwrap();
-    let verts: Vec<Vector3<f32>> = model.vertices.iter()
-        .map(|tv|
-            Vector3::new(
-                tv.position[0],
-                tv.position[1],
-                tv.position[2]))
-        .collect();
-    vert_vbo.load_vec3s(gl, verts);
-
-    // load uvs into uv_vbo
-    let mut uv_vbo = VBO::new(gl).unwrap();
-    let uvs: Vec<Vector2<f32>> = model.vertices.iter()
-        .map(|tv|
-            Vector2::new(
-                0.,
-                0.,
-            ))
-        .collect();
-    uv_vbo.load_vec2s(gl, uvs);
-
-    // load norms into norms_vbo
-    let mut norm_vbo = VBO::new(gl).unwrap();
-    let norms: Vec<Vector3<f32>> = model.vertices.iter()
-        .map(|tv|
-            Vector3::new(
-                tv.position[0],
-                tv.position[1],
-                tv.position[2]))
-        .collect();
-    norm_vbo.load_vec3s(gl, norms);
-
-    // load indices from model and convert them to i32
-    let indices: Vec<i32> = model.indices.iter()
-        .map(|number| *number as i32)
-        .collect();
-
-    // Create VAO, add VBOs and indices to VAO
-    let mut vao = VAO::new(gl).unwrap();
-    vao.addIndexBuffer(gl, indices);
-    vao.add_vbo(gl, 0, &vert_vbo);
-    vao.add_vbo(gl, 1, &uv_vbo);
-    vao.add_vbo(gl, 2, &norm_vbo);
-
-    let texture = Texture::new(gl, "copper_block.png");
-    let transform: Transform = Transform::default();
-
-    OBJModel {
-        texture: Some(texture),
-        shader,
-        vao,
-        verts: vert_vbo,
-        uvs: uv_vbo,
-        norms: norm_vbo,
-        transform,
+///Reads every MTL library an obj references. Libraries that can't be found are skipped, so the
+///model still loads with flat colors.
+fn load_materials(raw : &RawObj, directory : &Path) -> Result<HashMap<String, Material>, ObjError> {
+    let mut materials = HashMap::new();
+    for library in &raw.material_libraries {
+        let file = match File::open(directory.join(library)) {
+            Ok(file) => file,
+            Err(_) => {
+                eprintln!("Unable to find material library '{}'.", library);
+                continue
+            }
+        };
+        materials.extend(parse_mtl(BufReader::new(file))?.materials);
     }
+    Ok(materials)
 }
-fn vao_load_obj_textured_vertex(render_context: Rc<RenderContext>, model: Obj<TexturedVertex, u32>, shader: Shader) -> OBJModel {
-    // load vertexes into vert_vbo
-    let gl = &render_context.gl;
-    let mut vert_vbo = VBO::new(gl).unwrap();
-    let verts: Vec<Vector3<f32>> = model.vertices.iter()
-        .map(|tv|
-            Vector3::new(
-                tv.position[0],
-                tv.position[1],
-                tv.position[2]))
-        .collect();
-    vert_vbo.load_vec3s(gl, verts);
-
-    // load uvs into uv_vbo
-    let mut uv_vbo = VBO::new(gl).unwrap();
-    let uvs: Vec<Vector2<f32>> = model.vertices.iter()
-        .map(|tv|
-            Vector2::new(
-                tv.texture[0],
-                tv.texture[1],
-            ))
-        .collect();
-    uv_vbo.load_vec2s(gl, uvs);
-
-    // load norms into norms_vbo
-    let mut norm_vbo = VBO::new(gl).unwrap();
-    let norms: Vec<Vector3<f32>> = model.vertices.iter()
-        .map(|tv|
-            Vector3::new(
-                tv.position[0],
-                tv.position[1],
-                tv.position[2]))
-        .collect();
-    norm_vbo.load_vec3s(gl, norms);
-
-    // load indices from model and convert them to i32
-    let indices: Vec<i32> = model.indices.iter()
-        .map(|number| *number as i32)
-        .collect();
-
-    // Create VAO, add VBOs and indices to VAO
-    let mut vao = VAO::new(gl).unwrap();
-    vao.addIndexBuffer(gl, indices);
-    vao.add_vbo(gl, 0, &vert_vbo);
-    vao.add_vbo(gl, 1, &uv_vbo);
-    vao.add_vbo(gl, 2, &norm_vbo);
-
-    let texture = Texture::new(gl, "copper_block.png");
-    let transform: Transform = Transform::default();
-
-    OBJModel {
-        texture: Some(texture),
-        shader,
-        vao,
-        verts: vert_vbo,
-        uvs: uv_vbo,
-        norms: norm_vbo,
-        transform,
+
+impl Model for OBJModel {
+    fn shader(&self) -> &Shader {
+        &self.shader
+    }
+
+    unsafe fn render_with(&self, gl : &Context, shader : &Shader) {
+        shader.bind();
+        for group in &self.groups {
+            group.render(gl, shader);
+        }
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.groups.iter().fold(Bounds::empty(), |bounds, group| bounds.union(&group.node_bounds()))
     }
 }
 
 impl Deletable for OBJModel {
     unsafe fn delete(&self, gl: &Context) {
-        self.vao.delete(&gl);
-        self.verts.delete(&gl);
-        self.uvs.delete(&gl);
+        for group in &self.groups {
+            group.delete(gl);
+        }
+        for texture in &self.textures {
+            texture.destroy(gl);
+        }
         self.shader.delete(&gl);
     }
-}
\ No newline at end of file
+}
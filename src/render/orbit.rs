@@ -0,0 +1,225 @@
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+    use crate::render::camera::{Camera, Projection};
+    use crate::render::mesh::Bounds;
+    use crate::render::orbit::*;
+
+    #[test]
+    fn zoom_is_logarithmic() {
+        let mut orbit = OrbitController::default();
+        let start = orbit.distance();
+        orbit.zoom(1.0);
+        let once = orbit.distance();
+        orbit.zoom(1.0);
+        assert!((once / start - orbit.distance() / once).abs() < 0.0001);
+        orbit.zoom(-2.0);
+        assert!((orbit.distance() - start).abs() < 0.0001);
+    }
+
+    #[test]
+    fn pan_moves_across_the_view() {
+        let mut camera = Camera::default();
+        let mut orbit = OrbitController::default();
+        let forward = orbit.forward();
+        orbit.pan(&camera, 10.0, -5.0, 100.0);
+        assert!(orbit.target().magnitude() > 0.0);
+        assert!(orbit.target().dot(forward).abs() < 0.0001);
+        orbit.apply(&mut camera);
+    }
+
+    #[test]
+    fn frame_fits_bounds() {
+        let bounds = Bounds { min: Vector3::new(-1.0, -1.0, -1.0), max: Vector3::new(3.0, 1.0, 1.0) };
+        let mut orbit = OrbitController::default();
+
+        let mut camera = Camera::new(Projection::PERSPECTIVE);
+        orbit.frame(&mut camera, &bounds, 64, 0);
+        assert_eq!(orbit.target(), Vector3::new(1.0, 0.0, 0.0));
+        let half_fov = (camera.fov() / 2.0).to_radians();
+        assert!((orbit.distance() * half_fov.sin() - bounds.radius()).abs() < 0.0001);
+
+        let mut camera = Camera::new(Projection::DIMETRIC);
+        orbit.frame(&mut camera, &bounds, 64, 2);
+        assert!((orbit.view_height(&camera) - bounds.radius() * 2.0 * 64.0 / 60.0).abs() < 0.0001);
+        assert_eq!(camera.projection(), Projection::DIMETRIC);
+    }
+}
+
+use cgmath::{Deg, Matrix3, Vector3};
+use egui::{DragValue, Grid, Ui};
+use glutin::event::MouseButton;
+use crate::render::camera::Camera;
+use crate::render::debug::Debugable;
+use crate::render::mesh::Bounds;
+use crate::util::input::MouseState;
+
+///Moves a `Camera` around a target point with the mouse. Right drag orbits, middle drag pans and
+///scrolling zooms. Zoom is stored as the log of the distance, so every scroll step feels the same
+///no matter how close the camera is.
+pub struct OrbitController {
+    target : Vector3<f32>,
+    pitch : f32,
+    yaw : f32,
+    log_distance : f32,
+    pub rotate_speed : f32,
+    pub zoom_speed : f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        OrbitController {
+            target: Vector3::new(0.0, 0.0, 0.0),
+            pitch: 35.264,
+            yaw: 45.0,
+            log_distance: 3.0f32.ln(),
+            rotate_speed: 1.0,
+            zoom_speed: 0.1,
+        }
+    }
+}
+
+impl OrbitController {
+    ///Starts the controller with the rotation the camera already has.
+    pub fn from_camera(camera : &Camera) -> Self {
+        let mut orbit = OrbitController::default();
+        orbit.sync_rotation(camera);
+        orbit
+    }
+
+    ///Takes the rotation from the camera, so presets and changes made elsewhere are kept.
+    pub fn sync_rotation(&mut self, camera : &Camera) {
+        let rotation = camera.transform().rotation();
+        self.pitch = if rotation.x > 180.0 { rotation.x - 360.0 } else { rotation.x };
+        self.yaw = rotation.y;
+    }
+
+    pub fn target(&self) -> Vector3<f32> {
+        self.target
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.log_distance.exp()
+    }
+
+    pub fn set_distance(&mut self, distance : f32) {
+        self.log_distance = distance.max(0.001).ln();
+    }
+
+    ///Reads the mouse and moves the camera. `viewport_height` is the height of the window in pixels, it
+    ///is used to keep the target under the cursor while panning. Returns true if the camera moved.
+    pub fn update(&mut self, mouse : &MouseState, camera : &mut Camera, viewport_height : f32) -> bool {
+        let mut changed = false;
+        self.sync_rotation(camera);
+
+        mouse.on_drag(MouseButton::Right, |x, y| {
+            self.orbit(x * self.rotate_speed, y * self.rotate_speed);
+            changed = true;
+        });
+
+        mouse.on_drag(MouseButton::Middle, |x, y| {
+            self.pan(camera, x, y, viewport_height);
+            changed = true;
+        });
+
+        mouse.on_scroll(|_, y| {
+            self.zoom(y);
+            changed = true;
+        });
+
+        if changed { self.apply(camera) }
+        changed
+    }
+
+    ///Rotates around the target by degrees. The pitch stops short of straight up and down.
+    pub fn orbit(&mut self, yaw : f32, pitch : f32) {
+        self.yaw = (self.yaw + yaw).rem_euclid(360.0);
+        self.pitch = (self.pitch + pitch).clamp(-89.9, 89.9);
+    }
+
+    ///Moves the target across the view by a number of window pixels.
+    pub fn pan(&mut self, camera : &Camera, dx : f32, dy : f32, viewport_height : f32) {
+        let world_per_pixel = self.view_height(camera) / viewport_height.max(1.0);
+        let rotation = self.rotation();
+        let right = Vector3::new(rotation.x.x, rotation.y.x, rotation.z.x);
+        let up = Vector3::new(rotation.x.y, rotation.y.y, rotation.z.y);
+        self.target += (-right * dx + up * dy) * world_per_pixel;
+    }
+
+    ///Zooms in for positive amounts.
+    pub fn zoom(&mut self, amount : f32) {
+        self.log_distance -= amount * self.zoom_speed;
+    }
+
+    ///Fits the camera around the bounding sphere of `bounds`, keeping `margin` pixels free on every side
+    ///of a `pixel_density` tall image so outlines still fit.
+    pub fn frame(&mut self, camera : &mut Camera, bounds : &Bounds, pixel_density : u32, margin : u32) {
+        if bounds.is_empty() { return }
+        self.sync_rotation(camera);
+
+        let pixel_density = pixel_density.max(1) as f32;
+        let usable = (pixel_density - 2.0 * margin as f32).max(1.0);
+        let radius = bounds.radius().max(0.001) * pixel_density / usable;
+        let half_fov = (camera.fov() / 2.0).to_radians();
+
+        self.target = bounds.center();
+        if camera.projection().is_orthographic() {
+            let radius = radius / camera.aspect_ratio().min(1.0);
+            self.set_distance(radius / half_fov.tan());
+        } else {
+            // A narrow window has less room across than up and down.
+            let half_fov = half_fov.min((half_fov.tan() * camera.aspect_ratio()).atan());
+            self.set_distance(radius / half_fov.sin());
+        }
+        self.apply(camera);
+    }
+
+    ///The height of the view in world units at the target. Orthographic cameras use this as their size,
+    ///so switching projections keeps the model the same size.
+    pub fn view_height(&self, camera : &Camera) -> f32 {
+        2.0 * self.distance() * (camera.fov() / 2.0).to_radians().tan()
+    }
+
+    ///Moves the camera to where the controller says it should be. Presets stay presets unless the
+    ///controller has been rotated away from them.
+    pub fn apply(&self, camera : &mut Camera) {
+        camera.set_rot((self.pitch.rem_euclid(360.0), self.yaw, 0.0));
+        camera.set_target(self.target);
+        camera.set_pos((0.0, 0.0, -self.distance()));
+        camera.set_ortho_size(self.view_height(camera));
+    }
+
+    ///The direction the camera looks in.
+    pub fn forward(&self) -> Vector3<f32> {
+        let rotation = self.rotation();
+        Vector3::new(rotation.x.z, rotation.y.z, rotation.z.z) * -1.0
+    }
+
+    fn rotation(&self) -> Matrix3<f32> {
+        Matrix3::from_angle_x(Deg(self.pitch)) * Matrix3::from_angle_y(Deg(self.yaw))
+    }
+}
+
+impl Debugable for OrbitController {
+    fn debug(&mut self, ui: &mut Ui, enabled: bool) -> bool {
+        ui.add_enabled_ui(enabled, |ui| {
+            Grid::new("orbit_grid")
+                .num_columns(2)
+                .spacing([20.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Target:");
+                    let b1 = self.target.debug(ui, true);
+                    ui.end_row();
+
+                    ui.label("Distance:");
+                    let mut distance = self.distance();
+                    let b2 = ui.add(DragValue::new(&mut distance).speed(0.05).clamp_range(0.01..=150.0)).changed();
+                    if b2 { self.set_distance(distance) }
+                    ui.end_row();
+
+                    b1 || b2
+                }).inner
+        }).inner
+    }
+}
@@ -0,0 +1,144 @@
+use std::rc::Rc;
+use cgmath::{Vector3, Vector4};
+use egui::{DragValue, Ui};
+use glow::*;
+use crate::render::debug::{debug_colorRBG, Debugable};
+use crate::render::post::{draw_screen_triangle, PostProcess, PostProcessInput};
+use crate::render::shader::{Shader, ShaderBuilder, ShaderResult, UniformValue};
+use crate::render::{Deletable, RenderContext};
+
+const MAX_THICKNESS : u32 = 8;
+
+///Where the outline is drawn. `OUTER` draws on the background pixels around the silhouette, `INNER`
+///draws on the edge pixels of the model itself, so the sprite does not grow.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutlineMode {
+    OUTER,
+    INNER
+}
+
+///A post process pass that draws a pixel perfect outline on the `Downsize` FBO. Silhouettes are found
+///with the depth attachment, creases are found where the normals in the normal attachment differ by
+///more than `crease_angle`.
+pub struct OutlinePass {
+    color : Vector3<f32>,
+    thickness : u32,
+    mode : OutlineMode,
+    creases : bool,
+    crease_angle : f32,
+    enabled : bool,
+    shader : Shader
+}
+
+impl OutlinePass {
+    pub fn new(render_context : &Rc<RenderContext>) -> ShaderResult<Self> {
+        let shader = ShaderBuilder::new()
+            .with_vert_shader("post_vert.glsl")
+            .with_frag_shader("outline_frag.glsl")
+            .build(render_context)?;
+        shader.send_uniform("screen_texture", UniformValue::SAMPLER(0))?;
+        shader.send_uniform("depth_texture", UniformValue::SAMPLER(1))?;
+        shader.send_uniform("normal_texture", UniformValue::SAMPLER(2))?;
+
+        let pass = OutlinePass {
+            color : Vector3::new(0.0, 0.0, 0.0),
+            thickness : 1,
+            mode : OutlineMode::OUTER,
+            creases : false,
+            crease_angle : 60.0,
+            enabled : false,
+            shader
+        };
+        pass.update_uniforms();
+        Ok(pass)
+    }
+
+    pub fn set_color<T>(&mut self, color : T) where T : Into<Vector3<f32>> {
+        self.color = color.into();
+        self.update_uniforms();
+    }
+
+    ///Sets the thickness in low resolution pixels.
+    pub fn set_thickness(&mut self, thickness : u32) {
+        self.thickness = thickness.clamp(1, MAX_THICKNESS);
+        self.update_uniforms();
+    }
+
+    pub fn set_mode(&mut self, mode : OutlineMode) {
+        self.mode = mode;
+        self.update_uniforms();
+    }
+
+    ///Turns crease detection on or off. `angle` is in degrees, normals that differ by more than this
+    ///get an outline between them.
+    pub fn set_creases(&mut self, creases : bool, angle : f32) {
+        self.creases = creases;
+        self.crease_angle = angle;
+        self.update_uniforms();
+    }
+
+    pub fn set_enabled(&mut self, enabled : bool) {
+        self.enabled = enabled;
+    }
+
+    fn update_uniforms(&self) {
+        self.shader.send_uniform("outline_color", Vector4::new(self.color.x, self.color.y, self.color.z, 1.0)).ok();
+        self.shader.send_uniform("thickness", self.thickness as i32).ok();
+        self.shader.send_uniform("outline_mode", match self.mode { OutlineMode::OUTER => 0, OutlineMode::INNER => 1 }).ok();
+        self.shader.send_uniform("use_creases", self.creases as i32).ok();
+        self.shader.send_uniform("crease_threshold", self.crease_angle.to_radians().cos()).ok();
+    }
+
+    pub fn delete(&self, gl : &Context) {
+        unsafe { self.shader.delete(gl) }
+    }
+}
+
+impl PostProcess for OutlinePass {
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    unsafe fn apply(&self, gl : &Context, input : &PostProcessInput) {
+        self.shader.bind();
+        gl.active_texture(TEXTURE1);
+        gl.bind_texture(TEXTURE_2D, Some(input.depth));
+        gl.active_texture(TEXTURE2);
+        gl.bind_texture(TEXTURE_2D, Some(input.normal));
+
+        draw_screen_triangle(gl);
+
+        gl.bind_texture(TEXTURE_2D, None);
+        gl.active_texture(TEXTURE1);
+        gl.bind_texture(TEXTURE_2D, None);
+        gl.active_texture(TEXTURE0);
+    }
+}
+
+impl Debugable for OutlinePass {
+    fn debug(&mut self, ui: &mut Ui, enabled: bool) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            changed |= ui.add_enabled(enabled, egui::Checkbox::new(&mut self.enabled, "Outline")).changed();
+            changed |= debug_colorRBG(ui, enabled, &mut self.color);
+        });
+
+        ui.add_enabled_ui(enabled && self.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Outline Thickness:");
+                changed |= ui.add(DragValue::new(&mut self.thickness).clamp_range(1..=MAX_THICKNESS)).changed();
+                changed |= ui.radio_value(&mut self.mode, OutlineMode::OUTER, "Outer").changed();
+                changed |= ui.radio_value(&mut self.mode, OutlineMode::INNER, "Inner").changed();
+            });
+
+            ui.horizontal(|ui| {
+                changed |= ui.checkbox(&mut self.creases, "Creases").changed();
+                changed |= ui.add_enabled(self.creases, DragValue::new(&mut self.crease_angle).speed(0.5).clamp_range(1.0..=180.0).suffix("°")).changed();
+            });
+        });
+
+        if changed { self.update_uniforms() }
+        changed
+    }
+}
@@ -0,0 +1,362 @@
+#[cfg(test)]
+mod tests {
+    use crate::render::palette::*;
+
+    #[test]
+    fn parse_hex_palette() {
+        let palette = Palette::parse_hex("#000000\nff0000\n\n; comment\n00FF00").unwrap();
+        assert_eq!(palette.colors(), &[[0, 0, 0], [255, 0, 0], [0, 255, 0]])
+    }
+
+    #[test]
+    fn parse_gimp_palette() {
+        let data = "GIMP Palette\nName: Test\nColumns: 2\n#\n  0   0   0\tBlack\n255 255 255\tWhite\n";
+        let palette = Palette::parse_gpl(data).unwrap();
+        assert_eq!(palette.colors(), &[[0, 0, 0], [255, 255, 255]])
+    }
+
+    #[test]
+    fn parse_jasc_palette() {
+        let data = "JASC-PAL\r\n0100\r\n2\r\n255 0 0\r\n0 0 255\r\n";
+        let palette = Palette::parse_pal(data).unwrap();
+        assert_eq!(palette.colors(), &[[255, 0, 0], [0, 0, 255]])
+    }
+
+    #[test]
+    fn jasc_palette_count_mismatch() {
+        assert!(Palette::parse_pal("JASC-PAL\n0100\n3\n255 0 0\n").is_err())
+    }
+
+    #[test]
+    fn nearest_color_is_perceptual() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255], [255, 0, 0]]);
+        assert_eq!(palette.nearest([250, 10, 10]), 2);
+        assert_eq!(palette.nearest([40, 40, 40]), 0);
+        assert_eq!(palette.nearest([200, 200, 200]), 1);
+    }
+
+    #[test]
+    fn quantize_keeps_transparent_pixels() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255]]);
+        let mut image = RgbaImage::from_raw(2, 1, vec![10, 10, 10, 0, 230, 230, 230, 255]).unwrap();
+        palette.quantize(&mut image);
+        assert_eq!(image.as_raw(), &vec![10, 10, 10, 0, 255, 255, 255, 255]);
+    }
+}
+
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use egui::{Color32, ComboBox, Ui, Vec2};
+use glow::*;
+use image::RgbaImage;
+use crate::render::debug::Debugable;
+use crate::render::dither::{ordered_spread, Dither};
+use crate::render::post::{draw_screen_triangle, PostProcess, PostProcessInput};
+use crate::render::shader::{Shader, ShaderBuilder, ShaderResult, UniformValue};
+use crate::render::{Deletable, RenderContext};
+use crate::util::color::{distance_squared, srgb_bytes_to_oklab};
+
+const PALETTE_DIRECTORY : &str = "assets/palettes";
+
+///A fixed set of sRGB colors that an image can be mapped onto. The OKLab value of every color is kept
+///around so that finding the nearest color does not have to convert the palette every time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors : Vec<[u8; 3]>,
+    lab : Vec<[f32; 3]>
+}
+
+impl Palette {
+    pub fn new(colors : Vec<[u8; 3]>) -> Self {
+        let lab = colors.iter().map(|color| srgb_bytes_to_oklab(*color)).collect();
+        Palette { colors, lab }
+    }
+
+    ///Loads a palette from `assets/palettes`.
+    pub fn from_file(file_name : &str) -> Result<Self, String> {
+        Palette::load(Path::new(PALETTE_DIRECTORY).join(file_name))
+    }
+
+    ///Loads a palette, the format is picked from the extension. `.hex` (one RRGGBB per line), `.gpl`
+    ///(GIMP) and `.pal` (JASC) are supported.
+    pub fn load<P>(path : P) -> Result<Self, String> where P : AsRef<Path> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path).map_err(|e| format!("Unable to read palette '{}': {}", path.display(), e))?;
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+
+        match extension.as_str() {
+            "hex" => Palette::parse_hex(&data),
+            "gpl" => Palette::parse_gpl(&data),
+            "pal" => Palette::parse_pal(&data),
+            _ => Err(format!("'{}' is not a supported palette format.", path.display()))
+        }
+    }
+
+    pub fn parse_hex(data : &str) -> Result<Self, String> {
+        let mut colors = Vec::new();
+        for line in data.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with(';') { continue }
+            let hex = line.trim_start_matches('#');
+            let value = u32::from_str_radix(hex, 16).map_err(|_| format!("'{}' is not a hex color.", line))?;
+            if hex.len() != 6 { return Err(format!("'{}' is not a hex color.", line)) }
+            colors.push([(value >> 16) as u8, (value >> 8) as u8, value as u8]);
+        }
+        Palette::non_empty(colors)
+    }
+
+    pub fn parse_gpl(data : &str) -> Result<Self, String> {
+        let mut lines = data.lines();
+        if lines.next().map(|line| line.trim()) != Some("GIMP Palette") {
+            return Err("GIMP palettes should start with 'GIMP Palette'.".to_owned())
+        }
+
+        let mut colors = Vec::new();
+        for line in lines.map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") { continue }
+            colors.push(parse_rgb(line)?);
+        }
+        Palette::non_empty(colors)
+    }
+
+    pub fn parse_pal(data : &str) -> Result<Self, String> {
+        let mut lines = data.lines().map(|line| line.trim()).filter(|line| !line.is_empty());
+        if lines.next() != Some("JASC-PAL") {
+            return Err("JASC palettes should start with 'JASC-PAL'.".to_owned())
+        }
+        lines.next();
+        let count : usize = lines.next()
+            .and_then(|line| line.parse().ok())
+            .ok_or_else(|| "JASC palette is missing the color count.".to_owned())?;
+
+        let colors = lines.map(parse_rgb).collect::<Result<Vec<_>, _>>()?;
+        if colors.len() != count {
+            return Err(format!("JASC palette says it has {} colors, but {} were found.", count, colors.len()))
+        }
+        Palette::non_empty(colors)
+    }
+
+    fn non_empty(colors : Vec<[u8; 3]>) -> Result<Self, String> {
+        if colors.is_empty() {
+            Err("The palette has no colors.".to_owned())
+        } else {
+            Ok(Palette::new(colors))
+        }
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    ///Returns the index of the color that is perceptually closest to `color`.
+    pub fn nearest(&self, color : [u8; 3]) -> usize {
+        self.nearest_lab(srgb_bytes_to_oklab(color))
+    }
+
+    pub fn nearest_lab(&self, lab : [f32; 3]) -> usize {
+        let mut best = 0;
+        let mut best_distance = f32::MAX;
+        for (i, palette_lab) in self.lab.iter().enumerate() {
+            let distance = distance_squared(lab, *palette_lab);
+            if distance < best_distance {
+                best = i;
+                best_distance = distance;
+            }
+        }
+        best
+    }
+
+    ///Maps every pixel of the image to the nearest color in the palette. Fully transparent pixels are
+    ///left alone so the background stays transparent.
+    pub fn quantize(&self, image : &mut RgbaImage) {
+        for pixel in image.pixels_mut() {
+            if pixel[3] == 0 { continue }
+            let color = self.colors[self.nearest([pixel[0], pixel[1], pixel[2]])];
+            pixel[0] = color[0];
+            pixel[1] = color[1];
+            pixel[2] = color[2];
+        }
+    }
+}
+
+fn parse_rgb(line : &str) -> Result<[u8; 3], String> {
+    let mut parts = line.split_whitespace().map(|part| part.parse::<u8>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Ok([r, g, b]),
+        _ => Err(format!("'{}' is not a valid color.", line))
+    }
+}
+
+///Lists the palette files in `assets/palettes`.
+pub fn available_palettes() -> Vec<String> {
+    let mut palettes : Vec<String> = fs::read_dir(PALETTE_DIRECTORY)
+        .map(|dir| dir.filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("hex") | Some("gpl") | Some("pal")))
+            .filter_map(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .collect())
+        .unwrap_or_default();
+    palettes.sort();
+    palettes
+}
+
+///The GPU side of the palette. This is a post process stage that maps every pixel of the `Downsize`
+///FBO to the nearest palette color. The palette is uploaded as a `n x 1` sRGB texture.
+pub struct PalettePass {
+    palette : Palette,
+    palette_name : String,
+    available : Vec<String>,
+    dither : Dither,
+    shader : Shader,
+    texture : NativeTexture,
+    render_context : Rc<RenderContext>,
+    enabled : bool
+}
+
+impl PalettePass {
+    ///Loads `palette_name` from `assets/palettes`. Fails if the palette can not be loaded or the shader
+    ///does not build.
+    pub fn new(render_context : &Rc<RenderContext>, palette_name : &str) -> Result<Self, String> {
+        let palette = Palette::from_file(palette_name)?;
+
+        let shader = Self::build_shader(render_context).map_err(|e| e.to_string())?;
+
+        let texture = unsafe {
+            let gl = &render_context.gl;
+            let texture = gl.create_texture().expect("Could not create palette texture.");
+            gl.bind_texture(TEXTURE_2D, Some(texture));
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
+            gl.bind_texture(TEXTURE_2D, None);
+            texture
+        };
+
+        let pass = PalettePass {
+            palette,
+            palette_name : palette_name.to_owned(),
+            available : available_palettes(),
+            dither : Dither::default(),
+            shader,
+            texture,
+            render_context : Rc::clone(render_context),
+            enabled : false
+        };
+        pass.upload_palette();
+        pass.upload_dither();
+        Ok(pass)
+    }
+
+    fn build_shader(render_context : &Rc<RenderContext>) -> ShaderResult<Shader> {
+        let shader = ShaderBuilder::new()
+            .with_vert_shader("post_vert.glsl")
+            .with_frag_shader("palette_frag.glsl")
+            .build(render_context)?;
+        shader.send_uniform("screen_texture", UniformValue::SAMPLER(0))?;
+        shader.send_uniform("palette_texture", UniformValue::SAMPLER(1))?;
+        Ok(shader)
+    }
+
+    pub fn set_palette(&mut self, palette : Palette) {
+        self.palette = palette;
+        self.upload_palette();
+    }
+
+    pub fn dither(&self) -> Dither {
+        self.dither
+    }
+
+    pub fn set_dither(&mut self, dither : Dither) {
+        self.dither = dither;
+        self.upload_dither();
+    }
+
+    fn upload_palette(&self) {
+        let data : Vec<u8> = self.palette.colors().iter()
+            .flat_map(|color| [color[0], color[1], color[2], 255])
+            .collect();
+
+        unsafe {
+            let gl = &self.render_context.gl;
+            gl.bind_texture(TEXTURE_2D, Some(self.texture));
+            gl.tex_image_2d(TEXTURE_2D, 0, SRGB8_ALPHA8 as i32, self.palette.len() as i32, 1, 0, RGBA, UNSIGNED_BYTE, Some(&data));
+            gl.bind_texture(TEXTURE_2D, None);
+        }
+        self.shader.send_uniform("palette_size", self.palette.len() as i32).ok();
+        self.upload_dither();
+    }
+
+    ///Only the ordered modes can run in the shader, error diffusion shows up undithered in the preview.
+    fn upload_dither(&self) {
+        self.shader.send_uniform("dither_size", self.dither.mode.bayer_size() as i32).ok();
+        self.shader.send_uniform("dither_spread", ordered_spread(self.dither.strength, self.palette.len())).ok();
+    }
+
+    pub fn delete(&self, gl : &Context) {
+        unsafe {
+            gl.delete_texture(self.texture);
+            self.shader.delete(gl);
+        }
+    }
+}
+
+impl PostProcess for PalettePass {
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    unsafe fn apply(&self, gl : &Context, _input : &PostProcessInput) {
+        self.shader.bind();
+        gl.active_texture(TEXTURE1);
+        gl.bind_texture(TEXTURE_2D, Some(self.texture));
+        draw_screen_triangle(gl);
+        gl.bind_texture(TEXTURE_2D, None);
+        gl.active_texture(TEXTURE0);
+    }
+}
+
+impl Debugable for PalettePass {
+    fn debug(&mut self, ui: &mut Ui, enabled: bool) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            changed |= ui.add_enabled(enabled, egui::Checkbox::new(&mut self.enabled, "Use Palette")).changed();
+
+            let mut selected = self.palette_name.clone();
+            ComboBox::from_id_source("palette_combo")
+                .selected_text(selected.as_str())
+                .show_ui(ui, |ui| {
+                    for name in &self.available {
+                        ui.selectable_value(&mut selected, name.clone(), name.as_str());
+                    }
+                });
+
+            if selected != self.palette_name {
+                match Palette::from_file(&selected) {
+                    Ok(palette) => {
+                        self.set_palette(palette);
+                        self.palette_name = selected;
+                        changed = true;
+                    }
+                    Err(e) => eprintln!("{}", e)
+                }
+            }
+        });
+
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing = Vec2::new(1.0, 1.0);
+            for color in self.palette.colors() {
+                egui::widgets::color_picker::show_color(ui, Color32::from_rgb(color[0], color[1], color[2]), Vec2::new(12.0, 12.0));
+            }
+        });
+
+        changed
+    }
+}
@@ -0,0 +1,25 @@
+//Post processing passes that run on the low resolution FBO of `Downsize`, after the scene has been
+//rendered but before it gets blitted to the screen or read back.
+
+use glow::*;
+
+///The textures of the `Downsize` FBO that a pass can sample besides the color texture, which is bound
+///to texture unit 0 before `apply` is called.
+pub struct PostProcessInput {
+    pub depth : NativeTexture,
+    pub normal : NativeTexture
+}
+
+pub trait PostProcess {
+    fn is_enabled(&self) -> bool;
+
+    ///Draws the pass into the currently bound framebuffer. The viewport is already set to the low
+    ///resolution size, and an empty VAO is bound so `draw_screen_triangle` can be used.
+    unsafe fn apply(&self, gl : &Context, input : &PostProcessInput);
+}
+
+///Draws a triangle that covers the whole viewport. The vertices are generated in `post_vert.glsl`
+///from `gl_VertexID`, so no buffers are needed.
+pub unsafe fn draw_screen_triangle(gl : &Context) {
+    gl.draw_arrays(TRIANGLES, 0, 3);
+}
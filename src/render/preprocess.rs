@@ -0,0 +1,162 @@
+//The preprocessor that runs on every shader file before it is compiled. It pastes in `#include "file.glsl"`
+//files and the defines of the `ShaderBuilder`, and keeps track of where every line came from so compile
+//errors point at the file that has the mistake.
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use crate::render::preprocess::*;
+
+    fn files(files : &[(&str, &str)]) -> HashMap<String, String> {
+        files.iter().map(|(name, source)| (name.to_string(), source.to_string())).collect()
+    }
+
+    fn run(files : &HashMap<String, String>, file_name : &str, defines : &[(String, String)]) -> Result<Preprocessed, String> {
+        preprocess(file_name, defines, |name| files.get(name).cloned().ok_or_else(|| format!("No file '{}'.", name)))
+    }
+
+    #[test]
+    fn includes_are_pasted_in_once() {
+        let files = files(&[
+            ("main.glsl", "#version 410\n#include \"common.glsl\"\n#include \"lib/light.glsl\"\nvoid main() {}"),
+            ("common.glsl", "float a;"),
+            ("lib/light.glsl", "#include \"common.glsl\"\nfloat b;"),
+        ]);
+        let result = run(&files, "main.glsl", &[]).unwrap();
+        assert_eq!(result.files, vec!["main.glsl", "common.glsl", "lib/light.glsl"]);
+        assert_eq!(result.source.matches("float a;").count(), 1);
+        assert!(result.source.starts_with("#version 410\n"));
+        assert!(result.source.find("float a;").unwrap() < result.source.find("float b;").unwrap());
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let files = files(&[
+            ("a.glsl", "#include \"b.glsl\""),
+            ("b.glsl", "#include \"a.glsl\""),
+        ]);
+        let error = run(&files, "a.glsl", &[]).unwrap_err();
+        assert!(error.contains("a.glsl -> b.glsl -> a.glsl"), "{}", error);
+    }
+
+    #[test]
+    fn defines_come_after_the_version() {
+        let files = files(&[("main.glsl", "#version 410\nuniform float x[MAX_LIGHTS];")]);
+        let result = run(&files, "main.glsl", &[("MAX_LIGHTS".to_owned(), "8".to_owned())]).unwrap();
+        let lines : Vec<&str> = result.source.lines().collect();
+        assert_eq!(lines[..3], ["#version 410", "#define MAX_LIGHTS 8", "#line 2 0"]);
+    }
+
+    #[test]
+    fn errors_point_at_the_original_file() {
+        let files = vec!["main.glsl".to_owned(), "lib/light.glsl".to_owned()];
+        assert_eq!(map_log("0:12(5): error: x undeclared", &files), "main.glsl:12(5): error: x undeclared");
+        assert_eq!(map_log("1(3) : error C0000: syntax error", &files), "lib/light.glsl(3) : error C0000: syntax error");
+        assert_eq!(map_log("ERROR: 1:7: 'y' : undeclared identifier", &files), "ERROR: lib/light.glsl:7: 'y' : undeclared identifier");
+        assert_eq!(map_log("5:1(1): error: out of range", &files), "5:1(1): error: out of range");
+    }
+}
+
+///A shader file with its includes and defines pasted in, ready to compile.
+#[derive(Debug)]
+pub struct Preprocessed {
+    pub source : String,
+    ///Every file that went into the source, in the order they were first included. The index of a file
+    ///is the source string number of its lines in compile errors.
+    pub files : Vec<String>,
+}
+
+///Pastes the `#include "file.glsl"` lines of `file_name` in, reading every file with `read`. Include
+///paths are relative to `assets/shaders`. Every file is only pasted in the first time it is included,
+///so files do not need their own include guards, but a file that ends up including itself is an error.
+///The defines go right after the `#version` line.
+pub fn preprocess(file_name : &str, defines : &[(String, String)], read : impl Fn(&str) -> Result<String, String>) -> Result<Preprocessed, String> {
+    let mut preprocessor = Preprocessor { defines, read, files : Vec::new(), stack : Vec::new(), source : String::new() };
+    preprocessor.include(file_name)?;
+    Ok(Preprocessed { source : preprocessor.source, files : preprocessor.files })
+}
+
+struct Preprocessor<'a, R> {
+    defines : &'a [(String, String)],
+    read : R,
+    files : Vec<String>,
+    stack : Vec<String>,
+    source : String,
+}
+
+impl<'a, R> Preprocessor<'a, R> where R : Fn(&str) -> Result<String, String> {
+    fn include(&mut self, file_name : &str) -> Result<(), String> {
+        if self.stack.iter().any(|file| file == file_name) {
+            let cycle : Vec<&str> = self.stack.iter().map(String::as_str).chain([file_name]).collect();
+            return Err(format!("Include cycle: {}", cycle.join(" -> ")))
+        }
+        if self.files.iter().any(|file| file == file_name) { return Ok(()) }
+
+        let data = (self.read)(file_name)?;
+        let index = self.files.len();
+        self.files.push(file_name.to_owned());
+        self.stack.push(file_name.to_owned());
+
+        // Nothing but comments can come before the version, so the defines go right after it.
+        let mut skip = 0;
+        if index == 0 {
+            if let Some(version) = data.lines().position(|line| line.trim_start().starts_with("#version")) {
+                for line in data.lines().take(version + 1) {
+                    self.push_line(line);
+                }
+                skip = version + 1;
+            }
+            self.push_defines();
+        }
+        self.push_line(&format!("#line {} {}", skip + 1, index));
+
+        for (number, line) in data.lines().enumerate().skip(skip) {
+            match parse_include(line.trim()) {
+                Some(included) => {
+                    let included = included.map_err(|e| format!("{}:{}: {}", file_name, number + 1, e))?;
+                    self.include(&included)?;
+                    self.push_line(&format!("#line {} {}", number + 2, index));
+                }
+                None => self.push_line(line)
+            }
+        }
+
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn push_defines(&mut self) {
+        for (name, value) in self.defines {
+            self.source.push_str(&format!("#define {} {}\n", name, value));
+        }
+    }
+
+    fn push_line(&mut self, line : &str) {
+        self.source.push_str(line);
+        self.source.push('\n');
+    }
+}
+
+///The path of an `#include "file.glsl"` line, or `None` if the line is not an include.
+fn parse_include(line : &str) -> Option<Result<String, String>> {
+    let rest = line.strip_prefix("#")?.trim_start().strip_prefix("include")?.trim();
+    let path = rest.strip_prefix('"').and_then(|rest| rest.strip_suffix('"'));
+    Some(match path {
+        Some(path) if !path.is_empty() => Ok(path.trim_start_matches("./").to_owned()),
+        _ => Err(format!("Expected #include \"file.glsl\", found '{}'.", line))
+    })
+}
+
+///Replaces the source string numbers at the start of the lines of a compile log with the files they stand
+///for. Drivers write locations as `0:12`, `0(12)` or `ERROR: 0:12`.
+pub fn map_log(log : &str, files : &[String]) -> String {
+    log.lines().map(|line| {
+        let prefix = ["ERROR: ", "WARNING: "].iter().find(|prefix| line.starts_with(*prefix)).map_or(0, |prefix| prefix.len());
+        let digits = line[prefix..].chars().take_while(|c| c.is_ascii_digit()).count();
+        let after = line[prefix + digits..].chars().next();
+        match (line[prefix..prefix + digits].parse::<usize>().ok().and_then(|index| files.get(index)), after) {
+            (Some(file), Some(':')) | (Some(file), Some('(')) => format!("{}{}{}", &line[..prefix], file, &line[prefix + digits..]),
+            _ => line.to_owned()
+        }
+    }).collect::<Vec<_>>().join("\n")
+}
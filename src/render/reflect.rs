@@ -0,0 +1,317 @@
+//Reads the declarations of a shader stage with the glsl crate, so the uniforms and the interface between
+//stages can be checked before anything is handed to the driver.
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use glsl::syntax::TypeSpecifierNonArray;
+    use cgmath::{Matrix4, SquareMatrix, Vector3};
+    use crate::render::lights::MAX_LIGHTS;
+    use crate::render::preprocess::preprocess;
+    use crate::render::reflect::*;
+    use crate::render::shader::UniformValue;
+
+    const VERTEX : &str = "#version 410
+layout (location = 0) in vec3 pos;
+layout (location = 2) in vec3 norm;
+out vec3 normal;
+out vec2 uv;
+uniform mat4 transform;
+void main() { gl_Position = transform * vec4(pos, 1.0); normal = norm; uv = pos.xy; }";
+
+    const FRAGMENT : &str = "#version 410
+#define COUNT 4
+struct Light { int kind; vec3 color; };
+uniform Light lights[COUNT];
+uniform sampler2D our_texture;
+in vec3 normal;
+in vec2 uv;
+out vec4 frag_color;
+void main() { frag_color = texture(our_texture, uv) * vec4(normal * lights[0].color, 1.0); }";
+
+    #[test]
+    fn finds_uniforms_and_inputs() {
+        let vertex = StageInterface::parse(VERTEX).unwrap();
+        assert_eq!(vertex.inputs.iter().map(|input| (input.name.as_str(), input.location)).collect::<Vec<_>>(),
+                   vec![("pos", Some(0)), ("norm", Some(2))]);
+        assert_eq!(vertex.outputs.len(), 2);
+        assert_eq!(vertex.uniforms[0].ty, TypeSpecifierNonArray::Mat4);
+
+        let fragment = StageInterface::parse(FRAGMENT).unwrap();
+        let lights = fragment.uniform("lights").unwrap();
+        assert!(lights.array);
+        assert_eq!(fragment.uniform("lights[3].kind").unwrap().ty, TypeSpecifierNonArray::Int);
+        assert_eq!(fragment.uniform("lights[0].color").unwrap().ty, TypeSpecifierNonArray::Vec3);
+        assert!(fragment.uniform("lights[0].range").is_none());
+        assert_eq!(type_name(&fragment.uniform("our_texture").unwrap().ty), "sampler2D");
+    }
+
+    #[test]
+    fn reports_mismatched_varyings() {
+        let vertex = StageInterface::parse(VERTEX).unwrap();
+        let fragment = StageInterface::parse(FRAGMENT).unwrap();
+        assert!(check_varyings(("vert.glsl", &vertex), ("frag.glsl", &fragment)).is_ok());
+
+        let missing = StageInterface::parse(VERTEX.replace("out vec2 uv;", "").replace("uv = pos.xy;", "")).unwrap();
+        let error = check_varyings(("vert.glsl", &missing), ("frag.glsl", &fragment)).unwrap_err();
+        assert!(error.contains("'uv'"), "{}", error);
+
+        let wrong_type = StageInterface::parse(VERTEX.replace("out vec2 uv;", "out vec3 uv;").replace("pos.xy", "pos")).unwrap();
+        let error = check_varyings(("vert.glsl", &wrong_type), ("frag.glsl", &fragment)).unwrap_err();
+        assert!(error.contains("vec3") && error.contains("vec2"), "{}", error);
+    }
+
+    #[test]
+    fn matches_varyings_by_location() {
+        let vertex = StageInterface::parse("#version 410
+layout (location = 0) out vec3 normal;
+layout (location = 1) out vec2 uv;
+void main() { normal = vec3(0.0); uv = vec2(0.0); }").unwrap();
+        let renamed = StageInterface::parse("#version 410
+layout (location = 1) in vec2 texture_coords;
+layout (location = 0) in vec3 surface_normal;
+out vec4 frag_color;
+void main() { frag_color = vec4(surface_normal, texture_coords.x); }").unwrap();
+        assert!(check_varyings(("vert.glsl", &vertex), ("frag.glsl", &renamed)).is_ok());
+
+        let swapped = StageInterface::parse("#version 410
+layout (location = 1) in vec3 normal;
+layout (location = 0) in vec2 uv;
+out vec4 frag_color;
+void main() { frag_color = vec4(normal, uv.x); }").unwrap();
+        let error = check_varyings(("vert.glsl", &vertex), ("frag.glsl", &swapped)).unwrap_err();
+        assert!(error.contains("vec3") && error.contains("vec2"), "{}", error);
+
+        let missing = StageInterface::parse("#version 410
+layout (location = 3) in vec2 tint;
+out vec4 frag_color;
+void main() { frag_color = vec4(tint, 0.0, 1.0); }").unwrap();
+        let error = check_varyings(("vert.glsl", &vertex), ("frag.glsl", &missing)).unwrap_err();
+        assert!(error.contains("location 3"), "{}", error);
+    }
+
+    #[test]
+    fn checks_uniform_values() {
+        assert!(accepts(&TypeSpecifierNonArray::Mat4, &UniformValue::MAT4F(Matrix4::identity())));
+        assert!(accepts(&TypeSpecifierNonArray::Sampler2D, &UniformValue::INT(1)));
+        assert!(accepts(&TypeSpecifierNonArray::Bool, &UniformValue::INT(1)));
+        assert!(!accepts(&TypeSpecifierNonArray::Vec2, &UniformValue::VEC3F(Vector3::new(0.0, 0.0, 0.0))));
+        assert!(!accepts(&TypeSpecifierNonArray::Float, &UniformValue::INT(1)));
+        assert!(accepts(&TypeSpecifierNonArray::Sampler2D, &UniformValue::SAMPLER(1)));
+        assert!(!accepts(&TypeSpecifierNonArray::Int, &UniformValue::SAMPLER(1)));
+        assert!(accepts(&TypeSpecifierNonArray::Vec3, &UniformValue::VEC3F_ARRAY(vec![Vector3::new(0.0, 0.0, 0.0)])));
+    }
+
+    #[test]
+    fn every_shader_parses() {
+        let defines = [("MAX_LIGHTS".to_owned(), MAX_LIGHTS.to_string())];
+        let read = |name : &str| fs::read_to_string(format!("assets/shaders/{}", name)).map_err(|e| e.to_string());
+        let parse = |name : &str| StageInterface::parse(preprocess(name, &defines, read).unwrap().source)
+            .unwrap_or_else(|e| panic!("{}: {}", name, e));
+
+        for (vertex, fragment) in [("static_vert.glsl", "static_frag.glsl"), ("shadow_vert.glsl", "shadow_frag.glsl"),
+                                   ("post_vert.glsl", "outline_frag.glsl"), ("post_vert.glsl", "palette_frag.glsl")] {
+            let result = check_varyings((vertex, &parse(vertex)), (fragment, &parse(fragment)));
+            assert!(result.is_ok(), "{:?}", result);
+        }
+    }
+}
+
+use std::collections::HashMap;
+use glsl::parser::{Parse, ParseError};
+use glsl::syntax::{Declaration, Expr, ExternalDeclaration, LayoutQualifierSpec, ShaderStage, StorageQualifier,
+                   StructFieldSpecifier, TypeQualifier, TypeQualifierSpec, TypeSpecifierNonArray};
+use crate::render::shader::UniformValue;
+
+///A declared variable of a shader stage. `location` is its `layout(location = n)`, if it has one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub name : String,
+    pub ty : TypeSpecifierNonArray,
+    pub array : bool,
+    pub location : Option<i32>,
+}
+
+///The uniforms, inputs and outputs that a stage declares, along with the structs the uniforms can use.
+#[derive(Clone, Debug, Default)]
+pub struct StageInterface {
+    pub uniforms : Vec<Variable>,
+    pub inputs : Vec<Variable>,
+    pub outputs : Vec<Variable>,
+    pub structs : HashMap<String, Vec<Variable>>,
+}
+
+impl StageInterface {
+    ///Parses a preprocessed stage. Macros are not expanded, so array sizes may be names.
+    pub fn parse<S>(source : S) -> Result<Self, ParseError> where S : AsRef<str> {
+        let stage = ShaderStage::parse(source)?;
+        let mut interface = StageInterface::default();
+
+        for declaration in &(stage.0).0 {
+            match declaration {
+                ExternalDeclaration::Declaration(Declaration::InitDeclaratorList(list)) => {
+                    let head = &list.head;
+                    let mut ty = head.ty.ty.ty.clone();
+                    if let TypeSpecifierNonArray::Struct(specifier) = &ty {
+                        let name = specifier.name.as_ref().map(|name| name.0.clone()).unwrap_or_default();
+                        interface.structs.insert(name.clone(), fields(&specifier.fields.0));
+                        ty = TypeSpecifierNonArray::TypeName(glsl::syntax::TypeName(name));
+                    }
+
+                    let qualifier = head.ty.qualifier.as_ref();
+                    let type_array = head.ty.ty.array_specifier.is_some();
+                    let names = head.name.iter().map(|name| (name.0.clone(), head.array_specifier.as_ref()))
+                        .chain(list.tail.iter().map(|tail| (tail.ident.ident.0.clone(), tail.ident.array_spec.as_ref())));
+                    for (name, array) in names {
+                        let variable = Variable { name, ty : ty.clone(), array : type_array || array.is_some(), location : location(qualifier) };
+                        interface.push(qualifier, variable);
+                    }
+                }
+                ExternalDeclaration::Declaration(Declaration::Block(block)) => {
+                    let qualifier = Some(&block.qualifier);
+                    interface.structs.insert(block.name.0.clone(), fields(&block.fields));
+                    match &block.identifier {
+                        // Members of a block without an instance name are used as if they were declared on their own.
+                        None => for member in fields(&block.fields) { interface.push(qualifier, member) },
+                        Some(identifier) => interface.push(qualifier, Variable {
+                            name : identifier.ident.0.clone(),
+                            ty : TypeSpecifierNonArray::TypeName(glsl::syntax::TypeName(block.name.0.clone())),
+                            array : identifier.array_spec.is_some(),
+                            location : location(qualifier)
+                        })
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(interface)
+    }
+
+    fn push(&mut self, qualifier : Option<&TypeQualifier>, variable : Variable) {
+        let storage = qualifier.into_iter().flat_map(|qualifier| &qualifier.qualifiers.0).find_map(|spec| match spec {
+            TypeQualifierSpec::Storage(storage) => Some(storage),
+            _ => None
+        });
+        match storage {
+            Some(StorageQualifier::Uniform) => self.uniforms.push(variable),
+            Some(StorageQualifier::In) | Some(StorageQualifier::Attribute) => self.inputs.push(variable),
+            Some(StorageQualifier::Out) => self.outputs.push(variable),
+            _ => {}
+        }
+    }
+
+    ///Finds a uniform by the name OpenGL gives it, like `lights[2].color`, looking inside structs and
+    ///ignoring array indices.
+    pub fn uniform(&self, name : &str) -> Option<&Variable> {
+        let mut parts = name.split('.').map(|part| part.split('[').next().unwrap_or(part));
+        let first = parts.next()?;
+        let mut variable = self.uniforms.iter().find(|uniform| uniform.name == first)?;
+        for part in parts {
+            let members = match &variable.ty {
+                TypeSpecifierNonArray::TypeName(name) => self.structs.get(&name.0)?,
+                _ => return None
+            };
+            variable = members.iter().find(|member| member.name == part)?;
+        }
+        Some(variable)
+    }
+}
+
+///The stages of a program in the order they run, each with the file it was built from.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramInterface {
+    pub stages : Vec<(String, StageInterface)>,
+}
+
+impl ProgramInterface {
+    ///Finds a uniform in any of the stages, see `StageInterface::uniform`.
+    pub fn uniform(&self, name : &str) -> Option<&Variable> {
+        self.stages.iter().find_map(|(_, stage)| stage.uniform(name))
+    }
+
+    ///Checks that every input of a stage is written by the stage before it, with the same type.
+    pub fn check_varyings(&self) -> Result<(), String> {
+        for pair in self.stages.windows(2) {
+            check_varyings((&pair[0].0, &pair[0].1), (&pair[1].0, &pair[1].1))?;
+        }
+        Ok(())
+    }
+}
+
+///Checks that every input of `next` is an output of `previous` with the same type. An input and an output
+///are matched by their `layout(location)` when both declare one, and by name otherwise. Geometry and
+///tessellation inputs are arrays of the outputs before them, so only the types are compared.
+pub fn check_varyings(previous : (&str, &StageInterface), next : (&str, &StageInterface)) -> Result<(), String> {
+    for input in &next.1.inputs {
+        let matches = |output : &&Variable| match (output.location, input.location) {
+            (Some(output), Some(input)) => output == input,
+            _ => output.name == input.name
+        };
+        match previous.1.outputs.iter().find(matches) {
+            None => return Err(match input.location {
+                Some(location) => format!("'{}' is an input of {} at location {}, but {} has no output there or with that name.",
+                    input.name, next.0, location, previous.0),
+                None => format!("'{}' is an input of {} but is not an output of {}.", input.name, next.0, previous.0)
+            }),
+            Some(output) if output.ty != input.ty => return Err(format!("'{}' is a {} output of {} but '{}' is a {} input of {}.",
+                output.name, type_name(&output.ty), previous.0, input.name, type_name(&input.ty), next.0)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+///Whether a value can be sent to a uniform of this type. Booleans and samplers can also be set with ints.
+pub fn accepts(ty : &TypeSpecifierNonArray, value : &UniformValue) -> bool {
+    use TypeSpecifierNonArray::*;
+    match value {
+        UniformValue::FLOAT(_) | UniformValue::FLOAT_ARRAY(_) => *ty == Float,
+        UniformValue::INT(_) | UniformValue::INT_ARRAY(_) => *ty == Int || *ty == Bool || is_sampler(&type_name(ty)),
+        UniformValue::U_INT(_) => *ty == UInt || *ty == Bool,
+        UniformValue::BOOL(_) => *ty == Bool,
+        UniformValue::VEC2F(_) | UniformValue::VEC2F_ARRAY(_) => *ty == Vec2,
+        UniformValue::VEC3F(_) | UniformValue::VEC3F_ARRAY(_) => *ty == Vec3,
+        UniformValue::VEC4F(_) | UniformValue::VEC4F_ARRAY(_) => *ty == Vec4,
+        UniformValue::VEC3U(_) => *ty == UVec3,
+        UniformValue::VEC4U(_) => *ty == UVec4,
+        UniformValue::MAT2F(_) => *ty == Mat2,
+        UniformValue::MAT3F(_) => *ty == Mat3,
+        UniformValue::MAT4F(_) | UniformValue::MAT4F_ARRAY(_) => *ty == Mat4,
+        UniformValue::SAMPLER(_) => is_sampler(&type_name(ty)),
+    }
+}
+
+///The GLSL name of a type, like `vec3` or `sampler2D`.
+pub fn type_name(ty : &TypeSpecifierNonArray) -> String {
+    let mut name = String::new();
+    glsl::transpiler::glsl::show_type_specifier_non_array(&mut name, ty);
+    name
+}
+
+///Whether a GLSL type name, like `sampler2D` or `usampler3D`, is an opaque type that is set with a texture unit.
+pub fn is_sampler(name : &str) -> bool {
+    name.contains("sampler") || name.contains("image")
+}
+
+fn fields(fields : &[StructFieldSpecifier]) -> Vec<Variable> {
+    fields.iter().flat_map(|field| field.identifiers.0.iter().map(move |identifier| Variable {
+        name : identifier.ident.0.clone(),
+        ty : field.ty.ty.clone(),
+        array : field.ty.array_specifier.is_some() || identifier.array_spec.is_some(),
+        location : location(field.qualifier.as_ref())
+    })).collect()
+}
+
+fn location(qualifier : Option<&TypeQualifier>) -> Option<i32> {
+    qualifier?.qualifiers.0.iter().find_map(|spec| match spec {
+        TypeQualifierSpec::Layout(layout) => layout.ids.0.iter().find_map(|id| match id {
+            LayoutQualifierSpec::Identifier(name, Some(value)) if name.0 == "location" => match value.as_ref() {
+                Expr::IntConst(location) => Some(*location),
+                Expr::UIntConst(location) => Some(*location as i32),
+                _ => None
+            },
+            _ => None
+        }),
+        _ => None
+    })
+}
@@ -1,21 +1,56 @@
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix3, SquareMatrix, Vector2};
+    use crate::render::shader::*;
+
+    #[test]
+    fn values_need_the_matching_gl_type() {
+        assert!(gl_type_accepts(glow::FLOAT_VEC2, 1, &VEC2F(Vector2::new(1.0, 2.0))));
+        assert!(!gl_type_accepts(glow::FLOAT_VEC3, 1, &VEC2F(Vector2::new(1.0, 2.0))));
+        assert!(gl_type_accepts(glow::FLOAT_MAT3, 1, &MAT3F(Matrix3::identity())));
+        assert!(!gl_type_accepts(glow::FLOAT_MAT4, 1, &MAT3F(Matrix3::identity())));
+        assert!(gl_type_accepts(glow::BOOL, 1, &BOOL(true)));
+        assert!(!gl_type_accepts(glow::INT, 1, &BOOL(true)));
+        assert!(!gl_type_accepts(glow::FLOAT, 1, &INT(1)));
+    }
+
+    #[test]
+    fn samplers_take_texture_units() {
+        assert!(gl_type_accepts(glow::SAMPLER_2D, 1, &SAMPLER(1)));
+        assert!(gl_type_accepts(glow::SAMPLER_2D, 1, &INT(1)));
+        assert!(!gl_type_accepts(glow::INT, 1, &SAMPLER(1)));
+        assert_eq!(gl_type_name(glow::SAMPLER_2D, 1), "sampler2D");
+    }
+
+    #[test]
+    fn arrays_fit_in_the_uniform() {
+        assert!(gl_type_accepts(glow::FLOAT, 4, &FLOAT_ARRAY(vec![1.0, 2.0])));
+        assert!(!gl_type_accepts(glow::FLOAT, 1, &FLOAT_ARRAY(vec![1.0, 2.0])));
+        assert!(gl_type_accepts(glow::FLOAT, 4, &FLOAT(1.0)));
+        assert_eq!(gl_type_name(glow::FLOAT_MAT4, 8), "mat4[8]");
+    }
+}
+
 use std::borrow::Borrow;
 use glow::{Context, HasContext, NativeProgram, VERTEX_SHADER, FRAGMENT_SHADER, NativeUniformLocation, NativeShader, UniformLocation, GEOMETRY_SHADER, TESS_CONTROL_SHADER};
 use std::ops::Add;
 use std::fs;
-use cgmath::{Vector3, Vector2, Vector4, Matrix4};
+use cgmath::{Vector3, Vector2, Vector4, Matrix2, Matrix3, Matrix4};
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::fmt::{Display, Formatter};
 use std::sync::mpsc;
-use crate::render::shader::UniformValue::{VEC4F, VEC3F, VEC2F, INT, FLOAT, MAT4F, U_INT, VEC4U};
+use crate::render::shader::UniformValue::*;
 use egui::{Ui, DragValue, Color32, Rgba};
 use crate::render::debug::{Debugable, UIRenderType};
 use crate::render::debug::UIRenderType::*;
-use crate::render::shader::ShaderError::{GLSL_COMPILE_ERROR, GLSL_LINK_ERROR, GLSL_PARSE_ERROR, MISSING_SHADER, UNIFORM_ALREADY_EXISTS, UNIFORM_LOCATION_NOT_FOUND};
+use crate::render::shader::ShaderError::{GLSL_COMPILE_ERROR, GLSL_LINK_ERROR, GLSL_PARSE_ERROR, MISSING_SHADER, UNIFORM_ALREADY_EXISTS, UNIFORM_LOCATION_NOT_FOUND, UNIFORM_TYPE_MISMATCH, VARYING_MISMATCH};
 use crate::render::transform::Transform;
-use glsl::parser::{Parse as _, ParseError};
-use glsl::syntax::{Declaration, ExternalDeclaration, ShaderStage, StorageQualifier, TypeQualifierSpec};
-use glsl::syntax::Declaration::InitDeclaratorList;
+use glsl::parser::ParseError;
 use crate::render::{Deletable, RenderContext};
+use crate::render::preprocess::{map_log, preprocess};
+use crate::render::reflect::{accepts, is_sampler, type_name, ProgramInterface, StageInterface};
 
 const VERTEX_SHADER_INDEX : usize = 0;
 const FRAGMENT_SHADER_INDEX : usize = 1;
@@ -24,17 +59,68 @@ const TESSELATION_SHADER_INDEX: usize = 3;
 
 /// This enum represents all of the types that we can turn into a uniform value. To make something
 /// have the ability to become a uniform, implement `Into<UniformValue>` for that type.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum UniformValue {
     FLOAT(f32),
     INT(i32),
     U_INT(u32),
+    BOOL(bool),
     VEC3U(Vector3<u32>),
     VEC4U(Vector4<u32>),
     VEC4F(Vector4<f32>),
     VEC3F(Vector3<f32>),
     VEC2F(Vector2<f32>),
+    MAT2F(Matrix2<f32>),
+    MAT3F(Matrix3<f32>),
     MAT4F(Matrix4<f32>),
+    ///The texture unit a sampler reads from.
+    SAMPLER(u32),
+    FLOAT_ARRAY(Vec<f32>),
+    INT_ARRAY(Vec<i32>),
+    VEC2F_ARRAY(Vec<Vector2<f32>>),
+    VEC3F_ARRAY(Vec<Vector3<f32>>),
+    VEC4F_ARRAY(Vec<Vector4<f32>>),
+    MAT4F_ARRAY(Vec<Matrix4<f32>>),
+}
+
+impl UniformValue {
+    ///The GLSL type this value is sent to, like `vec3` or `mat4[]` for arrays.
+    pub fn glsl_type(&self) -> &'static str {
+        match self {
+            FLOAT(_) => "float",
+            INT(_) => "int",
+            U_INT(_) => "uint",
+            BOOL(_) => "bool",
+            VEC3U(_) => "uvec3",
+            VEC4U(_) => "uvec4",
+            VEC4F(_) => "vec4",
+            VEC3F(_) => "vec3",
+            VEC2F(_) => "vec2",
+            MAT2F(_) => "mat2",
+            MAT3F(_) => "mat3",
+            MAT4F(_) => "mat4",
+            SAMPLER(_) => "sampler",
+            FLOAT_ARRAY(_) => "float[]",
+            INT_ARRAY(_) => "int[]",
+            VEC2F_ARRAY(_) => "vec2[]",
+            VEC3F_ARRAY(_) => "vec3[]",
+            VEC4F_ARRAY(_) => "vec4[]",
+            MAT4F_ARRAY(_) => "mat4[]",
+        }
+    }
+
+    ///How many elements of an array uniform this fills, 1 for everything that is not an array.
+    pub fn count(&self) -> usize {
+        match self {
+            FLOAT_ARRAY(values) => values.len(),
+            INT_ARRAY(values) => values.len(),
+            VEC2F_ARRAY(values) => values.len(),
+            VEC3F_ARRAY(values) => values.len(),
+            VEC4F_ARRAY(values) => values.len(),
+            MAT4F_ARRAY(values) => values.len(),
+            _ => 1
+        }
+    }
 }
 
 /// `Into<UniformValue>` implementation for `Vector4<f32>`. This is so that the type can be used in the
@@ -77,6 +163,60 @@ impl Into<UniformValue> for Matrix4<f32> {
     }
 }
 
+impl From<Matrix3<f32>> for UniformValue {
+    fn from(value : Matrix3<f32>) -> Self {
+        MAT3F(value)
+    }
+}
+
+impl From<Matrix2<f32>> for UniformValue {
+    fn from(value : Matrix2<f32>) -> Self {
+        MAT2F(value)
+    }
+}
+
+impl From<bool> for UniformValue {
+    fn from(value : bool) -> Self {
+        BOOL(value)
+    }
+}
+
+impl From<Vec<f32>> for UniformValue {
+    fn from(value : Vec<f32>) -> Self {
+        FLOAT_ARRAY(value)
+    }
+}
+
+impl From<Vec<i32>> for UniformValue {
+    fn from(value : Vec<i32>) -> Self {
+        INT_ARRAY(value)
+    }
+}
+
+impl From<Vec<Vector2<f32>>> for UniformValue {
+    fn from(value : Vec<Vector2<f32>>) -> Self {
+        VEC2F_ARRAY(value)
+    }
+}
+
+impl From<Vec<Vector3<f32>>> for UniformValue {
+    fn from(value : Vec<Vector3<f32>>) -> Self {
+        VEC3F_ARRAY(value)
+    }
+}
+
+impl From<Vec<Vector4<f32>>> for UniformValue {
+    fn from(value : Vec<Vector4<f32>>) -> Self {
+        VEC4F_ARRAY(value)
+    }
+}
+
+impl From<Vec<Matrix4<f32>>> for UniformValue {
+    fn from(value : Vec<Matrix4<f32>>) -> Self {
+        MAT4F_ARRAY(value)
+    }
+}
+
 impl Into<UniformValue> for Transform {
     fn into(self) -> UniformValue {
         MAT4F(self.calc_mat())
@@ -102,15 +242,18 @@ impl From<Rgba> for UniformValue {
 }
 
 ///Rewrite of the shader
+#[derive(Clone)]
 pub struct ShaderBuilder {
-    shaders : [Option<String>; 4]
+    shaders : [Option<String>; 4],
+    defines : Vec<(String, String)>
 }
 
 impl ShaderBuilder {
 
     pub fn new() -> Self {
         ShaderBuilder {
-            shaders: [None, None, None, None]
+            shaders: [None, None, None, None],
+            defines: Vec::new()
         }
     }
 
@@ -134,37 +277,85 @@ impl ShaderBuilder {
         self
     }
 
+    ///Adds `#define name value` to every stage, right after its `#version`. Defining a name again
+    ///replaces the value.
+    pub fn with_define(mut self, name : &str, value : impl ToString) -> Self {
+        self.defines.retain(|(defined, _)| defined != name);
+        self.defines.push((name.to_owned(), value.to_string()));
+        self
+    }
+
+    ///Builds the program and registers it with the render context, so a `ShaderWatcher` can rebuild it
+    ///when one of its files changes.
     pub fn build(self, render_context : &Rc<RenderContext>) -> ShaderResult<Shader> {
+        let linked = self.link(render_context)?;
+        let program = Rc::new(ReloadableProgram {
+            builder : self,
+            linked : RefCell::new(linked),
+            error : RefCell::new(None),
+            deleted : Cell::new(false)
+        });
+        render_context.shaders.borrow_mut().push(Rc::downgrade(&program));
+
+        Ok(Shader {
+            program,
+            render_context: Rc::clone(render_context)
+        })
+    }
+
+    fn link(&self, render_context : &RenderContext) -> ShaderResult<LinkedProgram> {
+        let gl = &render_context.gl;
         if self.shaders[VERTEX_SHADER_INDEX].is_none() || self.shaders[FRAGMENT_SHADER_INDEX].is_none() {
             return Err(MISSING_SHADER)
         }
 
-        let gl = &render_context.gl;
+        //In the order they run, so each stage's inputs can be checked against the outputs before it.
+        let stage_types = [
+            (VERTEX_SHADER_INDEX, VERTEX_SHADER),
+            (TESSELATION_SHADER_INDEX, TESS_CONTROL_SHADER),
+            (GEOMETRY_SHADER_INDEX, GEOMETRY_SHADER),
+            (FRAGMENT_SHADER_INDEX, FRAGMENT_SHADER)
+        ];
 
         unsafe {
             let program = gl.create_program().expect("Unable to create shader program.");
-
-            let vert_shader = self.load_shader(gl, self.shaders[VERTEX_SHADER_INDEX].clone().unwrap().as_str(), VERTEX_SHADER)?;
-            gl.attach_shader(program, vert_shader);
-
-            let frag_shader = self.load_shader(gl, self.shaders[FRAGMENT_SHADER_INDEX].clone().unwrap().as_str(), FRAGMENT_SHADER)?;
-            gl.attach_shader(program, frag_shader);
-
-            let mut geo_shader = None;
-            if self.shaders[GEOMETRY_SHADER_INDEX].is_some() {
-                geo_shader = Some(self.load_shader(gl, self.shaders[GEOMETRY_SHADER_INDEX].clone().unwrap().as_str(), GEOMETRY_SHADER)?);
-                gl.attach_shader(program, geo_shader.unwrap());
+            let mut stages = Vec::new();
+            let mut files = Vec::new();
+            let mut interface = ProgramInterface::default();
+
+            for (index, shader_type) in stage_types {
+                if let Some(file_name) = &self.shaders[index] {
+                    match self.load_shader(gl, file_name, shader_type) {
+                        Ok((shader, included, stage)) => {
+                            gl.attach_shader(program, shader);
+                            stages.push(shader);
+                            interface.stages.push((file_name.clone(), stage));
+                            for file in included {
+                                if !files.contains(&file) { files.push(file) }
+                            }
+                        }
+                        Err(error) => {
+                            delete_program(gl, program, &stages);
+                            return Err(error)
+                        }
+                    }
+                }
             }
 
-            let mut tes_shader = None;
-            if self.shaders[TESSELATION_SHADER_INDEX].is_some() {
-                tes_shader = Some(self.load_shader(gl, self.shaders[TESSELATION_SHADER_INDEX].clone().unwrap().as_str(), TESS_CONTROL_SHADER)?);
-                gl.attach_shader(program, tes_shader.unwrap())
+            if let Err(error) = interface.check_varyings() {
+                delete_program(gl, program, &stages);
+                return Err(VARYING_MISMATCH(error))
             }
 
             gl.link_program(program);
             if !gl.get_program_link_status(program) {
-                return Err(GLSL_LINK_ERROR)
+                let log = gl.get_program_info_log(program);
+                delete_program(gl, program, &stages);
+                return Err(GLSL_LINK_ERROR(log))
+            }
+
+            for (name, binding) in render_context.uniform_blocks() {
+                bind_uniform_block(gl, program, &name, binding);
             }
 
             let mut uniform_map = HashMap::new();
@@ -172,114 +363,213 @@ impl ShaderBuilder {
                 let uniform = gl.get_active_uniform(program, i);
                 if uniform.is_some() {
                     let uniform = uniform.unwrap();
-                    uniform_map.insert(uniform.name.clone(), gl.get_uniform_location(program, uniform.name.as_str()).unwrap());
+                    //Members of uniform blocks have no location, they are set through a `UniformBlock`.
+                    let location = match gl.get_uniform_location(program, uniform.name.as_str()) {
+                        Some(location) => location,
+                        None => continue
+                    };
+                    let info = UniformInfo {
+                        location,
+                        utype : uniform.utype,
+                        size : uniform.size
+                    };
+                    //Arrays are listed as `name[0]`, but can be sent to by their name as well.
+                    if let Some(array_name) = uniform.name.strip_suffix("[0]") {
+                        uniform_map.insert(array_name.to_owned(), info.clone());
+                    }
+                    uniform_map.insert(uniform.name.clone(), info);
                 }
-                // println!("Active Shader {}: {:?} {:?}", i, uni.name, uni.utype)
             }
 
-            Ok(Shader {
+            Ok(LinkedProgram {
                 program,
-                vert_shader,
-                frag_shader,
-                geo_shader,
-                tes_shader,
-                render_context: Rc::clone(render_context),
-                uniform_map
+                stages,
+                files,
+                uniform_map,
+                interface,
+                values : HashMap::new()
             })
         }
     }
 
-    fn load_shader(&self, gl : &Context, file_name : &str, shader_type : u32) -> ShaderResult<NativeShader> {
+    ///Compiles one stage after running it through the preprocessor. The source is parsed first, so syntax
+    ///errors are caught before the driver sees it. Returns the shader along with every file that went into
+    ///it and the declarations it has.
+    fn load_shader(&self, gl : &Context, file_name : &str, shader_type : u32) -> ShaderResult<(NativeShader, Vec<String>, StageInterface)> {
+        let preprocessed = preprocess(file_name, &self.defines, |name| {
+            let filepath = String::from("assets/shaders/").add(name);
+            fs::read_to_string(filepath.as_str()).map_err(|e| format!("Could not read '{}': {}", filepath, e))
+        }).map_err(GLSL_COMPILE_ERROR)?;
+
+        let interface = StageInterface::parse(&preprocessed.source).map_err(|error| {
+            GLSL_PARSE_ERROR(ParseError { info : format!("{}: {}", file_name, error.info) })
+        })?;
+
         unsafe {
             let shader = gl.create_shader(shader_type).expect("Unable to create shader.");
-            let filepath = String::from("assets/shaders/").add(file_name);
-            let data = fs::read_to_string(filepath.as_str()).expect("Could not find file.");
-
-            gl.shader_source(shader, data.as_str());
+            gl.shader_source(shader, preprocessed.source.as_str());
             gl.compile_shader(shader);
 
             if !gl.get_shader_compile_status(shader) {
-                return Err(GLSL_COMPILE_ERROR(gl.get_shader_info_log(shader)))
+                let log = map_log(&gl.get_shader_info_log(shader), &preprocessed.files);
+                gl.delete_shader(shader);
+                return Err(GLSL_COMPILE_ERROR(log))
             }
-            Ok(shader)
+            Ok((shader, preprocessed.files, interface))
         }
     }
 }
 
-pub struct Shader{
+///Points the uniform block `name` of the program at a binding point, if the program has the block.
+unsafe fn bind_uniform_block(gl : &Context, program : NativeProgram, name : &str, binding : u32) {
+    if let Some(index) = gl.get_uniform_block_index(program, name) {
+        gl.uniform_block_binding(program, index, binding);
+    }
+}
+
+unsafe fn delete_program(gl : &Context, program : NativeProgram, stages : &[NativeShader]) {
+    gl.delete_program(program);
+    for stage in stages {
+        gl.delete_shader(*stage);
+    }
+}
+
+///An active uniform of a linked program, with the type and array size OpenGL reports for it.
+#[derive(Clone)]
+struct UniformInfo {
+    location : NativeUniformLocation,
+    utype : u32,
+    size : i32
+}
+
+///A linked program, along with the last value that was sent to each of its uniforms so they can be sent
+///again when the program is rebuilt.
+struct LinkedProgram {
     program : NativeProgram,
-    vert_shader : NativeShader,
-    frag_shader : NativeShader,
-    geo_shader : Option<NativeShader>,
-    tes_shader : Option<NativeShader>,
-    render_context : Rc<RenderContext>,
-    uniform_map : HashMap<String, NativeUniformLocation>
+    stages : Vec<NativeShader>,
+    files : Vec<String>,
+    uniform_map : HashMap<String, UniformInfo>,
+    interface : ProgramInterface,
+    values : HashMap<String, UniformValue>
+}
+
+impl LinkedProgram {
+    ///Sends the value to the uniform. The value is remembered even if this program does not have the
+    ///uniform, as it may come back after the shader is edited. A value that does not fit the type OpenGL
+    ///reports for the uniform is not sent. Uniforms the driver removed are checked against the type they
+    ///are declared with instead.
+    fn send(&mut self, gl : &Context, uniform_name : &str, value : UniformValue) -> ShaderResult<()> {
+        match self.uniform_map.get(uniform_name) {
+            Some(info) if !gl_type_accepts(info.utype, info.size, &value) => return Err(UNIFORM_TYPE_MISMATCH(
+                format!("'{}' is a {}, but was sent a {}.", uniform_name, gl_type_name(info.utype, info.size), value.glsl_type()))),
+            None => if let Some(uniform) = self.interface.uniform(uniform_name) {
+                if !accepts(&uniform.ty, &value) {
+                    return Err(UNIFORM_TYPE_MISMATCH(format!("'{}' is a {}, but was sent a {}.", uniform_name, type_name(&uniform.ty), value.glsl_type())))
+                }
+            },
+            _ => {}
+        }
+        self.values.insert(uniform_name.to_owned(), value.clone());
+        let uniform_location = self.uniform_map.get(uniform_name).ok_or(UNIFORM_LOCATION_NOT_FOUND)?.location;
+        send_uniforms(gl, value, self.program, uniform_location);
+        Ok(())
+    }
+}
+
+///The part of a `Shader` that is shared with its uniform handlers. Rebuilding the program swaps it out
+///in here, so every handler moves over to the new program without being handed out again.
+pub(crate) struct ReloadableProgram {
+    builder : ShaderBuilder,
+    linked : RefCell<LinkedProgram>,
+    error : RefCell<Option<String>>,
+    deleted : Cell<bool>
+}
+
+impl ReloadableProgram {
+    ///Builds the program again from its files. If that fails the old program is kept, and the error is
+    ///kept until a rebuild succeeds.
+    pub(crate) fn reload(&self, render_context : &RenderContext) -> ShaderResult<()> {
+        if self.deleted.get() { return Ok(()) }
+
+        let gl = &render_context.gl;
+        let mut linked = match self.builder.link(render_context) {
+            Ok(linked) => linked,
+            Err(error) => {
+                *self.error.borrow_mut() = Some(error.to_string());
+                return Err(error)
+            }
+        };
+
+        let old = self.linked.replace_with(|old| {
+            for (name, value) in &old.values {
+                linked.send(gl, name, value.clone()).ok();
+            }
+            linked
+        });
+        unsafe { delete_program(gl, old.program, &old.stages) }
+        *self.error.borrow_mut() = None;
+        Ok(())
+    }
+
+    pub(crate) fn bind_uniform_block(&self, gl : &Context, name : &str, binding : u32) {
+        if self.deleted.get() { return }
+        unsafe { bind_uniform_block(gl, self.linked.borrow().program, name, binding) }
+    }
+
+    ///Whether the program is built from `file_name`, relative to `assets/shaders`.
+    pub(crate) fn uses_file(&self, file_name : &str) -> bool {
+        self.linked.borrow().files.iter().any(|file| file == file_name)
+    }
+
+    pub(crate) fn error(&self) -> Option<String> {
+        self.error.borrow().clone()
+    }
+
+    pub(crate) fn is_deleted(&self) -> bool {
+        self.deleted.get()
+    }
+}
+
+pub struct Shader{
+    program : Rc<ReloadableProgram>,
+    render_context : Rc<RenderContext>
 }
 
 impl Shader {
     pub fn add_uniform<T>(&mut self, uniform_name : &str, uniform : &mut T) -> ShaderResult<()> where T : Uniform {
-        unsafe {
-            let uniform_location = self.get_uniform_location(uniform_name)?;
-
-            uniform.provide_handle(ShaderUniformHandler{
-                program: self.program.clone(),
-                uniform : uniform_location,
-                render_context: Rc::clone(&self.render_context)
-            })
+        if !self.has_uniform(uniform_name) {
+            return Err(UNIFORM_LOCATION_NOT_FOUND)
         }
 
+        uniform.provide_handle(self.handler(uniform_name));
         Ok(())
     }
 
+    ///Hands `uniform` a handler for every uniform it asks for. Names the shader doesn't have, or that the
+    ///driver optimized away, get `None`.
     pub fn add_multi_uniform<T>(&self, uniform : &mut T) where T : MultiUniform {
-        uniform.provide_handle_provider(|name| {
-            let uniform_location = self.get_uniform_location(name)
-                .expect(format!("Need to have uniform with the name '{}'.", name).as_ref());
-            let shader_handle = ShaderUniformHandler{
-                program: self.program,
-                uniform: uniform_location,
-                render_context: Rc::clone(&self.render_context)
-            };
-            Some(shader_handle)
-        });
+        uniform.provide_handle_provider(|name| self.has_uniform(name).then(|| self.handler(name)));
     }
 
     pub fn send_uniform(&self, uniform_name : &str, value : impl Into<UniformValue>) -> ShaderResult<()>{
-        let uniform_location = self.get_uniform_location(uniform_name)?;
-        send_uniforms(&self.render_context.gl, value, self.program, uniform_location);
-        Ok(())
+        self.program.linked.borrow_mut().send(&self.render_context.gl, uniform_name, value.into())
     }
 
     pub fn has_uniform(&self, uniform_name : &str) -> bool {
-        self.uniform_map.contains_key(uniform_name)
+        self.program.linked.borrow().uniform_map.contains_key(uniform_name)
     }
 
     pub fn bind(&self) {
         unsafe {
-            self.render_context.gl.use_program(Some(self.program))
+            self.render_context.gl.use_program(Some(self.program.linked.borrow().program))
         }
     }
 
-    // pub fn destory(&self) {
-    //     unsafe {
-    //         let gl = &self.render_context.gl;
-    //         gl.delete_shader(self.vert_shader);
-    //         gl.delete_shader(self.frag_shader);
-    //         if self.geo_shader.is_some() { gl.delete_shader(self.geo_shader.unwrap())}
-    //         if self.tes_shader.is_some() { gl.delete_shader(self.tes_shader.unwrap())}
-    //         gl.delete_program(self.program);
-    //     }
-    // }
-
-    fn get_uniform_location(&self, uniform_name : &str) -> ShaderResult<NativeUniformLocation>{
-        unsafe {
-            if !self.uniform_map.contains_key(uniform_name) {
-                return Err(UNIFORM_LOCATION_NOT_FOUND)
-            }
-
-            let uniform_location = self.uniform_map.get(uniform_name).unwrap();
-
-            Ok(*uniform_location)
+    fn handler(&self, uniform_name : &str) -> ShaderUniformHandler {
+        ShaderUniformHandler {
+            program : Rc::clone(&self.program),
+            uniform : uniform_name.to_owned(),
+            render_context : Rc::clone(&self.render_context)
         }
     }
 }
@@ -289,33 +579,47 @@ pub type ShaderResult<T> = Result<T, ShaderError>;
 #[derive(Debug)]
 pub enum ShaderError {
     MISSING_SHADER,
-    GLSL_LINK_ERROR,
+    GLSL_LINK_ERROR(String),
     GLSL_PARSE_ERROR(ParseError),
     GLSL_COMPILE_ERROR(String),
     UNIFORM_ALREADY_EXISTS,
-    UNIFORM_LOCATION_NOT_FOUND
+    UNIFORM_LOCATION_NOT_FOUND,
+    UNIFORM_TYPE_MISMATCH(String),
+    VARYING_MISMATCH(String)
+}
+
+impl Display for ShaderError {
+    fn fmt(&self, f : &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GLSL_COMPILE_ERROR(log) => write!(f, "{}", log),
+            GLSL_LINK_ERROR(log) => write!(f, "Link error:\n{}", log),
+            GLSL_PARSE_ERROR(error) => write!(f, "{}", error),
+            UNIFORM_TYPE_MISMATCH(message) => write!(f, "Uniform type mismatch: {}", message),
+            VARYING_MISMATCH(message) => write!(f, "Varying mismatch: {}", message),
+            error => write!(f, "{:?}", error)
+        }
+    }
 }
 
 impl Deletable for Shader {
     unsafe fn delete(&self, gl: &Context) {
-        gl.delete_program(self.program);
-        gl.delete_shader(self.vert_shader);
-        gl.delete_shader(self.frag_shader);
-        if self.geo_shader.is_some() { gl.delete_shader(self.geo_shader.unwrap())}
-        if self.tes_shader.is_some() { gl.delete_shader(self.tes_shader.unwrap())}
+        let linked = self.program.linked.borrow();
+        delete_program(gl, linked.program, &linked.stages);
+        self.program.deleted.set(true);
     }
 }
 
+///Sends values to one uniform of a shader. It follows the shader when it is rebuilt.
 #[derive(Clone)]
 pub struct ShaderUniformHandler {
-    program : NativeProgram,
-    uniform : NativeUniformLocation,
+    program : Rc<ReloadableProgram>,
+    uniform : String,
     render_context: Rc<RenderContext>
 }
 
 impl ShaderUniformHandler {
     pub fn update_uniform(&self, value : impl Into<UniformValue>) {
-        send_uniforms(&self.render_context.gl, value, self.program, self.uniform)
+        self.program.linked.borrow_mut().send(&self.render_context.gl, &self.uniform, value.into()).ok();
     }
 }
 
@@ -347,18 +651,137 @@ fn send_uniforms(gl : &Context, uniform_value : impl Into<UniformValue>, program
             VEC3F(value) => {
                 gl.uniform_3_f32(Some(location.borrow()), value.x, value.y, value.z);
             }
-            VEC2F(_) => {}
+            VEC2F(value) => {
+                gl.uniform_2_f32(Some(location.borrow()), value.x, value.y);
+            }
+            MAT2F(value) => {
+                let slice : [[f32; 2]; 2] = value.into();
+                gl.uniform_matrix_2_f32_slice(Some(&location), false, &slice.concat());
+            }
+            MAT3F(value) => {
+                let slice : [[f32; 3]; 3] = value.into();
+                gl.uniform_matrix_3_f32_slice(Some(&location), false, &slice.concat());
+            }
             MAT4F(value) => {
                 let slice : [[f32; 4]; 4] = value.into();
                 let result = &slice.concat();
                 gl.uniform_matrix_4_f32_slice(Some(&location), false, result);
             }
-            UniformValue::VEC3U(value) => {
+            VEC3U(value) => {
                 gl.uniform_3_u32(Some(location.borrow()), value.x, value.y, value.z)
             }
-            UniformValue::VEC4U(value) => {
+            VEC4U(value) => {
                 gl.uniform_4_u32(Some(location.borrow()), value.x, value.y, value.z, value.w)
             }
+            BOOL(value) => {
+                gl.uniform_1_i32(Some(location.borrow()), value as i32);
+            }
+            SAMPLER(unit) => {
+                gl.uniform_1_i32(Some(location.borrow()), unit as i32);
+            }
+            FLOAT_ARRAY(values) => {
+                gl.uniform_1_f32_slice(Some(location.borrow()), &values);
+            }
+            INT_ARRAY(values) => {
+                gl.uniform_1_i32_slice(Some(location.borrow()), &values);
+            }
+            VEC2F_ARRAY(values) => {
+                let flat : Vec<f32> = values.iter().flat_map(|value| [value.x, value.y]).collect();
+                gl.uniform_2_f32_slice(Some(location.borrow()), &flat);
+            }
+            VEC3F_ARRAY(values) => {
+                let flat : Vec<f32> = values.iter().flat_map(|value| [value.x, value.y, value.z]).collect();
+                gl.uniform_3_f32_slice(Some(location.borrow()), &flat);
+            }
+            VEC4F_ARRAY(values) => {
+                let flat : Vec<f32> = values.iter().flat_map(|value| [value.x, value.y, value.z, value.w]).collect();
+                gl.uniform_4_f32_slice(Some(location.borrow()), &flat);
+            }
+            MAT4F_ARRAY(values) => {
+                let flat : Vec<f32> = values.iter().flat_map(|value| {
+                    let slice : [[f32; 4]; 4] = (*value).into();
+                    slice.concat()
+                }).collect();
+                gl.uniform_matrix_4_f32_slice(Some(&location), false, &flat);
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+///Whether a value can be sent to a uniform with the type and array size OpenGL reports for it. Ints can
+///also set booleans and samplers, like `glUniform1i` allows, and arrays can fill the start of an array.
+fn gl_type_accepts(utype : u32, size : i32, value : &UniformValue) -> bool {
+    if value.count() > size.max(1) as usize { return false }
+    match value {
+        FLOAT(_) | FLOAT_ARRAY(_) => utype == glow::FLOAT,
+        INT(_) | INT_ARRAY(_) => utype == glow::INT || utype == glow::BOOL || gl_is_sampler(utype),
+        U_INT(_) => utype == glow::UNSIGNED_INT || utype == glow::BOOL,
+        BOOL(_) => utype == glow::BOOL,
+        VEC2F(_) | VEC2F_ARRAY(_) => utype == glow::FLOAT_VEC2,
+        VEC3F(_) | VEC3F_ARRAY(_) => utype == glow::FLOAT_VEC3,
+        VEC4F(_) | VEC4F_ARRAY(_) => utype == glow::FLOAT_VEC4,
+        VEC3U(_) => utype == glow::UNSIGNED_INT_VEC3,
+        VEC4U(_) => utype == glow::UNSIGNED_INT_VEC4,
+        MAT2F(_) => utype == glow::FLOAT_MAT2,
+        MAT3F(_) => utype == glow::FLOAT_MAT3,
+        MAT4F(_) | MAT4F_ARRAY(_) => utype == glow::FLOAT_MAT4,
+        SAMPLER(_) => gl_is_sampler(utype),
+    }
+}
+
+fn gl_is_sampler(utype : u32) -> bool {
+    glsl_type_name(utype).map_or(false, is_sampler)
+}
+
+///The GLSL name of a type OpenGL reports for a uniform, for error messages.
+fn gl_type_name(utype : u32, size : i32) -> String {
+    let name = match glsl_type_name(utype) {
+        Some(name) => name,
+        None => return format!("GL type {:#x}", utype)
+    };
+    if size > 1 { format!("{}[{}]", name, size) } else { name.to_owned() }
+}
+
+fn glsl_type_name(utype : u32) -> Option<&'static str> {
+    Some(match utype {
+        glow::FLOAT => "float",
+        glow::INT => "int",
+        glow::UNSIGNED_INT => "uint",
+        glow::BOOL => "bool",
+        glow::FLOAT_VEC2 => "vec2",
+        glow::FLOAT_VEC3 => "vec3",
+        glow::FLOAT_VEC4 => "vec4",
+        glow::INT_VEC2 => "ivec2",
+        glow::INT_VEC3 => "ivec3",
+        glow::INT_VEC4 => "ivec4",
+        glow::UNSIGNED_INT_VEC3 => "uvec3",
+        glow::UNSIGNED_INT_VEC4 => "uvec4",
+        glow::FLOAT_MAT2 => "mat2",
+        glow::FLOAT_MAT3 => "mat3",
+        glow::FLOAT_MAT4 => "mat4",
+        glow::SAMPLER_1D => "sampler1D",
+        glow::SAMPLER_2D => "sampler2D",
+        glow::SAMPLER_3D => "sampler3D",
+        glow::SAMPLER_CUBE => "samplerCube",
+        glow::SAMPLER_1D_SHADOW => "sampler1DShadow",
+        glow::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        glow::SAMPLER_CUBE_SHADOW => "samplerCubeShadow",
+        glow::SAMPLER_1D_ARRAY => "sampler1DArray",
+        glow::SAMPLER_2D_ARRAY => "sampler2DArray",
+        glow::SAMPLER_1D_ARRAY_SHADOW => "sampler1DArrayShadow",
+        glow::SAMPLER_2D_ARRAY_SHADOW => "sampler2DArrayShadow",
+        glow::SAMPLER_2D_MULTISAMPLE => "sampler2DMS",
+        glow::SAMPLER_2D_MULTISAMPLE_ARRAY => "sampler2DMSArray",
+        glow::SAMPLER_BUFFER => "samplerBuffer",
+        glow::SAMPLER_2D_RECT => "sampler2DRect",
+        glow::INT_SAMPLER_2D => "isampler2D",
+        glow::INT_SAMPLER_3D => "isampler3D",
+        glow::INT_SAMPLER_CUBE => "isamplerCube",
+        glow::INT_SAMPLER_2D_ARRAY => "isampler2DArray",
+        glow::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        glow::UNSIGNED_INT_SAMPLER_3D => "usampler3D",
+        glow::UNSIGNED_INT_SAMPLER_CUBE => "usamplerCube",
+        glow::UNSIGNED_INT_SAMPLER_2D_ARRAY => "usampler2DArray",
+        _ => return None
+    })
+}
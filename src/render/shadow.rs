@@ -0,0 +1,257 @@
+#[cfg(test)]
+mod tests {
+    use cgmath::{Vector3, Vector4};
+    use crate::render::mesh::Bounds;
+    use crate::render::shadow::*;
+
+    #[test]
+    fn light_space_covers_bounds() {
+        let bounds = Bounds { min : Vector3::new(-1.0, 0.0, -2.0), max : Vector3::new(3.0, 2.0, 1.0) };
+        for direction in [Vector3::new(0.3, 1.0, 0.2), Vector3::new(0.0, 1.0, 0.0), Vector3::new(-1.0, 0.0, 0.0)] {
+            let matrix = light_space(direction, &bounds);
+            for x in [bounds.min.x, bounds.max.x] {
+                for y in [bounds.min.y, bounds.max.y] {
+                    for z in [bounds.min.z, bounds.max.z] {
+                        let clip = matrix * Vector4::new(x, y, z, 1.0);
+                        assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0 && clip.z.abs() <= 1.0);
+                    }
+                }
+            }
+            let center = matrix * bounds.center().extend(1.0);
+            assert!(center.x.abs() < 0.0001 && center.y.abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn closer_to_the_light_is_shallower() {
+        let bounds = Bounds { min : Vector3::new(-1.0, -1.0, -1.0), max : Vector3::new(1.0, 1.0, 1.0) };
+        let matrix = light_space(Vector3::new(0.0, 1.0, 0.0), &bounds);
+        let top = matrix * Vector4::new(0.0, 1.0, 0.0, 1.0);
+        let bottom = matrix * Vector4::new(0.0, -1.0, 0.0, 1.0);
+        assert!(top.z < bottom.z);
+    }
+}
+
+use std::rc::Rc;
+use cgmath::{ortho, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use egui::{ComboBox, DragValue, Ui};
+use glow::*;
+use crate::render::debug::Debugable;
+use crate::render::mesh::Bounds;
+use crate::render::shader::{MultiUniform, Shader, ShaderBuilder, ShaderResult, ShaderUniformHandler, UniformValue};
+use crate::render::{Deletable, RenderContext};
+
+///The texture unit the shadow map is bound to while the model is drawn.
+const SHADOW_TEXTURE_UNIT : u32 = 1;
+const RESOLUTIONS : [u32; 5] = [256, 512, 1024, 2048, 4096];
+
+///The matrix that takes world space into the clip space of a directional light looking at `bounds`.
+///`direction` points towards the light, like the global light direction. The whole bounding sphere fits
+///in the view, so the shadows do not swim while a model turns.
+pub fn light_space(direction : Vector3<f32>, bounds : &Bounds) -> Matrix4<f32> {
+    let direction = if direction.magnitude2() > 0.0 { direction.normalize() } else { Vector3::unit_y() };
+    let radius = bounds.radius().max(0.001);
+    let center = bounds.center();
+    let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+
+    let eye = center + direction * radius * 2.0;
+    let view = Matrix4::look_at_rh(Point3::new(eye.x, eye.y, eye.z), Point3::new(center.x, center.y, center.z), up);
+    ortho(-radius, radius, -radius, radius, radius, radius * 3.0) * view
+}
+
+///Renders the depth of the model as seen from the global light into its own FBO, which the static shader
+///compares against to find what is in shadow. This has to happen before the model is drawn into `Downsize`.
+pub struct ShadowMap {
+    fbo : NativeFramebuffer,
+    depth : NativeTexture,
+    resolution : u32,
+    bias : f32,
+    hard_edges : bool,
+    enabled : bool,
+    light_space : Matrix4<f32>,
+    depth_shader : Shader,
+    render_context : Rc<RenderContext>,
+    light_space_uniform : Option<ShaderUniformHandler>,
+    texture_uniform : Option<ShaderUniformHandler>,
+    enabled_uniform : Option<ShaderUniformHandler>,
+    bias_uniform : Option<ShaderUniformHandler>,
+    hard_edges_uniform : Option<ShaderUniformHandler>,
+}
+
+impl ShadowMap {
+    pub fn new(render_context : &Rc<RenderContext>, resolution : u32) -> ShaderResult<Self> {
+        let depth_shader = ShaderBuilder::new()
+            .with_vert_shader("shadow_vert.glsl")
+            .with_frag_shader("shadow_frag.glsl")
+            .build(render_context)?;
+        let gl = &render_context.gl;
+
+        unsafe {
+            let depth = gl.create_texture().expect("Could not create shadow map texture.");
+            gl.bind_texture(TEXTURE_2D, Some(depth));
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
+            //Everything outside of the map is lit.
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_BORDER as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_BORDER as i32);
+            gl.tex_parameter_f32_slice(TEXTURE_2D, TEXTURE_BORDER_COLOR, &[1.0, 1.0, 1.0, 1.0]);
+            gl.bind_texture(TEXTURE_2D, None);
+
+            let fbo = gl.create_framebuffer().expect("Can't create shadow map fbo.");
+            gl.bind_framebuffer(FRAMEBUFFER, Some(fbo));
+            gl.framebuffer_texture_2d(FRAMEBUFFER, DEPTH_ATTACHMENT, TEXTURE_2D, Some(depth), 0);
+            gl.draw_buffer(NONE);
+            gl.read_buffer(NONE);
+            gl.bind_framebuffer(FRAMEBUFFER, None);
+
+            let mut shadow_map = ShadowMap {
+                fbo,
+                depth,
+                resolution : 0,
+                bias : 0.005,
+                hard_edges : true,
+                enabled : false,
+                light_space : Matrix4::identity(),
+                depth_shader,
+                render_context : Rc::clone(render_context),
+                light_space_uniform : None,
+                texture_uniform : None,
+                enabled_uniform : None,
+                bias_uniform : None,
+                hard_edges_uniform : None,
+            };
+            shadow_map.set_resolution(resolution);
+            Ok(shadow_map)
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled : bool) {
+        self.enabled = enabled;
+        self.update_uniforms();
+    }
+
+    ///Sets the width and height of the depth texture in pixels.
+    pub fn set_resolution(&mut self, resolution : u32) {
+        let resolution = resolution.clamp(16, 8192);
+        if resolution == self.resolution { return }
+        self.resolution = resolution;
+        unsafe {
+            let gl = &self.render_context.gl;
+            gl.bind_texture(TEXTURE_2D, Some(self.depth));
+            gl.tex_image_2d(TEXTURE_2D, 0, DEPTH_COMPONENT24 as i32, resolution as i32, resolution as i32, 0, DEPTH_COMPONENT, UNSIGNED_INT, None);
+            gl.bind_texture(TEXTURE_2D, None);
+        }
+    }
+
+    ///Sets how far a surface has to be behind the shadow map before it is shadowed, which keeps
+    ///surfaces from shadowing themselves.
+    pub fn set_bias(&mut self, bias : f32) {
+        self.bias = bias;
+        self.update_uniforms();
+    }
+
+    ///Hard edges take a single sample of the map, so a pixel is either lit or not. Otherwise nine samples
+    ///are averaged and the result is banded with the rest of the lighting.
+    pub fn set_hard_edges(&mut self, hard_edges : bool) {
+        self.hard_edges = hard_edges;
+        self.update_uniforms();
+    }
+
+    ///Points the light at `bounds` from `direction`, which points towards the light.
+    pub fn fit(&mut self, direction : Vector3<f32>, bounds : &Bounds) {
+        self.light_space = light_space(direction, bounds);
+        if let Some(uniform) = &self.light_space_uniform { uniform.update_uniform(self.light_space) }
+    }
+
+    ///Renders the depth of the scene into the shadow map and binds it for the static shader. The callback
+    ///draws the scene with the depth shader it is handed, after sending it the model transform. The
+    ///viewport is restored afterwards, and `restore` is bound again, so this can run in the middle of a
+    ///`Downsize` render.
+    pub fn render(&self, gl : &Context, restore : Option<NativeFramebuffer>, render_callback : impl FnOnce(&Context, &Shader)) {
+        if !self.enabled { return }
+        unsafe {
+            let mut viewport = [0; 4];
+            gl.get_parameter_i32_slice(VIEWPORT, &mut viewport);
+
+            gl.bind_framebuffer(FRAMEBUFFER, Some(self.fbo));
+            gl.viewport(0, 0, self.resolution as i32, self.resolution as i32);
+            gl.clear(DEPTH_BUFFER_BIT);
+            self.depth_shader.send_uniform("light_space", self.light_space).ok();
+            render_callback(gl, &self.depth_shader);
+
+            gl.bind_framebuffer(FRAMEBUFFER, restore);
+            gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            gl.active_texture(TEXTURE0 + SHADOW_TEXTURE_UNIT);
+            gl.bind_texture(TEXTURE_2D, Some(self.depth));
+            gl.active_texture(TEXTURE0);
+        }
+    }
+
+    fn update_uniforms(&self) {
+        if let Some(uniform) = &self.light_space_uniform { uniform.update_uniform(self.light_space) }
+        if let Some(uniform) = &self.texture_uniform { uniform.update_uniform(UniformValue::SAMPLER(SHADOW_TEXTURE_UNIT)) }
+        if let Some(uniform) = &self.enabled_uniform { uniform.update_uniform(self.enabled as i32) }
+        if let Some(uniform) = &self.bias_uniform { uniform.update_uniform(self.bias) }
+        if let Some(uniform) = &self.hard_edges_uniform { uniform.update_uniform(self.hard_edges as i32) }
+    }
+}
+
+impl MultiUniform for ShadowMap {
+    fn provide_handle_provider(&mut self, provider : impl Fn(&str) -> Option<ShaderUniformHandler>) {
+        self.light_space_uniform = provider("light_space");
+        self.texture_uniform = provider("shadow_map");
+        self.enabled_uniform = provider("shadows_enabled");
+        self.bias_uniform = provider("shadow_bias");
+        self.hard_edges_uniform = provider("shadow_hard_edges");
+        self.update_uniforms();
+    }
+}
+
+impl Debugable for ShadowMap {
+    fn debug(&mut self, ui : &mut Ui, enabled : bool) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            changed |= ui.add_enabled(enabled, egui::Checkbox::new(&mut self.enabled, "Shadows")).changed();
+            changed |= ui.add_enabled(enabled && self.enabled, egui::Checkbox::new(&mut self.hard_edges, "Hard Edges")).changed();
+        });
+
+        ui.add_enabled_ui(enabled && self.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Resolution:");
+                let mut resolution = self.resolution;
+                ComboBox::from_id_source("shadow_resolution")
+                    .selected_text(resolution.to_string())
+                    .show_ui(ui, |ui| {
+                        for option in RESOLUTIONS {
+                            ui.selectable_value(&mut resolution, option, option.to_string());
+                        }
+                    });
+                if resolution != self.resolution {
+                    self.set_resolution(resolution);
+                    changed = true;
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Bias:");
+                changed |= ui.add(DragValue::new(&mut self.bias).speed(0.0001).clamp_range(0.0..=0.1)).changed();
+            });
+        });
+
+        if changed { self.update_uniforms() }
+        changed
+    }
+}
+
+impl Deletable for ShadowMap {
+    unsafe fn delete(&self, gl : &Context) {
+        gl.delete_framebuffer(self.fbo);
+        gl.delete_texture(self.depth);
+        self.depth_shader.delete(gl);
+    }
+}
@@ -0,0 +1,305 @@
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, InnerSpace, Matrix4, Quaternion, Rotation3, Vector2, Vector3, Vector4};
+    use crate::render::mesh::MeshData;
+    use crate::render::skin::*;
+
+    fn node(parent : Option<usize>, translation : Vector3<f32>) -> SceneNode {
+        SceneNode { parent, translation, ..SceneNode::default() }
+    }
+
+    fn channel(path : ChannelPath, interpolation : SamplerInterpolation, times : Vec<f32>, values : Vec<Vector4<f32>>) -> Channel {
+        Channel { node : 0, path, interpolation, times, values }
+    }
+
+    fn close(a : Vector3<f32>, b : Vector3<f32>) -> bool {
+        (a.x - b.x).abs() < 0.0001 && (a.y - b.y).abs() < 0.0001 && (a.z - b.z).abs() < 0.0001
+    }
+
+    #[test]
+    fn linear_and_step_sampling() {
+        let values = vec![Vector4::new(0.0, 0.0, 0.0, 0.0), Vector4::new(2.0, 4.0, 0.0, 0.0)];
+        let linear = channel(ChannelPath::TRANSLATION, SamplerInterpolation::LINEAR, vec![0.0, 1.0], values.clone());
+        assert_eq!(linear.sample(0.5), Vector4::new(1.0, 2.0, 0.0, 0.0));
+        assert_eq!(linear.sample(-1.0), values[0]);
+        assert_eq!(linear.sample(5.0), values[1]);
+
+        let step = channel(ChannelPath::TRANSLATION, SamplerInterpolation::STEP, vec![0.0, 1.0], values.clone());
+        assert_eq!(step.sample(0.99), values[0]);
+    }
+
+    #[test]
+    fn rotations_are_slerped() {
+        let to_vec = |q : Quaternion<f32>| Vector4::new(q.v.x, q.v.y, q.v.z, q.s);
+        let start = Quaternion::from_angle_y(Deg(0.0));
+        let end = Quaternion::from_angle_y(Deg(90.0));
+        let rotation = channel(ChannelPath::ROTATION, SamplerInterpolation::LINEAR, vec![0.0, 2.0], vec![to_vec(start), to_vec(end)]);
+        let half = to_vec(Quaternion::from_angle_y(Deg(45.0)));
+        assert!((rotation.sample(1.0) - half).x.abs() < 0.0001);
+        assert!((rotation.sample(1.0) - half).y.abs() < 0.0001);
+        assert!((rotation.sample(1.0) - half).w.abs() < 0.0001);
+    }
+
+    #[test]
+    fn cubic_spline_with_flat_tangents_eases() {
+        let zero = Vector4::new(0.0, 0.0, 0.0, 0.0);
+        let values = vec![zero, zero, zero, zero, Vector4::new(1.0, 0.0, 0.0, 0.0), zero];
+        let cubic = channel(ChannelPath::SCALE, SamplerInterpolation::CUBICSPLINE, vec![0.0, 1.0], values);
+        assert!((cubic.sample(0.25).x - 0.15625).abs() < 0.0001);
+        assert_eq!(cubic.sample(1.0).x, 1.0);
+    }
+
+    #[test]
+    fn pose_follows_parents_in_any_order() {
+        let mut parent = node(None, Vector3::new(1.0, 0.0, 0.0));
+        parent.rotation = Quaternion::from_angle_z(Deg(90.0));
+        let nodes = vec![node(Some(1), Vector3::new(1.0, 0.0, 0.0)), parent];
+
+        let world = pose(&nodes, None, 0.0);
+        let child = world[0] * Vector4::new(0.0, 0.0, 0.0, 1.0);
+        assert!(close(child.truncate(), Vector3::new(1.0, 1.0, 0.0)));
+
+        let clip = AnimationClip {
+            name : String::from("move"),
+            channels : vec![Channel { node : 1, ..channel(ChannelPath::TRANSLATION, SamplerInterpolation::STEP, vec![0.0], vec![Vector4::new(0.0, 3.0, 0.0, 0.0)]) }],
+            duration : 0.0
+        };
+        let world = pose(&nodes, Some(&clip), 0.0);
+        assert!(close((world[0] * Vector4::new(0.0, 0.0, 0.0, 1.0)).truncate(), Vector3::new(0.0, 4.0, 0.0)));
+    }
+
+    #[test]
+    fn weights_blend_joints() {
+        let mesh = MeshData {
+            positions : vec![Vector3::new(0.0, 1.0, 0.0)],
+            uvs : vec![Vector2::new(0.0, 0.0)],
+            normals : vec![Vector3::new(0.0, 1.0, 0.0)],
+            indices : vec![0],
+            skin : vec![SkinWeights { joints : [0, 1, 0, 0], weights : [0.5, 0.5, 0.0, 0.0] }]
+        };
+        let joints = [Matrix4::from_translation(Vector3::new(2.0, 0.0, 0.0)), Matrix4::from_angle_z(Deg(90.0))];
+        let (positions, normals) = skin_mesh(&mesh, &joints);
+        assert!(close(positions[0], Vector3::new(0.5, 0.5, 0.0)));
+        assert!(close(normals[0], Vector3::new(-1.0, 1.0, 0.0).normalize()));
+    }
+
+    #[test]
+    fn clips_are_found_by_name_or_index() {
+        let clips = vec![
+            AnimationClip { name : String::from("walk"), channels : Vec::new(), duration : 1.0 },
+            AnimationClip { name : String::from("attack"), channels : Vec::new(), duration : 0.5 },
+        ];
+        assert_eq!(find_clip(&clips, "attack"), Some(1));
+        assert_eq!(find_clip(&clips, "0"), Some(0));
+        assert_eq!(find_clip(&clips, "run"), None);
+        assert_eq!(clips[0].frame_count(24), 24);
+        assert_eq!(clips[1].frame_count(24), 12);
+    }
+}
+
+use cgmath::{InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector3, Vector4, Zero};
+use crate::render::mesh::MeshData;
+
+///The joints that move a vertex and how much each of them pulls on it.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SkinWeights {
+    pub joints : [u16; 4],
+    pub weights : [f32; 4],
+}
+
+///A node of a glTF scene, with its rest transform and the node it hangs from.
+#[derive(Clone, Debug)]
+pub struct SceneNode {
+    pub parent : Option<usize>,
+    pub translation : Vector3<f32>,
+    pub rotation : Quaternion<f32>,
+    pub scale : Vector3<f32>,
+}
+
+impl Default for SceneNode {
+    fn default() -> Self {
+        SceneNode {
+            parent : None,
+            translation : Vector3::zero(),
+            rotation : Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale : Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+///The joints of a skin as node indices, along with the matrices that take the mesh from its bind
+///pose into the space of each joint.
+#[derive(Clone, Debug)]
+pub struct Skin {
+    pub joints : Vec<usize>,
+    pub inverse_binds : Vec<Matrix4<f32>>,
+}
+
+impl Skin {
+    ///The matrix of every joint, given the world matrices of the nodes from `pose`.
+    pub fn joint_matrices(&self, world : &[Matrix4<f32>]) -> Vec<Matrix4<f32>> {
+        self.joints.iter().enumerate()
+            .map(|(index, joint)| world[*joint] * self.inverse_binds.get(index).copied().unwrap_or_else(Matrix4::identity))
+            .collect()
+    }
+}
+
+///The part of a node transform a channel animates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChannelPath {
+    TRANSLATION,
+    ROTATION,
+    SCALE,
+}
+
+///How a channel gets from one key to the next, as glTF defines it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SamplerInterpolation {
+    STEP,
+    LINEAR,
+    CUBICSPLINE,
+}
+
+///The keys of one node property. Values are stored as xyzw, so rotations are quaternions and the w of
+///translations and scales is unused. Cubic spline channels have an in tangent, a value and an out
+///tangent for every key.
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub node : usize,
+    pub path : ChannelPath,
+    pub interpolation : SamplerInterpolation,
+    pub times : Vec<f32>,
+    pub values : Vec<Vector4<f32>>,
+}
+
+impl Channel {
+    ///The value of the channel at `time` seconds. The first and last keys are held outside of the keys.
+    pub fn sample(&self, time : f32) -> Vector4<f32> {
+        let last = match self.times.len() {
+            0 => return Vector4::zero(),
+            len => len - 1
+        };
+        if time <= self.times[0] { return self.value(0) }
+        if time >= self.times[last] { return self.value(last) }
+
+        let next = self.times.iter().position(|t| *t > time).unwrap_or(last);
+        let key = next - 1;
+        let duration = self.times[next] - self.times[key];
+        let t = if duration > 0.0 { (time - self.times[key]) / duration } else { 0.0 };
+
+        let value = match self.interpolation {
+            SamplerInterpolation::STEP => return self.value(key),
+            SamplerInterpolation::LINEAR if self.path == ChannelPath::ROTATION => {
+                let start = to_quaternion(self.value(key));
+                let mut end = to_quaternion(self.value(next));
+                // Going the short way around keeps a joint from spinning the long way between keys.
+                if start.dot(end) < 0.0 { end = -end }
+                let q = start.slerp(end, t);
+                Vector4::new(q.v.x, q.v.y, q.v.z, q.s)
+            }
+            SamplerInterpolation::LINEAR => self.value(key) + (self.value(next) - self.value(key)) * t,
+            SamplerInterpolation::CUBICSPLINE => {
+                let (t2, t3) = (t * t, t * t * t);
+                let out_tangent = self.values[key * 3 + 2] * duration;
+                let in_tangent = self.values[next * 3] * duration;
+                self.value(key) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * (t3 - 2.0 * t2 + t)
+                    + self.value(next) * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * (t3 - t2)
+            }
+        };
+
+        if self.path == ChannelPath::ROTATION { value.normalize() } else { value }
+    }
+
+    fn value(&self, key : usize) -> Vector4<f32> {
+        match self.interpolation {
+            SamplerInterpolation::CUBICSPLINE => self.values[key * 3 + 1],
+            _ => self.values[key]
+        }
+    }
+}
+
+///A named animation of a glTF file, like a walk cycle or an attack.
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name : String,
+    pub channels : Vec<Channel>,
+    pub duration : f32,
+}
+
+impl AnimationClip {
+    ///The amount of frames the clip is split into at `fps`. Always at least one.
+    pub fn frame_count(&self, fps : u32) -> u32 {
+        ((self.duration * fps as f32).round() as u32).max(1)
+    }
+}
+
+///Finds a clip by its name, or by its index if `name` is a number.
+pub fn find_clip(clips : &[AnimationClip], name : &str) -> Option<usize> {
+    clips.iter().position(|clip| clip.name == name)
+        .or_else(|| name.parse::<usize>().ok().filter(|index| *index < clips.len()))
+}
+
+///Puts the nodes in their rest pose, applies `clip` at `time` seconds on top of it, and returns the
+///world matrix of every node.
+pub fn pose(nodes : &[SceneNode], clip : Option<&AnimationClip>, time : f32) -> Vec<Matrix4<f32>> {
+    let mut locals = nodes.to_vec();
+    for channel in clip.iter().flat_map(|clip| &clip.channels) {
+        let Some(node) = locals.get_mut(channel.node) else { continue };
+        let value = channel.sample(time);
+        match channel.path {
+            ChannelPath::TRANSLATION => node.translation = value.truncate(),
+            ChannelPath::ROTATION => node.rotation = to_quaternion(value),
+            ChannelPath::SCALE => node.scale = value.truncate(),
+        }
+    }
+
+    let mut world : Vec<Option<Matrix4<f32>>> = vec![None; nodes.len()];
+    for index in 0..nodes.len() {
+        world_matrix(&locals, &mut world, index);
+    }
+    world.into_iter().map(|matrix| matrix.unwrap_or_else(Matrix4::identity)).collect()
+}
+
+fn world_matrix(nodes : &[SceneNode], world : &mut [Option<Matrix4<f32>>], index : usize) -> Matrix4<f32> {
+    if let Some(matrix) = world[index] { return matrix }
+
+    let node = &nodes[index];
+    let local = Matrix4::from_translation(node.translation)
+        * Matrix4::from(node.rotation)
+        * Matrix4::from_nonuniform_scale(node.scale.x, node.scale.y, node.scale.z);
+    let matrix = match node.parent {
+        Some(parent) => world_matrix(nodes, world, parent) * local,
+        None => local
+    };
+    world[index] = Some(matrix);
+    matrix
+}
+
+///Moves the vertices of a mesh in its bind pose by the matrices of its joints, and returns the new
+///positions and normals.
+pub fn skin_mesh(mesh : &MeshData, joints : &[Matrix4<f32>]) -> (Vec<Vector3<f32>>, Vec<Vector3<f32>>) {
+    let mut positions = Vec::with_capacity(mesh.positions.len());
+    let mut normals = Vec::with_capacity(mesh.normals.len());
+
+    for (index, (position, normal)) in mesh.positions.iter().zip(&mesh.normals).enumerate() {
+        let weights = mesh.skin.get(index).copied().unwrap_or_default();
+        let mut matrix = Matrix4::zero();
+        for (joint, weight) in weights.joints.iter().zip(weights.weights) {
+            if weight == 0.0 { continue }
+            if let Some(joint) = joints.get(*joint as usize) { matrix += joint * weight }
+        }
+        if matrix == Matrix4::zero() { matrix = Matrix4::identity() }
+
+        positions.push((matrix * position.extend(1.0)).truncate());
+        let normal = (matrix * normal.extend(0.0)).truncate();
+        normals.push(if normal.magnitude2() > 0.0 { normal.normalize() } else { normal });
+    }
+
+    (positions, normals)
+}
+
+fn to_quaternion(value : Vector4<f32>) -> Quaternion<f32> {
+    Quaternion::new(value.w, value.x, value.y, value.z)
+}
@@ -2,8 +2,9 @@ use glow::*;
 use image::io::Reader as ImageReader;
 use egui_glow::glow::UNSIGNED_BYTE;
 use std::borrow::Borrow;
-use image::{EncodableLayout, GenericImageView};
+use image::{EncodableLayout, GenericImageView, RgbaImage};
 use std::fs::File;
+use std::path::Path;
 use png::ColorType;
 
 pub struct Texture {
@@ -49,6 +50,29 @@ impl Texture {
         }
     }
 
+    ///Loads a texture from any path, like one referenced by a material.
+    pub fn from_path<P>(gl : &Context, path : P) -> Result<Self, String> where P : AsRef<Path> {
+        let image = image::open(path.as_ref())
+            .map_err(|e| format!("Unable to load texture '{}': {}", path.as_ref().display(), e))?;
+        Ok(Texture::from_rgba(gl, &image.into_rgba8()))
+    }
+
+    ///Creates a texture from an image that is already in memory, like one embedded in a glTF file.
+    ///The pixels are treated as sRGB. It is always sampled NEAREST, so it has no mipmaps.
+    pub fn from_rgba(gl : &Context, image : &RgbaImage) -> Self {
+        unsafe {
+            let texture = gl.create_texture().expect("Can not create texture.");
+            gl.bind_texture(TEXTURE_2D, Some(texture));
+
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, NEAREST as i32);
+            gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, NEAREST as i32);
+
+            gl.tex_image_2d(TEXTURE_2D, 0, SRGB8_ALPHA8 as i32, image.width() as i32, image.height() as i32, 0, RGBA, UNSIGNED_BYTE, Some(image.as_raw()));
+
+            Texture{texture}
+        }
+    }
+
     pub fn destroy(&self, gl : &Context) {
         unsafe { gl.delete_texture(self.texture); }
     }
@@ -1,10 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+    use crate::render::transform::*;
+
+    fn assert_mat_eq(a : Matrix4<f32>, b : Matrix4<f32>) {
+        let a : &[f32; 16] = a.as_ref();
+        let b : &[f32; 16] = b.as_ref();
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x - y).abs() < 0.0001, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn from_matrix_round_trip() {
+        let transform = Transform::new((1.0, -2.0, 3.0), (2.0, 0.5, 1.5), (30.0, 45.0, 300.0), (0.0, 0.0, 0.0));
+        let decomposed = Transform::from_matrix(transform.calc_mat());
+        assert_mat_eq(decomposed.calc_mat(), transform.calc_mat());
+    }
+
+    #[test]
+    fn from_matrix_gimbal_lock() {
+        let transform = Transform::new((0.0, 0.0, 0.0), (1.0, 1.0, 1.0), (20.0, 90.0, 10.0), (0.0, 0.0, 0.0));
+        let decomposed = Transform::from_matrix(transform.calc_mat());
+        assert_mat_eq(decomposed.calc_mat(), transform.calc_mat());
+    }
+
+    #[test]
+    fn from_matrix_negative_scale() {
+        let mat = Matrix4::from_nonuniform_scale(-1.0, 2.0, 1.0);
+        let decomposed = Transform::from_matrix(mat);
+        assert_mat_eq(decomposed.calc_mat(), mat);
+        assert_eq!(decomposed.scale, Vector3::new(-1.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = Transform::new((3.0, 0.0, 0.0), (1.0, 4.0, 1.0), (0.0, 0.0, 30.0), (0.0, 0.0, 0.0));
+        let tangent = transform.calc_mat() * Vector4::new(1.0, -1.0, 0.0, 0.0);
+        let normal = transform.normal_matrix() * Vector3::new(1.0, 1.0, 0.0);
+        assert!(normal.dot(tangent.truncate()).abs() < 0.0001, "{:?} . {:?}", normal, tangent);
+    }
+
+    #[test]
+    fn from_matrix_keeps_the_normal_matrix() {
+        let mat = Matrix4::from_angle_y(Deg(40.0)) * Matrix4::from_nonuniform_scale(0.5, 3.0, 2.0);
+        let node = Transform::from_matrix(mat);
+        let tangent = mat * Vector4::new(0.0, 1.0, -1.0, 0.0);
+        let normal = node.normal_matrix() * Vector3::new(0.0, 1.0, 1.0);
+        assert!(normal.dot(tangent.truncate()).abs() < 0.0001, "{:?} . {:?}", normal, tangent);
+    }
+}
+
 use std::borrow::BorrowMut;
-use cgmath::{Matrix4, Quaternion, Vector3, SquareMatrix, Zero, Deg, Angle, Rad, BaseFloat, Transform as TransformMatrix};
+use cgmath::{Matrix, Matrix3, Matrix4, InnerSpace, Quaternion, Vector3, SquareMatrix, Zero, Deg, Angle, Rad, BaseFloat, Transform as TransformMatrix};
 use egui::emath::Numeric;
 use crate::render::debug::{Debugable, UIRenderType};
 use egui::{Grid, Ui};
 use cgmath::Rotation3;
-use crate::render::shader::{ShaderUniformHandler, Uniform, UniformValue};
+use crate::render::shader::{ShaderUniformHandler, Uniform};
 use crate::util::math::{clamp_vec3, clamp_vec3_xz, wrap_vec3};
 use crate::util::variable::UpdateVariable;
 
@@ -40,6 +93,34 @@ impl Transform {
         }
     }
 
+    ///Splits a matrix into position, rotation and scale. Shear can't be represented by a transform,
+    ///so a matrix with shear only comes out approximately the same.
+    pub fn from_matrix(mat : Matrix4<f32>) -> Self {
+        let pos = mat.w.truncate();
+        let mut scale = Vector3::new(mat.x.truncate().magnitude(), mat.y.truncate().magnitude(), mat.z.truncate().magnitude());
+        if Matrix3::from_cols(mat.x.truncate(), mat.y.truncate(), mat.z.truncate()).determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        let safe = |v : f32| if v == 0.0 { 1.0 } else { v };
+        let x = mat.x.truncate() / safe(scale.x);
+        let y = mat.y.truncate() / safe(scale.y);
+        let z = mat.z.truncate() / safe(scale.z);
+
+        // The rotation is applied as Rx * Ry * Rz, so sin(y) sits in the top right corner.
+        let rot_y = z.x.clamp(-1.0, 1.0).asin();
+        let (rot_x, rot_z) = if z.x.abs() < 0.9999 {
+            ((-z.y).atan2(z.z), (-y.x).atan2(x.x))
+        } else {
+            (y.z.atan2(y.y), 0.0)
+        };
+
+        let mut rotation = Vector3::new(rot_x.to_degrees(), rot_y.to_degrees(), rot_z.to_degrees());
+        wrap_vec3(rotation.borrow_mut(), 0.0, 360.0);
+
+        Transform::new(pos, scale, rotation, Vector3::new(0.0, 0.0, 0.0))
+    }
+
     pub fn calc_mat(&self) -> Matrix4<f32> {
         let origin_mat = Matrix4::from_translation(self.origin);
         let transform_mat = Matrix4::from_translation(self.pos);
@@ -51,6 +132,14 @@ impl Transform {
         mat
     }
 
+    ///The matrix normals are transformed with, the inverse transpose of `calc_mat` without the translation.
+    ///Unlike the transform itself, it keeps normals perpendicular to their surface under non-uniform scale.
+    pub fn normal_matrix(&self) -> Matrix3<f32> {
+        let mat = self.calc_mat();
+        let linear = Matrix3::from_cols(mat.x.truncate(), mat.y.truncate(), mat.z.truncate());
+        linear.invert().map(|inverse| inverse.transpose()).unwrap_or(linear)
+    }
+
     pub fn calc_cam_mat(&self) -> Matrix4<f32> {
         let origin_mat = Matrix4::from_translation(-self.origin);
         let transform_mat = Matrix4::from_translation(-self.pos);
@@ -62,6 +151,18 @@ impl Transform {
         mat
     }
 
+    pub fn position(&self) -> Vector3<f32> {
+        self.pos
+    }
+
+    pub fn rotation(&self) -> Vector3<f32> {
+        self.rotation
+    }
+
+    pub fn scale(&self) -> Vector3<f32> {
+        self.scale
+    }
+
     pub fn set_pos<T>(&mut self, value : T) -> &mut Self where T: Into<Vector3<f32>> {
         self.pos = value.into();
         self.update_uniform();
@@ -75,6 +176,12 @@ impl Transform {
         self
     }
 
+    pub fn set_origin<T>(&mut self, value : T) -> &mut Self where T: Into<Vector3<f32>> {
+        self.origin = value.into();
+        self.update_uniform();
+        self
+    }
+
     pub fn set_scale<T>(&mut self, value : T) -> &mut Self where T: Into<Vector3<f32>> {
         self.scale = value.into();
         self.update_uniform();
@@ -165,8 +272,3 @@ impl Debugable for Transform {
         changed
     }
 }
-
-///This a transform meant to control a camera. It wraps transform and inverts all incoming changes.
-pub struct CameraTransform {
-
-}
\ No newline at end of file
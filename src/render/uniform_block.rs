@@ -0,0 +1,295 @@
+//Uniform blocks that live in one buffer shared by every shader. The data is packed with the std140 layout,
+//so it can be written once without asking each program where its members are.
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix3, Matrix4, SquareMatrix, Vector2, Vector3, Vector4};
+    use crate::render::uniform_block::*;
+
+    struct Inner {
+        a : f32,
+        b : Vector2<f32>,
+    }
+
+    impl Std140 for Inner {
+        fn write_std140(&self, writer : &mut Std140Writer) {
+            writer.push(&self.a).push(&self.b);
+        }
+    }
+
+    struct Outer {
+        color : Vector3<f32>,
+        ambient : f32,
+        inner : Inner,
+        flag : bool,
+        values : [f32; 2],
+        matrix : Matrix3<f32>,
+    }
+
+    impl Std140 for Outer {
+        fn write_std140(&self, writer : &mut Std140Writer) {
+            writer.push(&self.color).push(&self.ambient).push(&self.inner).push(&self.flag).push(&self.values).push(&self.matrix);
+        }
+    }
+
+    struct Light {
+        color : Vector3<f32>,
+        ambient : f32,
+        direction : Vector3<f32>,
+    }
+
+    impl Std140 for Light {
+        fn write_std140(&self, writer : &mut Std140Writer) {
+            writer.push(&self.color).push(&self.ambient).push(&self.direction);
+        }
+    }
+
+    struct Camera {
+        near : f32,
+        view : Matrix4<f32>,
+        tint : Vector4<f32>,
+    }
+
+    impl Std140 for Camera {
+        fn write_std140(&self, writer : &mut Std140Writer) {
+            writer.push(&self.near).push(&self.view).push(&self.tint);
+        }
+    }
+
+    fn floats(bytes : &[u8]) -> Vec<f32> {
+        bytes.chunks(4).map(|chunk| f32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect()
+    }
+
+    #[test]
+    fn scalars_pack_after_a_vec3() {
+        let bytes = std140_bytes(&Light { color : Vector3::new(1.0, 2.0, 3.0), ambient : 4.0, direction : Vector3::new(5.0, 6.0, 7.0) });
+        assert_eq!(floats(&bytes), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 0.0]);
+    }
+
+    #[test]
+    fn structs_arrays_and_matrices_are_aligned_to_a_vec4() {
+        let outer = Outer {
+            color : Vector3::new(1.0, 1.0, 1.0),
+            ambient : 0.5,
+            inner : Inner { a : 2.0, b : Vector2::new(3.0, 4.0) },
+            flag : true,
+            values : [5.0, 6.0],
+            matrix : Matrix3::identity(),
+        };
+        let bytes = std140_bytes(&outer);
+        // vec3 + float, struct { float, vec2 } rounded up to 16, bool, float[2] with a 16 byte stride, mat3 as three vec4s.
+        assert_eq!(bytes.len(), 16 + 16 + 16 + 32 + 48);
+        let floats = floats(&bytes);
+        assert_eq!(floats[4], 2.0);
+        assert_eq!(floats[6..8], [3.0, 4.0]);
+        assert_eq!(u32::from_ne_bytes([bytes[32], bytes[33], bytes[34], bytes[35]]), 1);
+        assert_eq!(floats[12], 5.0);
+        assert_eq!(floats[16], 6.0);
+        assert_eq!(floats[20..23], [1.0, 0.0, 0.0]);
+        assert_eq!(floats[24..27], [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn mat4_is_column_major() {
+        let matrix = Matrix4::from_translation(Vector3::new(7.0, 8.0, 9.0));
+        let bytes = std140_bytes(&Camera { near : 1.0, view : matrix, tint : Vector4::new(1.0, 2.0, 3.0, 4.0) });
+        let floats = floats(&bytes);
+        assert_eq!(floats.len(), 4 + 16 + 4);
+        assert_eq!(floats[16..20], [7.0, 8.0, 9.0, 1.0]);
+        assert_eq!(floats[20..24], [1.0, 2.0, 3.0, 4.0]);
+    }
+}
+
+use std::marker::PhantomData;
+use std::rc::Rc;
+use cgmath::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use glow::{Context, HasContext, NativeBuffer, DYNAMIC_DRAW, UNIFORM_BUFFER};
+use crate::render::{Deletable, RenderContext};
+
+///A type that can be written into a uniform block with the std140 layout. There is no derive for it,
+///so a struct implements it by hand, pushing its fields in the order the block declares them.
+pub trait Std140 {
+    ///The base alignment in bytes. Structs, arrays and matrices are aligned like a vec4.
+    const ALIGN : usize = 16;
+    ///Whether the size is rounded up to a multiple of 16 bytes, as structs, arrays and matrices are.
+    const PADDED : bool = true;
+
+    fn write_std140(&self, writer : &mut Std140Writer);
+}
+
+///Packs values one after the other with the alignment rules of std140.
+#[derive(Default)]
+pub struct Std140Writer {
+    bytes : Vec<u8>,
+}
+
+impl Std140Writer {
+    ///Writes the next member of the block.
+    pub fn push<T>(&mut self, value : &T) -> &mut Self where T : Std140 {
+        self.align(T::ALIGN);
+        value.write_std140(self);
+        if T::PADDED { self.align(16) }
+        self
+    }
+
+    fn align(&mut self, alignment : usize) {
+        let padding = (alignment - self.bytes.len() % alignment) % alignment;
+        self.bytes.resize(self.bytes.len() + padding, 0);
+    }
+
+    fn write_bytes(&mut self, bytes : &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+///The bytes of a value laid out with std140, padded to a multiple of 16 bytes.
+pub fn std140_bytes<T>(value : &T) -> Vec<u8> where T : Std140 {
+    let mut writer = Std140Writer::default();
+    value.write_std140(&mut writer);
+    writer.align(16);
+    writer.bytes
+}
+
+impl Std140 for f32 {
+    const ALIGN : usize = 4;
+    const PADDED : bool = false;
+
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.write_bytes(&self.to_ne_bytes());
+    }
+}
+
+impl Std140 for i32 {
+    const ALIGN : usize = 4;
+    const PADDED : bool = false;
+
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.write_bytes(&self.to_ne_bytes());
+    }
+}
+
+impl Std140 for u32 {
+    const ALIGN : usize = 4;
+    const PADDED : bool = false;
+
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.write_bytes(&self.to_ne_bytes());
+    }
+}
+
+impl Std140 for bool {
+    const ALIGN : usize = 4;
+    const PADDED : bool = false;
+
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        (*self as u32).write_std140(writer);
+    }
+}
+
+impl<T> Std140 for Vector2<T> where T : Std140 {
+    const ALIGN : usize = 2 * T::ALIGN;
+    const PADDED : bool = false;
+
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.push(&self.x).push(&self.y);
+    }
+}
+
+impl<T> Std140 for Vector3<T> where T : Std140 {
+    const ALIGN : usize = 4 * T::ALIGN;
+    const PADDED : bool = false;
+
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.push(&self.x).push(&self.y).push(&self.z);
+    }
+}
+
+impl<T> Std140 for Vector4<T> where T : Std140 {
+    const ALIGN : usize = 4 * T::ALIGN;
+    const PADDED : bool = false;
+
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.push(&self.x).push(&self.y).push(&self.z).push(&self.w);
+    }
+}
+
+//Matrices are stored as arrays of their columns, so every column starts on 16 bytes.
+impl Std140 for Matrix2<f32> {
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.push(&[self.x, self.y]);
+    }
+}
+
+impl Std140 for Matrix3<f32> {
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.push(&[self.x, self.y, self.z]);
+    }
+}
+
+impl Std140 for Matrix4<f32> {
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        writer.push(&[self.x, self.y, self.z, self.w]);
+    }
+}
+
+impl<T, const N : usize> Std140 for [T; N] where T : Std140 {
+    fn write_std140(&self, writer : &mut Std140Writer) {
+        for element in self {
+            writer.align(16);
+            writer.push(element);
+            writer.align(16);
+        }
+    }
+}
+
+///A uniform block that every shader of the render context shares. The block gets its own binding point
+///the first time a block with its name is created, and every program that declares a block with that
+///name reads from it, including ones that are built or rebuilt later. Updating it is one buffer upload,
+///no matter how many shaders use it.
+pub struct UniformBlock<T> {
+    buffer : NativeBuffer,
+    render_context : Rc<RenderContext>,
+    block : PhantomData<T>,
+}
+
+impl<T> UniformBlock<T> where T : Std140 {
+    ///Creates the buffer for the block named `name` in the shaders, like `Camera` for
+    ///`layout(std140) uniform Camera { ... };`, and fills it with `value`.
+    pub fn new(render_context : &Rc<RenderContext>, name : &str, value : &T) -> Result<Self, String> {
+        let gl = &render_context.gl;
+        let binding = render_context.uniform_block_binding(name);
+        let buffer = unsafe {
+            let buffer = gl.create_buffer()?;
+            gl.bind_buffer(UNIFORM_BUFFER, Some(buffer));
+            gl.buffer_data_u8_slice(UNIFORM_BUFFER, &std140_bytes(value), DYNAMIC_DRAW);
+            gl.bind_buffer(UNIFORM_BUFFER, None);
+            gl.bind_buffer_base(UNIFORM_BUFFER, binding, Some(buffer));
+            buffer
+        };
+        for shader in render_context.shaders() {
+            shader.bind_uniform_block(gl, name, binding);
+        }
+
+        Ok(UniformBlock {
+            buffer,
+            render_context : Rc::clone(render_context),
+            block : PhantomData
+        })
+    }
+
+    ///Uploads the new value for every shader at once.
+    pub fn update(&self, value : &T) {
+        let gl = &self.render_context.gl;
+        unsafe {
+            gl.bind_buffer(UNIFORM_BUFFER, Some(self.buffer));
+            gl.buffer_sub_data_u8_slice(UNIFORM_BUFFER, 0, &std140_bytes(value));
+            gl.bind_buffer(UNIFORM_BUFFER, None);
+        }
+    }
+}
+
+impl<T> Deletable for UniformBlock<T> {
+    unsafe fn delete(&self, gl : &Context) {
+        gl.delete_buffer(self.buffer);
+    }
+}
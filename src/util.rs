@@ -3,4 +3,5 @@ use std::any::TypeId;
 pub mod bitflag;
 pub mod math;
 pub mod variable;
-pub mod input;
\ No newline at end of file
+pub mod input;
+pub mod color;
\ No newline at end of file
@@ -0,0 +1,54 @@
+//Color space conversions used by the palette and dithering code. Colors are stored as sRGB bytes,
+//but all of the distance math happens in OKLab so that it matches what the eye sees.
+
+pub fn srgb_to_linear(value : f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value : f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+///Converts a linear RGB color to OKLab (https://bottosson.github.io/posts/oklab/).
+pub fn linear_to_oklab(rgb : [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s
+    ]
+}
+
+pub fn srgb_bytes_to_oklab(color : [u8; 3]) -> [f32; 3] {
+    linear_to_oklab(srgb_bytes_to_linear(color))
+}
+
+pub fn srgb_bytes_to_linear(color : [u8; 3]) -> [f32; 3] {
+    [
+        srgb_to_linear(color[0] as f32 / 255.0),
+        srgb_to_linear(color[1] as f32 / 255.0),
+        srgb_to_linear(color[2] as f32 / 255.0)
+    ]
+}
+
+pub fn linear_to_srgb_bytes(color : [f32; 3]) -> [u8; 3] {
+    let to_byte = |value : f32| (linear_to_srgb(value.clamp(0.0, 1.0)) * 255.0).round() as u8;
+    [to_byte(color[0]), to_byte(color[1]), to_byte(color[2])]
+}
+
+pub fn distance_squared(a : [f32; 3], b : [f32; 3]) -> f32 {
+    let d = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    d[0] * d[0] + d[1] * d[1] + d[2] * d[2]
+}
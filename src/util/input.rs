@@ -1,7 +1,9 @@
 use egui::{Pos2, Rect};
 use glutin::dpi::PhysicalPosition;
 use glutin::event::{DeviceId, ElementState, ModifiersState, MouseButton, MouseScrollDelta, TouchPhase, WindowEvent};
-use glutin::event::MouseScrollDelta::LineDelta;
+use glutin::event::MouseScrollDelta::{LineDelta, PixelDelta};
+
+const PIXELS_PER_LINE : f32 = 20.0;
 
 pub struct InputState {
     pub mouse : MouseState
@@ -146,9 +148,16 @@ impl MouseState {
     }
 
     pub fn handle_mouse_scroll(&mut self, delta: &MouseScrollDelta, phase: &TouchPhase) {
-        if let LineDelta(x, y) = delta {
-            self.scroll_dx = *x;
-            self.scroll_dy = *y;
+        match delta {
+            LineDelta(x, y) => {
+                self.scroll_dx = *x;
+                self.scroll_dy = *y;
+            }
+            // Touchpads scroll in pixels, so turn them into roughly the same number of lines.
+            PixelDelta(position) => {
+                self.scroll_dx = position.x as f32 / PIXELS_PER_LINE;
+                self.scroll_dy = position.y as f32 / PIXELS_PER_LINE;
+            }
         }
     }
 